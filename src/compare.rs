@@ -0,0 +1,157 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use svd_expander::DeviceSpec;
+
+#[derive(PartialEq)]
+struct FieldLayout {
+  address: u32,
+  mask: u32,
+  offset: u32,
+}
+
+struct DeviceLayout {
+  peripherals: BTreeSet<String>,
+  registers: BTreeSet<String>,
+  fields: BTreeMap<String, FieldLayout>,
+}
+impl DeviceLayout {
+  fn new(device: &DeviceSpec) -> Self {
+    let mut layout = Self {
+      peripherals: BTreeSet::new(),
+      registers: BTreeSet::new(),
+      fields: BTreeMap::new(),
+    };
+
+    for peripheral in device.peripherals.iter() {
+      layout.peripherals.insert(peripheral.name.to_lowercase());
+
+      for field in peripheral.iter_fields() {
+        let path = field.path().to_lowercase();
+        layout.registers.insert(parent_path(&path).to_owned());
+        layout.fields.insert(
+          path,
+          FieldLayout {
+            address: field.address(),
+            mask: field.mask(),
+            offset: field.offset,
+          },
+        );
+      }
+    }
+
+    layout
+  }
+}
+
+// Lists what changed between two versions of a device's SVD. Registers and
+// fields of added or removed peripherals aren't listed individually, and
+// neither are fields of added or removed registers.
+pub fn differences(old: &DeviceSpec, new: &DeviceSpec) -> Vec<String> {
+  let old = DeviceLayout::new(old);
+  let new = DeviceLayout::new(new);
+
+  let mut diffs = Vec::new();
+
+  for peripheral in old.peripherals.difference(&new.peripherals) {
+    diffs.push(f!("- peripheral {peripheral}"));
+  }
+  for peripheral in new.peripherals.difference(&old.peripherals) {
+    diffs.push(f!("+ peripheral {peripheral}"));
+  }
+
+  for register in old.registers.difference(&new.registers) {
+    if new.peripherals.contains(peripheral_name(register)) {
+      diffs.push(f!("- register {register}"));
+    }
+  }
+  for register in new.registers.difference(&old.registers) {
+    if old.peripherals.contains(peripheral_name(register)) {
+      diffs.push(f!("+ register {register}"));
+    }
+  }
+
+  for (path, old_field) in old.fields.iter() {
+    match new.fields.get(path) {
+      Some(new_field) if new_field != old_field => diffs.push(format!(
+        "~ field {}: address {:#010x} -> {:#010x}, mask {:#034b} -> {:#034b}, offset {} -> {}",
+        path,
+        old_field.address,
+        new_field.address,
+        old_field.mask,
+        new_field.mask,
+        old_field.offset,
+        new_field.offset
+      )),
+      Some(_) => {}
+      None => {
+        if new.registers.contains(parent_path(path)) {
+          diffs.push(f!("- field {path}"));
+        }
+      }
+    }
+  }
+  for path in new.fields.keys() {
+    if !old.fields.contains_key(path) && old.registers.contains(parent_path(path)) {
+      diffs.push(f!("+ field {path}"));
+    }
+  }
+
+  diffs
+}
+
+fn peripheral_name(path: &str) -> &str {
+  match path.find('.') {
+    Some(i) => &path[..i],
+    None => path,
+  }
+}
+
+fn parent_path(path: &str) -> &str {
+  match path.rfind('.') {
+    Some(i) => &path[..i],
+    None => path,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn edit_timer0_cr(device: &mut DeviceSpec, edit: impl Fn(&mut Vec<svd_expander::FieldSpec>)) {
+    let cr = device
+      .peripherals
+      .iter_mut()
+      .find(|p| p.name == "TIMER0")
+      .unwrap()
+      .registers
+      .iter_mut()
+      .find(|r| r.name == "CR")
+      .unwrap();
+    edit(&mut cr.fields);
+  }
+
+  #[test]
+  fn lists_added_removed_and_changed_fields() {
+    let mut old = DeviceSpec::from_file("specs/svd/arm_device.svd").unwrap();
+    edit_timer0_cr(&mut old, |fields| fields.retain(|f| f.name != "RST"));
+
+    let mut new = DeviceSpec::from_file("specs/svd/arm_device.svd").unwrap();
+    edit_timer0_cr(&mut new, |fields| {
+      fields.retain(|f| f.name != "CNT");
+      for field in fields.iter_mut().filter(|f| f.name == "MODE") {
+        field.offset = 5;
+      }
+    });
+
+    assert_eq!(
+      vec![
+        "- field timer0.cr.cnt",
+        "~ field timer0.cr.mode: address 0x40010000 -> 0x40010000, \
+         mask 0b00000000000000000000000001110000 -> 0b00000000000000000000000011100000, \
+         offset 4 -> 5",
+        "+ field timer0.cr.rst",
+      ],
+      differences(&old, &new)
+    );
+  }
+}
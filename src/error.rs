@@ -0,0 +1,67 @@
+use thiserror::Error;
+
+// The kinds of failure callers may want to handle differently, like skipping a
+// device with a missing field but stopping on an I/O error. Converts into
+// `anyhow::Error` like any other error, so `?` still works in the CLI.
+#[derive(Debug, Error)]
+pub enum GeneratorError {
+  #[error("Could not parse SVD: {message}")]
+  SvdParse { message: String },
+  #[error("Could not parse clock spec: {message}")]
+  ClockSpecParse { message: String },
+  #[error("Field '{path}' not found in device {device}")]
+  FieldNotFound { device: String, path: String },
+  #[error("{message}")]
+  Validation { message: String },
+  #[error(transparent)]
+  Io(#[from] std::io::Error),
+  #[error("{message}")]
+  PostProcess { message: String },
+}
+impl GeneratorError {
+  // Most of the generator still passes errors around as `anyhow::Error`. This
+  // recovers the kind they were raised as, and anything raised without one is
+  // a validation failure.
+  pub fn from_anyhow(err: anyhow::Error) -> Self {
+    match err.downcast::<GeneratorError>() {
+      Ok(err) => err,
+      Err(err) => match err.downcast::<std::io::Error>() {
+        Ok(err) => GeneratorError::Io(err),
+        Err(err) => GeneratorError::Validation {
+          message: format!("{:#}", err),
+        },
+      },
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use anyhow::anyhow;
+
+  use super::*;
+
+  #[test]
+  fn recovers_kind_from_anyhow() {
+    let field_err: anyhow::Error = GeneratorError::FieldNotFound {
+      device: "STM32F303".to_owned(),
+      path: "tim2.cr1.bogus".to_owned(),
+    }
+    .into();
+    assert!(matches!(
+      GeneratorError::from_anyhow(field_err),
+      GeneratorError::FieldNotFound { ref path, .. } if path == "tim2.cr1.bogus"
+    ));
+
+    let io_err: anyhow::Error = std::io::Error::new(std::io::ErrorKind::NotFound, "gone").into();
+    assert!(matches!(
+      GeneratorError::from_anyhow(io_err),
+      GeneratorError::Io(_)
+    ));
+
+    assert!(matches!(
+      GeneratorError::from_anyhow(anyhow!("Bad clock tree")),
+      GeneratorError::Validation { ref message } if message == "Bad clock tree"
+    ));
+  }
+}
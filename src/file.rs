@@ -1,32 +1,94 @@
 use std::fs;
 use std::fs::create_dir_all;
-use std::{io, ops::Deref, path::PathBuf, process::Command};
+use std::{
+  io,
+  ops::Deref,
+  path::{Path, PathBuf},
+  process::Command,
+};
 
-use anyhow::{anyhow, bail, Result};
+use anyhow::{bail, Result};
 use io::Write;
 
+use crate::{error::GeneratorError, tidy::tidy_rust};
+
+// Hand-written code kept next to the generated modules. The generator never
+// writes anything under it, so it survives regenerating the crate.
+pub const USER_DIR: &str = "user";
+
 #[derive(Clone)]
 pub struct OutputDirectory {
   dir_path: String,
+  tidy_rust: bool,
+  sink: bool,
 }
 impl OutputDirectory {
   pub fn new(dir_path: &str) -> Result<Self> {
     create_dir_all(dir_path.clone())?;
     Ok(Self {
       dir_path: dir_path.to_owned(),
+      tidy_rust: false,
+      sink: false,
     })
   }
 
+  // A directory that isn't on disk, for rendering everything without writing
+  // it anywhere. Its subdirectories and parent are sinks too, and it reads as
+  // empty.
+  pub fn sink() -> Self {
+    Self {
+      dir_path: String::new(),
+      tidy_rust: false,
+      sink: true,
+    }
+  }
+
+  // Re-indents Rust files as they're published, for when `cargo fmt` won't run
+  // on the output. Carries over to subdirectories.
+  pub fn with_tidy_rust(mut self, tidy_rust: bool) -> Self {
+    self.tidy_rust = tidy_rust;
+    self
+  }
+
   pub fn new_in_subdir(&self, subdir: &str) -> Result<Self> {
+    if self.sink {
+      return Ok(self.clone());
+    }
+
     let mut path_buf = PathBuf::from(&self.dir_path);
     path_buf.push(subdir);
-    Self::new(match path_buf.into_os_string().into_string() {
-      Ok(ref s) => s,
-      Err(_) => bail!("Could not convert path to string"),
-    })
+    Ok(
+      Self::new(match path_buf.into_os_string().into_string() {
+        Ok(ref s) => s,
+        Err(_) => bail!("Could not convert path to string"),
+      })?
+      .with_tidy_rust(self.tidy_rust),
+    )
+  }
+
+  pub fn parent(&self) -> Result<Self> {
+    if self.sink {
+      return Ok(self.clone());
+    }
+
+    let path_buf = PathBuf::from(&self.dir_path).canonicalize()?;
+    Ok(
+      Self::new(match path_buf.parent() {
+        Some(parent) => match parent.to_str() {
+          Some(s) => s,
+          None => bail!("Could not convert path to string"),
+        },
+        None => bail!("Directory {} has no parent directory", self.dir_path),
+      })?
+      .with_tidy_rust(self.tidy_rust),
+    )
   }
 
   pub fn get_path(&self) -> Result<String> {
+    if self.sink {
+      bail!("The output isn't written to a directory");
+    }
+
     Ok(
       PathBuf::from(&self.dir_path)
         .canonicalize()?
@@ -36,8 +98,27 @@ impl OutputDirectory {
     )
   }
 
+  pub fn contains(&self, rel_file_path: &str) -> bool {
+    !self.sink && PathBuf::from(&self.dir_path).join(rel_file_path).is_file()
+  }
+
+  pub fn read(&self, rel_file_path: &str) -> Option<String> {
+    if self.sink {
+      return None;
+    }
+    fs::read_to_string(PathBuf::from(&self.dir_path).join(rel_file_path)).ok()
+  }
+
   pub fn publish(&self, dry_run: bool, rel_file_path: &str, file_content: &str) -> Result<()> {
-    if dry_run {
+    if Path::new(rel_file_path).starts_with(USER_DIR) {
+      bail!(
+        "Refusing to overwrite {}, files under {}/ are hand-written",
+        rel_file_path,
+        USER_DIR
+      );
+    }
+
+    if dry_run || self.sink {
       return Ok(());
     }
 
@@ -58,12 +139,20 @@ impl OutputDirectory {
       }
     })?;
 
-    fs::write(file_path_buf, file_content)?;
+    match self.tidy_rust && rel_file_path.ends_with(".rs") {
+      true => fs::write(file_path_buf, tidy_rust(file_content))?,
+      false => fs::write(file_path_buf, file_content)?,
+    }
     Ok(())
   }
 }
 
-pub fn run_command(dry_run: bool, path: &str, command: &str, args: Vec<&str>) -> Result<()> {
+pub fn run_command(
+  dry_run: bool,
+  path: &str,
+  command: &str,
+  args: Vec<&str>,
+) -> std::result::Result<(), GeneratorError> {
   if dry_run {
     return Ok(());
   }
@@ -84,26 +173,35 @@ pub fn run_command(dry_run: bool, path: &str, command: &str, args: Vec<&str>) ->
   }
 
   if !output.status.success() {
-    return Err(match output.status.code() {
-      Some(code) => anyhow!("Command failed with exit code {}.", code),
-      None => anyhow!("Command failed."),
+    return Err(GeneratorError::PostProcess {
+      message: match output.status.code() {
+        Some(code) => format!("Command failed with exit code {}.", code),
+        None => "Command failed.".to_owned(),
+      },
     });
   }
 
   Ok(())
 }
 
+// When the output is a module in a host crate, `module_root` is its `mod.rs`.
+// Only the module is formatted then, and `cargo fix` is skipped, so that the
+// host crate's own files are left as they are.
 pub fn post_process(
   dry_run: bool,
   path: &str,
+  module_root: Option<&str>,
   run_fix: bool,
   run_format: bool,
   run_check: bool,
   build_release: bool,
   build_debug: bool,
   build_docs: bool,
-) -> Result<()> {
-  if run_fix {
+  post_commands: &[String],
+) -> std::result::Result<(), GeneratorError> {
+  if run_fix && module_root.is_some() {
+    info!("Skipping cargo fix, it would change the host crate's files too.");
+  } else if run_fix {
     info!("Fixing...");
     run_command(
       dry_run,
@@ -121,7 +219,15 @@ pub fn post_process(
 
   if run_format {
     info!("Formatting...");
-    run_command(dry_run, path, "cargo", vec!["+nightly", "fmt"])?;
+    match module_root {
+      Some(root) => run_command(
+        dry_run,
+        path,
+        "rustfmt",
+        vec!["+nightly", "--edition", "2018", root],
+      )?,
+      None => run_command(dry_run, path, "cargo", vec!["+nightly", "fmt"])?,
+    }
   }
 
   if run_check {
@@ -149,5 +255,31 @@ pub fn post_process(
     run_command(dry_run, path, "cargo", vec!["doc", "--all-features"])?;
   }
 
+  for post_command in post_commands.iter() {
+    if dry_run {
+      info!("Would run `{}` in {}", post_command, path);
+    }
+    run_command(dry_run, path, "sh", vec!["-c", post_command])?;
+  }
+
   Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+  use std::env;
+
+  use super::*;
+
+  #[test]
+  fn never_publishes_user_files() {
+    let dir_path = env::temp_dir().join(format!("stm32-api-generator-user-{}", std::process::id()));
+    let out_dir = OutputDirectory::new(&dir_path.to_string_lossy()).unwrap();
+
+    assert!(out_dir.publish(true, "user/mod.rs", "").is_err());
+    assert!(out_dir.publish(true, "user/spi1_ext.rs", "").is_err());
+    assert!(out_dir.publish(true, "users.rs", "").is_ok());
+
+    fs::remove_dir_all(&dir_path).ok();
+  }
+}
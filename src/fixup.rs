@@ -0,0 +1,315 @@
+use anyhow::Result;
+use regex::Regex;
+use svd_expander::{DeviceSpec, FieldSpec};
+
+// A systematic mistake in a vendor's SVDs, fixed in code on every device it
+// applies to before anything is generated from it. One-off fixes to a single
+// device belong in a `DevicePatch` file instead.
+pub trait SvdFixup {
+  fn name(&self) -> &str;
+
+  // Usually decided by `device.name`, like `name.starts_with("STM32F1")`.
+  fn applies_to(&self, device: &DeviceSpec) -> bool;
+
+  fn apply(&self, device: DeviceSpec) -> Result<DeviceSpec>;
+}
+
+// The fixups run on each parsed device, in the order they were registered.
+pub struct Fixups {
+  fixups: Vec<Box<dyn SvdFixup>>,
+}
+impl Fixups {
+  pub fn empty() -> Self {
+    Self { fixups: Vec::new() }
+  }
+
+  pub fn builtin() -> Self {
+    let mut fixups = Self::empty();
+    fixups.register(InheritEnumeratedValues);
+    fixups.register(InheritGenericAltFuncs);
+    fixups
+  }
+
+  pub fn register<F: SvdFixup + 'static>(&mut self, fixup: F) -> &mut Self {
+    self.fixups.push(Box::new(fixup));
+    self
+  }
+
+  pub fn apply(&self, mut device: DeviceSpec) -> Result<DeviceSpec> {
+    for fixup in self.fixups.iter() {
+      if fixup.applies_to(&device) {
+        info!("Applying fixup '{}' to {}", fixup.name(), device.name);
+        device = fixup.apply(device)?;
+      }
+    }
+    Ok(device)
+  }
+}
+
+// ST's SVDs define the enumerated values of a set of matching fields once and
+// have the rest use `derivedFrom`, which isn't always inherited, or leave them
+// out of the rest entirely. Fields with no values get them from the first
+// field of the same set in the peripheral that has them.
+pub struct InheritEnumeratedValues;
+impl InheritEnumeratedValues {
+  // Timer compare modes (`OC2M`) and capture filters (`IC2F`). GPIO alternate
+  // functions (`AFRL2`) aren't in here: their values differ from pin to pin.
+  const FIELD_SETS: [&str; 2] = [r"^oc[0-9]+m$", r"^ic[0-9]+f$"];
+}
+impl SvdFixup for InheritEnumeratedValues {
+  fn name(&self) -> &str {
+    "inherit enumerated values"
+  }
+
+  fn applies_to(&self, device: &DeviceSpec) -> bool {
+    device.name.to_lowercase().starts_with("stm32")
+  }
+
+  fn apply(&self, mut device: DeviceSpec) -> Result<DeviceSpec> {
+    for pattern in Self::FIELD_SETS.iter() {
+      let field_set = Regex::new(pattern)?;
+      let in_set = |name: &str| field_set.is_match(&name.to_lowercase());
+
+      for peripheral in device.peripherals.iter_mut() {
+        let values = match peripheral
+          .registers
+          .iter()
+          .flat_map(|r| r.fields.iter())
+          .find(|f| in_set(&f.name) && has_values(f))
+        {
+          Some(f) => f.enumerated_value_sets.clone(),
+          None => continue,
+        };
+
+        for field in peripheral
+          .registers
+          .iter_mut()
+          .flat_map(|r| r.fields.iter_mut())
+          .filter(|f| in_set(&f.name) && !has_values(f))
+        {
+          field.enumerated_value_sets = values.clone();
+        }
+      }
+    }
+
+    Ok(device)
+  }
+}
+
+// Some SVDs list the alternate functions of a GPIO port's AFR fields only by
+// number (`AF0` to `AF15`), the same for every pin, and leave them off the rest
+// of the fields. Those are copied to the AFR fields with none. Values naming
+// real functions belong to one pin, so they're never copied; pins without any
+// are left empty, with a warning.
+//
+// `derivedFrom` is already resolved by the time the fixups run, so a field that
+// declared it has its values by now.
+pub struct InheritGenericAltFuncs;
+impl SvdFixup for InheritGenericAltFuncs {
+  fn name(&self) -> &str {
+    "inherit generic alternate functions"
+  }
+
+  fn applies_to(&self, device: &DeviceSpec) -> bool {
+    device.name.to_lowercase().starts_with("stm32")
+  }
+
+  fn apply(&self, mut device: DeviceSpec) -> Result<DeviceSpec> {
+    let afr_field = Regex::new(r"^afr[lh][0-9]+$")?;
+    let generic_name = Regex::new(r"^af[0-9]+$")?;
+    let in_set = |name: &str| afr_field.is_match(&name.to_lowercase());
+
+    for peripheral in device.peripherals.iter_mut() {
+      let source = match peripheral
+        .registers
+        .iter()
+        .flat_map(|r| r.fields.iter())
+        .find(|f| in_set(&f.name) && has_values(f))
+      {
+        Some(f) => f,
+        None => continue,
+      };
+
+      let generic = source
+        .enumerated_value_sets
+        .iter()
+        .flat_map(|vs| vs.values.iter())
+        .all(|v| {
+          let name = v.name.to_lowercase();
+          generic_name.is_match(&name)
+            && match v.description {
+              Some(ref d) => d.trim().to_lowercase() == name,
+              None => true,
+            }
+        });
+      let values = match generic {
+        true => Some(source.enumerated_value_sets.clone()),
+        false => None,
+      };
+
+      for register in peripheral.registers.iter_mut() {
+        for field in register
+          .fields
+          .iter_mut()
+          .filter(|f| in_set(&f.name) && !has_values(f))
+        {
+          match values {
+            Some(ref v) => field.enumerated_value_sets = v.clone(),
+            None => warn!(
+              "{}.{}.{} has no alternate functions in the SVD, and its siblings' are pin specific; \
+               leaving it empty",
+              peripheral.name, register.name, field.name
+            ),
+          }
+        }
+      }
+    }
+
+    Ok(device)
+  }
+}
+
+fn has_values(field: &FieldSpec) -> bool {
+  field
+    .enumerated_value_sets
+    .iter()
+    .any(|vs| !vs.values.is_empty())
+}
+
+// Loads a device for a test the way the CLI does, with the built-in fixups.
+#[cfg(test)]
+pub fn load_fixed(path: &str) -> DeviceSpec {
+  Fixups::builtin()
+    .apply(DeviceSpec::from_file(path).unwrap())
+    .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::system::gpio::Gpio;
+
+  #[test]
+  fn leaves_afr_fields_without_values_empty() {
+    let mut device = DeviceSpec::from_file("specs/svd/stm32f303.svd.patched").unwrap();
+
+    // AFRL0's values name PA0's functions, which would be wrong on any other pin
+    let peripheral = device
+      .peripherals
+      .iter_mut()
+      .find(|p| p.name == "GPIOA")
+      .unwrap();
+    for field in peripheral
+      .registers
+      .iter_mut()
+      .flat_map(|r| r.fields.iter_mut())
+      .filter(|f| f.name.to_lowercase() == "afrl1")
+    {
+      field.enumerated_value_sets = vec![];
+    }
+
+    let device = Fixups::builtin().apply(device).unwrap();
+    let peripheral = device
+      .peripherals
+      .iter()
+      .find(|p| p.name == "GPIOA")
+      .unwrap();
+    let gpio = Gpio::new(&device, peripheral).unwrap();
+    assert!(gpio.pins[1].alt_funcs.is_empty());
+
+    // The other pins keep their own functions
+    let names = |pin: usize| -> Vec<String> {
+      gpio.pins[pin]
+        .alt_funcs
+        .iter()
+        .map(|af| af.name.snake())
+        .collect()
+    };
+    assert!(names(0).contains(&"tim2_ch1_etr".to_owned()));
+    assert!(names(2).contains(&"tim2_ch3".to_owned()));
+    assert!(!names(2).contains(&"tim2_ch1_etr".to_owned()));
+  }
+
+  #[test]
+  fn inherits_generic_alt_funcs_for_afr_fields() {
+    let mut device = DeviceSpec::from_file("specs/svd/stm32f303.svd.patched").unwrap();
+
+    // Number AFRL0's values only, and leave the other fields without any
+    let peripheral = device
+      .peripherals
+      .iter_mut()
+      .find(|p| p.name == "GPIOA")
+      .unwrap();
+    for field in peripheral
+      .registers
+      .iter_mut()
+      .flat_map(|r| r.fields.iter_mut())
+      .filter(|f| f.name.to_lowercase().starts_with("afr"))
+    {
+      match field.name.to_lowercase() == "afrl0" {
+        true => {
+          for value in field
+            .enumerated_value_sets
+            .iter_mut()
+            .flat_map(|vs| vs.values.iter_mut())
+          {
+            value.description = None;
+          }
+        }
+        false => field.enumerated_value_sets = vec![],
+      }
+    }
+
+    let device = Fixups::builtin().apply(device).unwrap();
+    let peripheral = device
+      .peripherals
+      .iter()
+      .find(|p| p.name == "GPIOA")
+      .unwrap();
+    let afr_fields: Vec<&FieldSpec> = peripheral
+      .registers
+      .iter()
+      .flat_map(|r| r.fields.iter())
+      .filter(|f| f.name.to_lowercase().starts_with("afr"))
+      .collect();
+    assert_eq!(16, afr_fields.len());
+    for field in afr_fields {
+      let names: Vec<String> = field
+        .enumerated_value_sets
+        .iter()
+        .flat_map(|vs| vs.values.iter())
+        .map(|v| v.name.to_lowercase())
+        .collect();
+      let expected: Vec<String> = (0..16).map(|n| f!("af{n}")).collect();
+      assert_eq!(expected, names, "{}", field.name);
+    }
+  }
+
+  #[test]
+  fn skips_fixups_for_other_devices() {
+    struct Rename;
+    impl SvdFixup for Rename {
+      fn name(&self) -> &str {
+        "rename"
+      }
+
+      fn applies_to(&self, device: &DeviceSpec) -> bool {
+        device.name.starts_with("STM32F1")
+      }
+
+      fn apply(&self, mut device: DeviceSpec) -> Result<DeviceSpec> {
+        device.name = "renamed".to_owned();
+        Ok(device)
+      }
+    }
+
+    let mut fixups = Fixups::empty();
+    fixups.register(Rename);
+
+    let device = DeviceSpec::from_file("specs/svd/stm32f303.svd.patched").unwrap();
+    assert_eq!("STM32F303", fixups.apply(device).unwrap().name);
+
+    let device = DeviceSpec::from_file("specs/svd/stm32f103.svd.patched").unwrap();
+    assert_eq!("renamed", fixups.apply(device).unwrap().name);
+  }
+}
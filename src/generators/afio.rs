@@ -0,0 +1,94 @@
+use crate::{clear_bit, set_bit, write_val, write_val_with};
+use crate::{
+  file::OutputDirectory,
+  generators::{render_or_skip, ReadWrite},
+  system::{afio::Afio, SystemInfo},
+};
+use anyhow::Result;
+use askama::Template;
+use svd_expander::DeviceSpec;
+
+pub fn generate(
+  dry_run: bool,
+  sys_info: &mut SystemInfo,
+  src_dir: &OutputDirectory,
+  api_path: String,
+) -> Result<()> {
+  let afio = match sys_info.afio {
+    Some(ref a) => a,
+    None => return Ok(()),
+  };
+
+  let rendered = PeripheralTemplate {
+    api_path: api_path.clone(),
+    afio,
+    d: &sys_info.device,
+  }
+  .render();
+  match render_or_skip(&afio.name, rendered)? {
+    Some(rendered) => src_dir.publish(
+      dry_run,
+      &format!("afio/{}.rs", afio.name.snake()),
+      &rendered,
+    )?,
+    None => {
+      sys_info.afio = None;
+      return Ok(());
+    }
+  }
+
+  src_dir.publish(
+    dry_run,
+    &f!("afio/mod.rs"),
+    &ModTemplate { s: sys_info }.render()?,
+  )?;
+
+  Ok(())
+}
+
+#[derive(Template)]
+#[template(path = "afio/mod.rs.askama", escape = "none")]
+struct ModTemplate<'a> {
+  s: &'a SystemInfo<'a>,
+}
+
+#[derive(Template)]
+#[template(path = "afio/peripheral.rs.askama", escape = "none")]
+struct PeripheralTemplate<'a> {
+  api_path: String,
+  afio: &'a Afio,
+  d: &'a DeviceSpec,
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{fixup::load_fixed, generators::find_method};
+
+  use super::*;
+
+  #[test]
+  fn sets_swj_cfg_with_every_mapr_remap() {
+    let device = load_fixed("specs/svd/stm32f103.svd.patched");
+    let peripheral = device
+      .peripherals
+      .iter()
+      .find(|p| p.name == "AFIO")
+      .unwrap();
+    let afio = Afio::new(&device, peripheral).unwrap().unwrap();
+
+    let rendered = PeripheralTemplate {
+      api_path: "crate".to_owned(),
+      afio: &afio,
+      d: &device,
+    }
+    .render()
+    .unwrap();
+
+    let remap_spi1 = find_method(&rendered, "pub fn remap_spi1(");
+    assert!(remap_spi1.contains(
+      "/* Set afio.mapr.spi1_remap = remapped as u32, afio.mapr.swj_cfg = self.debug_port as u32 */"
+    ));
+    // Only set_debug_port writes MAPR without setting SWJ_CFG alongside.
+    assert_eq!(1, rendered.matches("write_val_itf(0x40010004").count());
+  }
+}
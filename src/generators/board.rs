@@ -0,0 +1,72 @@
+use crate::{file::OutputDirectory, system::SystemInfo};
+use anyhow::Result;
+use askama::Template;
+
+pub fn generate(
+  dry_run: bool,
+  sys_info: &SystemInfo,
+  src_dir: &OutputDirectory,
+  api_path: String,
+) -> Result<()> {
+  if sys_info.board.is_empty() {
+    return Ok(());
+  }
+
+  src_dir.publish(
+    dry_run,
+    &f!("board.rs"),
+    &ModTemplate {
+      api_path,
+      device_name: sys_info.device.name.to_lowercase(),
+      s: sys_info,
+    }
+    .render()?,
+  )?;
+
+  Ok(())
+}
+
+#[derive(Template)]
+#[template(path = "board/mod.rs.askama", escape = "none")]
+struct ModTemplate<'a> {
+  api_path: String,
+  device_name: String,
+  s: &'a SystemInfo<'a>,
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::fixup::load_fixed;
+  use crate::system::board::BoardPins;
+
+  use super::*;
+
+  #[test]
+  fn hands_out_configured_pins() {
+    let device = load_fixed("specs/svd/stm32f303.svd.patched");
+    let mut sys_info = SystemInfo::new(&device).unwrap();
+    sys_info.board = BoardPins::from_ron(
+      r#"BoardPins(pins: {
+        "led": (pin: "PE9", mode: Output),
+        "uart_tx": (pin: "PA9", mode: AltFunc("USART1_TX")),
+      })"#,
+    )
+    .unwrap()
+    .resolve(&sys_info.gpios)
+    .unwrap();
+
+    let rendered = ModTemplate {
+      api_path: "crate".to_owned(),
+      device_name: "stm32f303".to_owned(),
+      s: &sys_info,
+    }
+    .render()
+    .unwrap();
+
+    assert!(rendered.contains("use crate::gpio::gpio_a::{ Pa9AltFunc, Pa9Usart1Tx };"));
+    assert!(rendered.contains(
+      "pub fn uart_tx(port: &mut crate::gpio::gpio_a::GpioA) -> Result<Pa9AltFunc<Pa9Usart1Tx>> {"
+    ));
+    assert!(rendered.contains("Ok(port.take_pe9()?.as_output("));
+  }
+}
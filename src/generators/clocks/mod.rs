@@ -1,31 +1,99 @@
 mod schematic;
 
-use std::path::Path;
+use std::{
+  collections::BTreeMap,
+  path::{Path, PathBuf},
+};
 use svd_expander::DeviceSpec;
 
-use crate::file::OutputDirectory;
+use crate::{file::OutputDirectory, generators::reset};
 
 use self::templates::ClocksTemplate;
 use askama::Template;
 
-use anyhow::{anyhow, bail, Result};
-use schematic::{ClockComponent, ClockSchematic};
+use anyhow::{anyhow, bail, Error, Result};
+use schematic::{combine_errors, ClockComponent, ClockOutputNameSelection, ClockSchematic};
 
-pub fn generate(
+pub fn generate<'a>(
   dry_run: bool,
-  d: &DeviceSpec,
+  clock_spec_filepath: &Path,
+  d: &'a DeviceSpec,
   out_dir: &OutputDirectory,
   api_path: String,
-) -> Result<()> {
-  let clock_spec_filepath = format!("specs/clock/{}.ron", d.name.to_lowercase());
+) -> Result<ClockGenerator<'a>> {
+  let generator = ClockGenerator::from_ron_file(clock_spec_filepath, d)?;
+  generator.generate(dry_run, out_dir, api_path.to_owned())?;
+
+  // Other generators depend on particular taps, like SysTick on the core clock.
+  Ok(generator)
+}
+
+pub fn list_terminal_taps(d: &DeviceSpec, location: &ClockSpecLocation) -> Result<Vec<String>> {
+  let clock_spec_filepath = match location.find(d)? {
+    Some(p) => p,
+    None => bail!("No clock spec found for device {}", d.name),
+  };
+
+  let mut taps = ClockGenerator::from_ron_file(clock_spec_filepath, d)?
+    .schematic
+    .list_outputs(ClockOutputNameSelection::TerminalTapsOnly);
+  taps.sort();
+
+  Ok(taps)
+}
+
+// The frequency of every tap, in Hz, with the schematic's default selections.
+pub fn resolved_frequencies(
+  d: &DeviceSpec,
+  location: &ClockSpecLocation,
+) -> Result<BTreeMap<String, u32>> {
+  let clock_spec_filepath = match location.find(d)? {
+    Some(p) => p,
+    None => bail!("No clock spec found for device {}", d.name),
+  };
+
+  ClockGenerator::from_ron_file(clock_spec_filepath, d)?.resolved_frequencies()
+}
 
-  ClockGenerator::from_ron_file(clock_spec_filepath, d)?.generate(
-    dry_run,
-    out_dir,
-    api_path.to_owned(),
-  )?;
+// Checks a clock spec on its own, without a device to check its field paths
+// against, and returns every problem found in it.
+pub fn check_spec(clock_spec_filepath: &Path) -> Result<Vec<Error>> {
+  Ok(ClockSchematic::parse_ron_file(clock_spec_filepath)?.validate_standalone())
+}
 
-  Ok(())
+// Where to look for a device's clock schematic. An explicit path is used for
+// every device, otherwise the search directories are tried in order for a file
+// named after the device.
+pub struct ClockSpecLocation {
+  pub path: Option<PathBuf>,
+  pub search_dirs: Vec<PathBuf>,
+}
+impl ClockSpecLocation {
+  pub fn find(&self, d: &DeviceSpec) -> Result<Option<PathBuf>> {
+    if let Some(ref path) = self.path {
+      if !path.is_file() {
+        bail!("Clock spec '{}' does not exist", path.display());
+      }
+      return Ok(Some(path.clone()));
+    }
+
+    let file_name = format!("{}.ron", d.name.to_lowercase());
+    Ok(
+      self
+        .search_dirs
+        .iter()
+        .map(|dir| dir.join(&file_name))
+        .find(|p| p.is_file()),
+    )
+  }
+}
+impl Default for ClockSpecLocation {
+  fn default() -> Self {
+    Self {
+      path: None,
+      search_dirs: vec![PathBuf::from("specs/clock")],
+    }
+  }
 }
 
 #[derive(Debug)]
@@ -40,7 +108,7 @@ impl<'a> ClockGenerator<'a> {
   ) -> Result<ClockGenerator<'a>> {
     let generator = ClockGenerator {
       spec,
-      schematic: ClockSchematic::from_ron_file(path)?,
+      schematic: ClockSchematic::parse_ron_file(path)?,
     };
     generator.validate()?;
     Ok(generator)
@@ -50,27 +118,96 @@ impl<'a> ClockGenerator<'a> {
   pub fn from_ron<S: Into<String>>(ron: S, spec: &'a DeviceSpec) -> Result<ClockGenerator<'a>> {
     let generator = ClockGenerator {
       spec,
-      schematic: ClockSchematic::from_ron(ron)?,
+      schematic: ClockSchematic::parse_ron(ron)?,
     };
     generator.validate()?;
     Ok(generator)
   }
 
+  pub fn tap_names(&self) -> Vec<String> {
+    self.schematic.taps().map(|t| t.name.clone()).collect()
+  }
+
+  // The clock that's divided down to feed a tap, when the tap sits right after
+  // a divider.
+  pub fn divided_input<S: Into<String>>(&self, tap_name: S) -> Option<String> {
+    match self.schematic.get_component(tap_name)? {
+      ClockComponent::Tap(tap) => match self.schematic.get_component(tap.input)? {
+        ClockComponent::Divider(div) => Some(div.input),
+        _ => None,
+      },
+      _ => None,
+    }
+  }
+
   pub fn generate(&self, dry_run: bool, src_dir: &OutputDirectory, api_path: String) -> Result<()> {
-    let clocks_file = ClocksTemplate::new(&self.schematic, &self.spec, api_path)?.render()?;
+    let clocks_file = ClocksTemplate::new(&self.schematic, &self.spec, api_path)?
+      .render()
+      .map_err(|_| anyhow!("Could not generate clocks for device {}", self.spec.name))?;
 
     src_dir.publish(dry_run, &f!("clocks.rs"), &clocks_file)?;
 
     Ok(())
   }
 
+  pub fn resolved_frequencies(&self) -> Result<BTreeMap<String, u32>> {
+    let no_selections = BTreeMap::new();
+    self
+      .schematic
+      .taps()
+      .map(|t| {
+        let freq = self.schematic.frequency(t.name.clone(), &no_selections)?;
+        Ok((t.name.clone(), freq.round() as u32))
+      })
+      .collect()
+  }
+
+  // Reports the problems with the schematic itself together with the ones
+  // found checking it against the device.
   fn validate(&self) -> Result<()> {
-    self.check_valid_field_paths()?;
-    self.check_valid_field_input_sizes()?;
+    let mut errors = self.schematic.validate_standalone();
+    errors.extend(self.check_valid_field_paths());
+    errors.extend(self.check_valid_field_input_sizes());
+    errors.extend(self.check_defaults_match_reset());
+
+    // Resolving frequencies needs a sound schematic, a loop would never end.
+    if errors.is_empty() {
+      self.check_default_frequencies()?;
+    }
+
+    combine_errors(errors)
+  }
+
+  fn check_default_frequencies(&self) -> Result<()> {
+    let no_selections = BTreeMap::new();
+    for tap in self.schematic.taps().filter(|t| t.max > 0) {
+      let freq = self.schematic.frequency(tap.name.clone(), &no_selections)?;
+      if freq > tap.max as f32 {
+        warn!(
+          "Default frequency {} Hz of {} is above its maximum of {} Hz",
+          freq, tap.name, tap.max
+        );
+      }
+    }
+
+    // A terminal tap that's stopped by default without being switched off is
+    // usually a default pointing at the wrong input, and the peripherals on it
+    // can't time anything.
+    for tap in self.schematic.taps().filter(|t| t.terminal) {
+      let freq = self.schematic.frequency(tap.name.clone(), &no_selections)?;
+      if freq == 0f32 && !self.schematic.defaults_to_off(tap.name.clone()) {
+        let message = format!("Default frequency of {} is 0 Hz", tap.name);
+        match reset::is_strict() {
+          true => bail!(message),
+          false => warn!("{}", message),
+        }
+      }
+    }
+
     Ok(())
   }
 
-  fn check_valid_field_paths(&self) -> Result<()> {
+  fn check_valid_field_paths(&self) -> Vec<Error> {
     let input_paths = self
       .schematic
       .get_all_components()
@@ -89,17 +226,14 @@ impl<'a> ClockGenerator<'a> {
       })
       .collect::<Vec<String>>();
 
-    for path in input_paths {
-      match self.spec.try_get_field(&path) {
-        None => bail!("No field named '{}' in SVD spec", path),
-        _ => {}
-      }
-    }
-
-    Ok(())
+    input_paths
+      .iter()
+      .filter(|path| self.spec.try_get_field(path.as_str()).is_none())
+      .map(|path| anyhow!("No field named '{}' in SVD spec", path))
+      .collect()
   }
 
-  fn check_valid_field_input_sizes(&self) -> Result<()> {
+  fn check_valid_field_input_sizes(&self) -> Vec<Error> {
     let field_vals = self
       .schematic
       .get_all_components()
@@ -130,15 +264,78 @@ impl<'a> ClockGenerator<'a> {
       })
       .collect::<Vec<(String, u32, String)>>();
 
-    for field_val in field_vals.iter() {
-      self.check_valid_input_size(&field_val.0, field_val.1, &field_val.2)?;
+    field_vals
+      .iter()
+      .filter_map(|field_val| {
+        self
+          .check_valid_input_size(&field_val.0, field_val.1, &field_val.2)
+          .err()
+      })
+      .collect()
+  }
+
+  // The apply sequence starts from the schematic's defaults, so a default that
+  // isn't what the hardware comes out of reset with can leave it waiting on
+  // the wrong clock. Defaults missing from their component's options and fields
+  // missing from the device are reported by other checks, so they're skipped.
+  fn check_defaults_match_reset(&self) -> Vec<Error> {
+    let defaults = self
+      .schematic
+      .get_all_components()
+      .iter()
+      .filter_map(|c| match c {
+        ClockComponent::Multiplexer(m) => m
+          .default_input()
+          .ok()
+          .map(|i| (m.path.clone(), i.bit_value, m.name.clone())),
+        ClockComponent::Divider(d) => match d.is_fixed() {
+          true => None,
+          false => d
+            .default_input()
+            .ok()
+            .map(|v| (d.path.clone(), v.bit_value, d.name.clone())),
+        },
+        ClockComponent::Multiplier(m) => match (m.is_fixed(), m.is_conditional()) {
+          (false, false) => m
+            .default_input()
+            .ok()
+            .map(|v| (m.path.clone(), v.bit_value, m.name.clone())),
+          _ => None,
+        },
+        _ => None,
+      })
+      .collect::<Vec<(String, u32, String)>>();
+
+    let mut errors = Vec::new();
+    for (path, bit_value, component_name) in defaults.iter() {
+      let reset_value = match reset::override_for(path) {
+        Some(v) => v,
+        None => match self.spec.try_get_field(path).and_then(|f| f.reset_value) {
+          Some(v) => v,
+          None => continue,
+        },
+      };
+
+      if *bit_value != reset_value {
+        let message = format!(
+          "Default bit value '{}' of {} does not match reset value '{}' of field '{}'",
+          bit_value, component_name, reset_value, path
+        );
+        match reset::is_strict() {
+          true => errors.push(anyhow!(message)),
+          false => warn!("{}", message),
+        }
+      }
     }
 
-    Ok(())
+    errors
   }
 
   fn check_valid_input_size(&self, path: &str, bit_value: u32, component_name: &str) -> Result<()> {
-    let field_spec = self.spec.get_field(path)?;
+    let field_spec = match self.spec.try_get_field(path) {
+      Some(f) => f,
+      None => return Ok(()),
+    };
     let shift = 32 - field_spec.width;
     let max_val = std::u32::MAX << shift >> shift;
 
@@ -175,6 +372,8 @@ mod templates {
     device: &'a DeviceSpec,
     sys_clk_mux: Mux,
     flash_latency: FlashLat,
+    flash_acceleration: Vec<String>,
+    presets: Vec<Preset>,
     oscillators: Vec<Osc>,
     multiplexers: Vec<Mux>,
     configurable_dividers: Vec<VarDiv>,
@@ -198,6 +397,12 @@ mod templates {
         device: spec,
         sys_clk_mux: Mux::new(schematic.get_sys_clk_mux()?)?,
         flash_latency: FlashLat::new(schematic.flash_latency()),
+        flash_acceleration: flash_acceleration_paths(schematic, spec),
+        presets: schematic
+          .sys_clk_presets()
+          .iter()
+          .map(|mhz| Preset::new(schematic, *mhz))
+          .collect::<Result<Vec<Preset>>>()?,
         oscillators: schematic.oscillators().map(|o| Osc::new(o)).collect(),
         multiplexers: schematic
           .multiplexers()
@@ -264,6 +469,113 @@ mod templates {
     }
   }
 
+  // Parts in a family don't all have the same flash accelerator, so bits the
+  // device doesn't have are left out instead of failing the whole schematic.
+  fn flash_acceleration_paths(schematic: &ClockSchematic, spec: &DeviceSpec) -> Vec<String> {
+    let paths = match schematic.flash_acceleration() {
+      Some(a) => a.paths(),
+      None => return vec![],
+    };
+
+    paths
+      .into_iter()
+      .filter(|path| match spec.try_get_field(path.as_str()) {
+        Some(_) => true,
+        None => {
+          warn!(
+            "No field named '{}' in SVD spec, leaving it out of the flash acceleration",
+            path
+          );
+          false
+        }
+      })
+      .cloned()
+      .collect()
+  }
+
+  // A configuration worked out for a system clock frequency when generating,
+  // as the value of each configurable field of `ClockConfig`.
+  pub struct Preset {
+    mhz: u32,
+    actual_freq: u32,
+    settings: Vec<PresetSetting>,
+  }
+  impl Preset {
+    pub fn new(schematic: &ClockSchematic, mhz: u32) -> Result<Preset> {
+      let selections = schematic
+        .solve_sys_clk(mhz as f32 * 1_000_000f32)
+        .map_err(|e| anyhow!("Can't configure the clocks for {} MHz: {}", mhz, e))?;
+      let sys_clk_mux = schematic.get_sys_clk_mux()?;
+      let actual_freq = schematic
+        .frequency(sys_clk_mux.name.clone(), &selections)?
+        .round() as u32;
+      if actual_freq != mhz * 1_000_000 {
+        warn!(
+          "The closest the system clock gets to {} MHz is {} Hz",
+          mhz, actual_freq
+        );
+      }
+
+      let mut settings = Vec::new();
+      for mux in schematic.multiplexers() {
+        let input = match selections.get(&mux.name) {
+          Some(i) => mux.inputs[i].clone(),
+          None => mux.default_input()?,
+        };
+        settings.push(PresetSetting {
+          field: format!("{}_input", mux.name.to_snake_case()),
+          value: format!(
+            "{}Input::{}",
+            mux.name.to_camel_case(),
+            input.public_name().to_camel_case()
+          ),
+        });
+      }
+      for div in schematic.dividers().filter(|d| !d.is_fixed()) {
+        let option = match selections.get(&div.name) {
+          Some(o) => &div.values[o],
+          None => div.default_input()?,
+        };
+        settings.push(PresetSetting {
+          field: format!("{}_value", div.name.to_snake_case()),
+          value: format!(
+            "{}Value::{}",
+            div.name.to_camel_case(),
+            option.name.to_camel_case()
+          ),
+        });
+      }
+      for mul in schematic
+        .multipliers()
+        .filter(|m| !m.is_fixed() && !m.is_conditional())
+      {
+        let option = match selections.get(&mul.name) {
+          Some(o) => &mul.values[o],
+          None => mul.default_input()?,
+        };
+        settings.push(PresetSetting {
+          field: format!("{}_value", mul.name.to_snake_case()),
+          value: format!(
+            "{}Value::{}",
+            mul.name.to_camel_case(),
+            option.name.to_camel_case()
+          ),
+        });
+      }
+
+      Ok(Preset {
+        mhz,
+        actual_freq,
+        settings,
+      })
+    }
+  }
+
+  pub struct PresetSetting {
+    field: String,
+    value: String,
+  }
+
   pub struct FlashLat {
     path: String,
     ranges: Vec<LatencyRange>,
@@ -554,10 +866,27 @@ mod templates {
 
 #[cfg(test)]
 mod tests {
+  use std::fs;
+
   use svd_expander::DeviceSpec;
 
+  use crate::fixup::load_fixed;
+
   use super::*;
 
+  #[test]
+  fn resolves_default_tap_frequencies() {
+    let device = load_fixed("specs/svd/stm32f303.svd.patched");
+    let frequencies = ClockGenerator::from_ron_file("specs/clock/stm32f303.ron", &device)
+      .unwrap()
+      .resolved_frequencies()
+      .unwrap();
+
+    assert_eq!(Some(&8_000_000), frequencies.get("sys_clk"));
+    assert_eq!(Some(&8_000_000), frequencies.get("h_clk"));
+    assert!(frequencies.contains_key("to_tim2"));
+  }
+
   #[test]
   fn rejects_invalid_field_paths() {
     let clock_ron = r#"
@@ -695,4 +1024,203 @@ mod tests {
       res.unwrap_err().to_string()
     );
   }
+
+  #[test]
+  fn rejects_defaults_that_differ_from_reset_in_strict_mode() {
+    let clock_ron = r#"
+      ClockSchematic(
+        oscillators: {
+          "hse": (
+            frequency: 8000000
+          )
+        },
+        multiplexers: {},
+        dividers: {
+          "pll_div": (
+            input: "hse",
+            path: "timer0.cr.mode",
+            values: {
+              "div1": (
+                divisor: 1, 
+                bit_value: 0
+              ),
+              "div2": (
+                divisor: 2, 
+                bit_value: 1
+              )
+            },
+            default: 2,
+          )
+        },
+        multipliers: {},
+        taps: {
+          "tap1": (
+            input: "pll_div", 
+            max: 1000000, 
+            terminal: true
+          ),
+        }
+      )
+    "#;
+
+    let device = DeviceSpec::from_file("specs/svd/arm_device.svd").unwrap();
+
+    reset::configure(&device, false).unwrap();
+    assert!(ClockGenerator::from_ron(clock_ron, &device).is_ok());
+
+    reset::configure(&device, true).unwrap();
+    let res = ClockGenerator::from_ron(clock_ron, &device);
+
+    assert!(res.is_err());
+    assert_eq!(
+      "Default bit value '1' of pll_div does not match reset value '0' of field 'timer0.cr.mode'",
+      res.unwrap_err().to_string()
+    );
+  }
+
+  #[test]
+  fn rejects_stopped_default_taps_in_strict_mode() {
+    let clock_ron = |hse_frequency: u32| {
+      format!(
+        r#"
+      ClockSchematic(
+        oscillators: {{
+          "hse": (
+            frequency: {}
+          )
+        }},
+        multiplexers: {{
+          "mco_mux": (
+            path: "timer0.cr.mode",
+            inputs: {{
+              "off": (bit_value: 0),
+              "hse": (bit_value: 1)
+            }},
+            default: "off"
+          )
+        }},
+        dividers: {{}},
+        multipliers: {{}},
+        taps: {{
+          "tap1": (
+            input: "hse",
+            max: 0,
+            terminal: true
+          ),
+          "mco": (
+            input: "mco_mux",
+            max: 0,
+            terminal: true
+          ),
+        }}
+      )
+    "#,
+        hse_frequency
+      )
+    };
+
+    let device = DeviceSpec::from_file("specs/svd/arm_device.svd").unwrap();
+    reset::configure(&device, true).unwrap();
+
+    // The MCO tap is switched off by default, which is fine.
+    assert!(ClockGenerator::from_ron(clock_ron(8000000), &device).is_ok());
+
+    let res = ClockGenerator::from_ron(clock_ron(0), &device);
+    assert!(res.is_err());
+    assert_eq!(
+      "Default frequency of tap1 is 0 Hz",
+      res.unwrap_err().to_string()
+    );
+  }
+
+  #[test]
+  fn searches_clock_spec_dirs_in_order() {
+    let f303 = load_fixed("specs/svd/stm32f303.svd.patched");
+    let f401 = load_fixed("specs/svd/stm32f401.svd.patched");
+    let location = ClockSpecLocation {
+      path: None,
+      search_dirs: vec![PathBuf::from("specs/missing"), PathBuf::from("specs/clock")],
+    };
+
+    assert_eq!(
+      Some(PathBuf::from("specs/clock/stm32f303.ron")),
+      location.find(&f303).unwrap()
+    );
+    assert_eq!(None, location.find(&f401).unwrap());
+
+    let missing = ClockSpecLocation {
+      path: Some(PathBuf::from("specs/clock/missing.ron")),
+      search_dirs: vec![],
+    };
+    assert!(missing.find(&f303).is_err());
+  }
+
+  #[test]
+  fn finds_the_clock_divided_for_a_tap() {
+    let device = load_fixed("specs/svd/stm32f303.svd.patched");
+    let generator = ClockGenerator::from_ron_file("specs/clock/stm32f303.ron", &device).unwrap();
+
+    assert_eq!(
+      Some("h_clk".to_owned()),
+      generator.divided_input("to_cortex_system_timer")
+    );
+    assert_eq!(None, generator.divided_input("f_clk_cortex_clock"));
+  }
+
+  #[test]
+  fn renders_chainable_config_setters() {
+    let device = load_fixed("specs/svd/stm32f303.svd.patched");
+    let generator = ClockGenerator::from_ron_file("specs/clock/stm32f303.ron", &device).unwrap();
+    let rendered = ClocksTemplate::new(&generator.schematic, &device, "crate".to_owned())
+      .unwrap()
+      .render()
+      .unwrap();
+
+    assert!(rendered.contains("pub fn hse(mut self, freq: u64) -> ClockConfig"));
+    assert!(rendered
+      .contains("pub fn system_clock_mux(mut self, input: SystemClockMuxInput) -> ClockConfig"));
+    assert!(rendered.contains("pub fn pll_mul(mut self, factor: PllMulValue) -> ClockConfig"));
+    assert!(rendered.contains("pub fn freeze(self) -> Result<Clocks>"));
+  }
+
+  #[test]
+  fn precomputes_preset_configurations() {
+    let device = load_fixed("specs/svd/stm32f303.svd.patched");
+    let generator = ClockGenerator::from_ron_file("specs/clock/stm32f303.ron", &device).unwrap();
+    let rendered = ClocksTemplate::new(&generator.schematic, &device, "crate".to_owned())
+      .unwrap()
+      .render()
+      .unwrap();
+
+    assert!(rendered.contains("pub fn configure_for(mhz: u32) -> Result<ClockConfig>"));
+    assert!(rendered.contains("48 => Ok(PRESET_48_MHZ),"));
+
+    let preset = &rendered[rendered.find("const PRESET_48_MHZ").unwrap()..];
+    let preset = &preset[..preset.find("};").unwrap()];
+    assert!(preset.contains("system_clock_mux_input: SystemClockMuxInput::Pll,"));
+    assert!(preset.contains("pll_source_mux_input: PllSourceMuxInput::Hsi,"));
+    assert!(preset.contains("pll_mul_value: PllMulValue::Mul12,"));
+    // 48 MHz is above the APB buses' 36 MHz maximum.
+    assert!(preset.contains("apb1_prescaler_value: Apb1PrescalerValue::Div2,"));
+    assert!(preset.contains("hse_pll_value: HsePllValue::NoDiv,"));
+  }
+
+  #[test]
+  fn sets_flash_acceleration_bits_the_device_has() {
+    let device = load_fixed("specs/svd/stm32f303.svd.patched");
+    let clock_ron = fs::read_to_string("specs/clock/stm32f303.ron")
+      .unwrap()
+      .replace(
+        r#"prefetch: Some("flash.acr.prftbe"),"#,
+        r#"prefetch: Some("flash.acr.prftbe"), data_cache: Some("flash.acr.dcen"),"#,
+      );
+    let generator = ClockGenerator::from_ron(clock_ron, &device).unwrap();
+    let rendered = ClocksTemplate::new(&generator.schematic, &device, "crate".to_owned())
+      .unwrap()
+      .render()
+      .unwrap();
+
+    assert!(rendered.contains("/* Set flash.acr.prftbe */"));
+    assert!(!rendered.contains("flash.acr.dcen"));
+  }
 }
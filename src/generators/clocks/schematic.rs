@@ -1,16 +1,95 @@
-use std::{collections::hash_map::Values, fs};
-use std::{collections::HashMap, path::Path};
+use std::{collections::btree_map::Values, fs};
+use std::{collections::BTreeMap, path::Path};
 
-use anyhow::{anyhow, bail, Result};
+use anyhow::{anyhow, bail, Error, Result};
+use regex::Regex;
 use serde::Deserialize;
 
-enum ClockOutputNameSelection {
-  #[allow(dead_code)]
+use crate::error::GeneratorError;
+
+pub enum ClockOutputNameSelection {
   TerminalTapsOnly,
   EverythingExceptTerminalTaps,
   Everything,
 }
 
+// Turns the problems found while validating a clock spec into one error that
+// lists all of them.
+pub fn combine_errors(errors: Vec<Error>) -> Result<()> {
+  let mut errors = errors.into_iter();
+  match (errors.next(), errors.next()) {
+    (None, _) => Ok(()),
+    (Some(only), None) => Err(only),
+    (Some(first), Some(second)) => {
+      let problems = vec![first, second]
+        .into_iter()
+        .chain(errors)
+        .map(|e| format!("  {}", e))
+        .collect::<Vec<String>>();
+      Err(anyhow!(
+        "{} problems in clock spec:\n{}",
+        problems.len(),
+        problems.join("\n")
+      ))
+    }
+  }
+}
+
+fn parse_error(err: ron::Error) -> GeneratorError {
+  GeneratorError::ClockSpecParse {
+    message: err.to_string(),
+  }
+}
+
+// The newest clock spec format this generator reads. Specs without a `version`
+// are version 1. The README lists what changed in each version.
+pub const CLOCK_SPEC_VERSION: u32 = 1;
+
+fn default_version() -> u32 {
+  1
+}
+
+fn check_version(version: u32) -> Result<(), GeneratorError> {
+  match version {
+    1..=CLOCK_SPEC_VERSION => Ok(()),
+    _ => Err(GeneratorError::ClockSpecParse {
+      message: format!(
+        "the spec is version {}, but this generator only reads versions 1 to {}",
+        version, CLOCK_SPEC_VERSION
+      ),
+    }),
+  }
+}
+
+// The version a spec declares, found without parsing the rest of it, which
+// may be in a format this generator doesn't understand.
+fn declared_version(ron: &str) -> Option<u32> {
+  Regex::new(r"(?m)^\s*version\s*:\s*([0-9]+)")
+    .ok()?
+    .captures(ron)?
+    .get(1)?
+    .as_str()
+    .parse()
+    .ok()
+}
+
+fn parse_str(ron: &str) -> Result<ClockSchematic> {
+  let mut sch: ClockSchematic = match ron::from_str(ron) {
+    Ok(sch) => sch,
+    Err(err) => {
+      // A spec for a newer generator is more likely to fail on the format
+      // than on a mistake, so say so rather than report the first field.
+      if let Some(version) = declared_version(ron) {
+        check_version(version)?;
+      }
+      return Err(parse_error(err).into());
+    }
+  };
+  check_version(sch.version)?;
+  sch.postprocess();
+  Ok(sch)
+}
+
 pub enum ClockComponent {
   Oscillator(Oscillator),
   Multiplexer(Multiplexer),
@@ -21,17 +100,32 @@ pub enum ClockComponent {
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct ClockSchematic {
+  #[serde(default = "default_version")]
+  version: u32,
   sys_clk_mux: String,
   flash_latency: FlashLatency,
+  #[serde(default)]
+  flash_acceleration: Option<FlashAcceleration>,
+  // System clock frequencies, in MHz, to precompute configurations for.
+  #[serde(default)]
+  sys_clk_presets: Vec<u32>,
   pll: Option<Pll>,
-  oscillators: HashMap<String, Oscillator>,
-  multiplexers: HashMap<String, Multiplexer>,
-  dividers: HashMap<String, Divider>,
-  multipliers: HashMap<String, Multiplier>,
-  taps: HashMap<String, Tap>,
+  oscillators: BTreeMap<String, Oscillator>,
+  multiplexers: BTreeMap<String, Multiplexer>,
+  dividers: BTreeMap<String, Divider>,
+  multipliers: BTreeMap<String, Multiplier>,
+  taps: BTreeMap<String, Tap>,
 }
 impl ClockSchematic {
   pub fn from_ron_file<P: AsRef<Path>>(path: P) -> Result<ClockSchematic> {
+    let sch = Self::parse_ron_file(path)?;
+    sch.validate()?;
+    Ok(sch)
+  }
+
+  // Parses the schematic without validating it, so `validate_standalone` can
+  // report everything that's wrong with it.
+  pub fn parse_ron_file<P: AsRef<Path>>(path: P) -> Result<ClockSchematic> {
     info!(
       "Parsing clock schematic from file '{}'",
       match path.as_ref().to_str() {
@@ -39,24 +133,25 @@ impl ClockSchematic {
         None => "(could not create string from path)",
       }
     );
-    let mut sch: ClockSchematic = ron::from_str(&fs::read_to_string(path)?)?;
-    sch.postprocess()?;
-    Ok(sch)
+    parse_str(&fs::read_to_string(path)?)
   }
 
   #[cfg(test)]
   pub fn from_ron<S: Into<String>>(ron: S) -> Result<ClockSchematic> {
-    info!("Parsing clock schematic from RON string");
-    let mut sch: ClockSchematic = ron::from_str(&ron.into())?;
-    sch.postprocess()?;
+    let sch = Self::parse_ron(ron)?;
+    sch.validate()?;
     Ok(sch)
   }
 
-  fn postprocess(&mut self) -> Result<()> {
+  #[cfg(test)]
+  pub fn parse_ron<S: Into<String>>(ron: S) -> Result<ClockSchematic> {
+    info!("Parsing clock schematic from RON string");
+    parse_str(&ron.into())
+  }
+
+  fn postprocess(&mut self) {
     self.set_names();
     self.flag_sys_clk_mux();
-    self.validate()?;
-    Ok(())
   }
 
   fn set_names(&mut self) {
@@ -103,16 +198,23 @@ impl ClockSchematic {
   }
 
   fn validate(&self) -> Result<()> {
-    self.check_valid_names()?;
-    self.check_no_duplicate_names()?;
-    self.check_all_inputs_exist()?;
-    self.check_all_outputs_are_used()?;
-    self.check_multiplexer_defaults_exist()?;
-    self.check_divider_defaults_exist()?;
-    self.check_multiplier_defaults_exist()?;
-    self.check_no_loops()?;
+    combine_errors(self.validate_standalone())
+  }
 
-    Ok(())
+  // Runs every check that doesn't need the device's SVD, returning all the
+  // problems found instead of stopping at the first one.
+  pub fn validate_standalone(&self) -> Vec<Error> {
+    let mut errors = Vec::new();
+    errors.extend(self.check_valid_names());
+    errors.extend(self.check_no_duplicate_names());
+    errors.extend(self.check_all_inputs_exist());
+    errors.extend(self.check_all_outputs_are_used());
+    errors.extend(self.check_multiplexer_defaults_exist());
+    errors.extend(self.check_divider_defaults_exist());
+    errors.extend(self.check_multiplier_defaults_exist());
+    errors.extend(self.check_multiplier_conditions_exist());
+    errors.extend(self.check_no_loops());
+    errors
   }
 
   pub fn pll(&self) -> Option<&Pll> {
@@ -136,6 +238,14 @@ impl ClockSchematic {
     &self.flash_latency
   }
 
+  pub fn flash_acceleration(&self) -> Option<&FlashAcceleration> {
+    self.flash_acceleration.as_ref()
+  }
+
+  pub fn sys_clk_presets(&self) -> &[u32] {
+    &self.sys_clk_presets
+  }
+
   pub fn oscillators(&self) -> Values<String, Oscillator> {
     self.oscillators.values()
   }
@@ -253,7 +363,7 @@ impl ClockSchematic {
     next
   }
 
-  fn list_outputs(&self, selection: ClockOutputNameSelection) -> Vec<String> {
+  pub fn list_outputs(&self, selection: ClockOutputNameSelection) -> Vec<String> {
     let terminal_taps_only = self
       .taps
       .values()
@@ -301,44 +411,44 @@ impl ClockSchematic {
     inputs
   }
 
-  fn check_valid_names(&self) -> Result<()> {
+  fn check_valid_names(&self) -> Vec<Error> {
     let allowed_chars: &'static str = "abcdefghijklmnopqrstuvwxyz0123456789_";
 
     let mut names = self.list_inputs();
     names.append(&mut self.list_outputs(ClockOutputNameSelection::Everything));
+    names.sort();
+    names.dedup();
 
-    for name in names.iter() {
-      for ch in name.to_lowercase().chars() {
-        if !allowed_chars.contains(ch) {
-          bail!("Name '{}' contains invalid character: '{}'", name, ch);
-        }
-      }
-    }
-
-    Ok(())
+    names
+      .iter()
+      .filter_map(|name| {
+        name
+          .to_lowercase()
+          .chars()
+          .find(|ch| !allowed_chars.contains(*ch))
+          .map(|ch| anyhow!("Name '{}' contains invalid character: '{}'", name, ch))
+      })
+      .collect()
   }
 
-  fn check_no_duplicate_names(&self) -> Result<()> {
+  fn check_no_duplicate_names(&self) -> Vec<Error> {
     let mut names = self.list_outputs(ClockOutputNameSelection::Everything);
     names.sort();
 
-    let mut last_name: Option<String> = None;
-    for cur_name in names.iter() {
-      match last_name {
-        Some(ref ln) => {
-          if ln == cur_name {
-            bail!("Duplicate name: {}", cur_name);
-          }
-        }
-        None => {}
-      };
-      last_name = Some(cur_name.clone());
-    }
+    let mut duplicates = names
+      .windows(2)
+      .filter(|pair| pair[0] == pair[1])
+      .map(|pair| pair[0].clone())
+      .collect::<Vec<String>>();
+    duplicates.dedup();
 
-    Ok(())
+    duplicates
+      .iter()
+      .map(|name| anyhow!("Duplicate name: {}", name))
+      .collect()
   }
 
-  fn check_all_inputs_exist(&self) -> Result<()> {
+  fn check_all_inputs_exist(&self) -> Vec<Error> {
     let inputs = self.list_inputs();
     let outputs = self.list_outputs(ClockOutputNameSelection::EverythingExceptTerminalTaps);
 
@@ -353,17 +463,16 @@ impl ClockSchematic {
       })
       .collect::<Vec<String>>();
 
-    if nonexistent_inputs.len() > 0 {
-      bail!(
+    match nonexistent_inputs.len() > 0 {
+      true => vec![anyhow!(
         "Nonexistent inputs: {} (maybe these are terminal taps?)",
         nonexistent_inputs.join(", ")
-      );
+      )],
+      false => vec![],
     }
-
-    Ok(())
   }
 
-  fn check_all_outputs_are_used(&self) -> Result<()> {
+  fn check_all_outputs_are_used(&self) -> Vec<Error> {
     let inputs = self.list_inputs();
     let outputs = self.list_outputs(ClockOutputNameSelection::EverythingExceptTerminalTaps);
 
@@ -375,17 +484,16 @@ impl ClockSchematic {
       })
       .collect::<Vec<String>>();
 
-    if unused_outputs.len() > 0 {
-      bail!(
+    match unused_outputs.len() > 0 {
+      true => vec![anyhow!(
         "Unused outputs: {} (maybe these are non-terminal taps?)",
         unused_outputs.join(", ")
-      );
+      )],
+      false => vec![],
     }
-
-    Ok(())
   }
 
-  fn check_multiplexer_defaults_exist(&self) -> Result<()> {
+  fn check_multiplexer_defaults_exist(&self) -> Vec<Error> {
     let multiplexers_with_bad_defaults = self
       .multiplexers
       .values()
@@ -393,17 +501,16 @@ impl ClockSchematic {
       .map(|m| m.name.clone())
       .collect::<Vec<String>>();
 
-    if multiplexers_with_bad_defaults.len() > 0 {
-      bail!(
+    match multiplexers_with_bad_defaults.len() > 0 {
+      true => vec![anyhow!(
         "Multiplexers have default inputs not in their input lists: {}",
         multiplexers_with_bad_defaults.join(", ")
-      );
+      )],
+      false => vec![],
     }
-
-    Ok(())
   }
 
-  fn check_divider_defaults_exist(&self) -> Result<()> {
+  fn check_divider_defaults_exist(&self) -> Vec<Error> {
     let dividers_with_bad_defaults = self
       .dividers
       .values()
@@ -414,17 +521,16 @@ impl ClockSchematic {
       .map(|d| d.name.clone())
       .collect::<Vec<String>>();
 
-    if dividers_with_bad_defaults.len() > 0 {
-      bail!(
+    match dividers_with_bad_defaults.len() > 0 {
+      true => vec![anyhow!(
         "Dividers have default values not in their value lists: {}",
         dividers_with_bad_defaults.join(", ")
-      );
+      )],
+      false => vec![],
     }
-
-    Ok(())
   }
 
-  fn check_multiplier_defaults_exist(&self) -> Result<()> {
+  fn check_multiplier_defaults_exist(&self) -> Vec<Error> {
     let multipliers_with_bad_defaults = self
       .multipliers
       .values()
@@ -435,24 +541,354 @@ impl ClockSchematic {
       .map(|m| m.name.clone())
       .collect::<Vec<String>>();
 
-    if multipliers_with_bad_defaults.len() > 0 {
-      bail!(
+    match multipliers_with_bad_defaults.len() > 0 {
+      true => vec![anyhow!(
         "Multipliers have default values not in their value lists: {}",
         multipliers_with_bad_defaults.join(", ")
-      );
+      )],
+      false => vec![],
+    }
+  }
+
+  // A conditional multiplier picks its factor by the option selected on the
+  // divider feeding it, like the timer clocks doubling when an APB prescaler
+  // divides.
+  fn check_multiplier_conditions_exist(&self) -> Vec<Error> {
+    let multipliers_with_bad_conditions = self
+      .multipliers
+      .values()
+      .filter(|m| m.is_conditional())
+      .filter(|m| match self.dividers.get(&m.input) {
+        Some(d) => m
+          .conditional_values
+          .iter()
+          .any(|c| !d.values.contains_key(&c.when)),
+        None => true,
+      })
+      .map(|m| m.name.clone())
+      .collect::<Vec<String>>();
+
+    match multipliers_with_bad_conditions.len() > 0 {
+      true => vec![anyhow!(
+        "Conditional multipliers need a divider input with an option for each condition: {}",
+        multipliers_with_bad_conditions.join(", ")
+      )],
+      false => vec![],
+    }
+  }
+
+  // Resolves the frequency at a component's output. `selections` maps
+  // configurable components to a chosen input or option name, the rest use
+  // their defaults.
+  pub fn frequency<S: Into<String>>(
+    &self,
+    name: S,
+    selections: &BTreeMap<String, String>,
+  ) -> Result<f32> {
+    let comp_name: String = name.into();
+
+    if comp_name == "off" {
+      return Ok(0f32);
+    }
+
+    if let Some(o) = self.oscillators.get(&comp_name) {
+      return Ok(o.frequency as f32);
+    }
+
+    if let Some(m) = self.multiplexers.get(&comp_name) {
+      let input = selections.get(&comp_name).unwrap_or(&m.default);
+      if !m.inputs.contains_key(input) {
+        bail!("Multiplexer {} has no input {}", comp_name, input);
+      }
+      return self.frequency(input.clone(), selections);
+    }
+
+    if let Some(d) = self.dividers.get(&comp_name) {
+      return Ok(self.frequency(d.input.clone(), selections)? / self.divisor(d, selections)?);
+    }
+
+    if let Some(m) = self.multipliers.get(&comp_name) {
+      let factor = match (m.is_conditional(), selections.get(&comp_name)) {
+        (true, _) => {
+          let input = self
+            .dividers
+            .get(&m.input)
+            .ok_or_else(|| anyhow!("Conditional multiplier {} has no divider input", comp_name))?;
+          let input_option = self.divider_option_name(input, selections)?;
+          match m.conditional_values.iter().find(|c| c.when == input_option) {
+            Some(c) => c.factor,
+            None => m.default,
+          }
+        }
+        (false, Some(option)) => match m.values.get(option) {
+          Some(v) => v.factor,
+          None => bail!("Multiplier {} has no option {}", comp_name, option),
+        },
+        (false, None) => m.default,
+      };
+      return Ok(self.frequency(m.input.clone(), selections)? * factor);
+    }
+
+    if let Some(t) = self.taps.get(&comp_name) {
+      return self.frequency(t.input.clone(), selections);
+    }
+
+    Err(anyhow!("No clock component named {}", comp_name))
+  }
+
+  // Whether a component's default selections lead to a multiplexer switched
+  // `off`, which is how a clock that's disabled out of reset is written.
+  pub fn defaults_to_off<S: Into<String>>(&self, name: S) -> bool {
+    let comp_name: String = name.into();
+
+    if comp_name == "off" {
+      return true;
+    }
+    if let Some(m) = self.multiplexers.get(&comp_name) {
+      return self.defaults_to_off(m.default.clone());
+    }
+    if let Some(d) = self.dividers.get(&comp_name) {
+      return self.defaults_to_off(d.input.clone());
+    }
+    if let Some(m) = self.multipliers.get(&comp_name) {
+      return self.defaults_to_off(m.input.clone());
+    }
+    if let Some(t) = self.taps.get(&comp_name) {
+      return self.defaults_to_off(t.input.clone());
+    }
+    false
+  }
+
+  // Works out the selections that bring the system clock closest to `target`
+  // Hz, for configurations computed at generation time. The components feeding
+  // the system clock multiplexer are settled nearest the oscillators first, and
+  // a setting that puts a tap along the way (like a PLL's input or output)
+  // outside its range isn't taken further. Of the complete combinations, the
+  // closest with the fewest changes from the defaults is kept, as long as no
+  // tap is out of range once the dividers after the system clock are raised as
+  // needed.
+  pub fn solve_sys_clk(&self, target: f32) -> Result<BTreeMap<String, String>> {
+    let mut upstream = Vec::new();
+    self.collect_configurables(&self.sys_clk_mux, &mut upstream);
+    upstream.reverse();
+
+    // Each tap that's settled by the components feeding the system clock goes
+    // with the last of them it depends on.
+    let mut stage_taps: Vec<Vec<&Tap>> = vec![Vec::new(); upstream.len()];
+    for tap in self.taps.values().filter(|t| t.min > 0 || t.max > 0) {
+      let mut depends_on = Vec::new();
+      self.collect_configurables(&tap.input, &mut depends_on);
+      let stages = depends_on
+        .iter()
+        .map(|(name, _)| upstream.iter().position(|(n, _)| n == name))
+        .collect::<Option<Vec<usize>>>();
+      if let Some(stage) = stages.and_then(|s| s.into_iter().max()) {
+        stage_taps[stage].push(tap);
+      }
+    }
+
+    let fixed = upstream
+      .iter()
+      .map(|(name, _)| name.as_str())
+      .collect::<Vec<&str>>();
+    let stages = upstream
+      .iter()
+      .zip(stage_taps.into_iter())
+      .map(|((name, options), taps)| (name.as_str(), options.as_slice(), taps))
+      .collect::<Vec<(&str, &[String], Vec<&Tap>)>>();
+    let mut best = None;
+    self.search_sys_clk(target, &stages, &fixed, &mut BTreeMap::new(), 0, &mut best)?;
+
+    match best {
+      Some((_, _, selections)) => Ok(selections),
+      None => Err(anyhow!(
+        "No configuration of {} keeps every clock within its range",
+        self.sys_clk_mux
+      )),
     }
+  }
+
+  // Tries each option of the first stage in turn, default first, going on to
+  // the rest of the stages for those that keep its taps in range. `best` holds
+  // the error, the number of changes and the selections of the best complete
+  // combination so far.
+  fn search_sys_clk(
+    &self,
+    target: f32,
+    stages: &[(&str, &[String], Vec<&Tap>)],
+    fixed: &[&str],
+    selections: &mut BTreeMap<String, String>,
+    changes: usize,
+    best: &mut Option<(f32, usize, BTreeMap<String, String>)>,
+  ) -> Result<()> {
+    let ((name, options, taps), rest) = match stages.split_first() {
+      Some(stage) => stage,
+      None => {
+        let freq = self.frequency(self.sys_clk_mux.clone(), selections)?;
+        let error = (freq - target).abs();
+        let better = match best {
+          Some((e, c, _)) => error < *e || (error == *e && changes < *c),
+          None => true,
+        };
+        if freq > 0f32 && better {
+          if let Some(fitted) = self.fit_taps(selections.clone(), fixed)? {
+            *best = Some((error, changes, fitted));
+          }
+        }
+        return Ok(());
+      }
+    };
+
+    for (i, option) in options.iter().enumerate() {
+      selections.insert(name.to_string(), option.clone());
+      let mut in_range = true;
+      for tap in taps.iter() {
+        if !tap.allows(self.frequency(tap.name.clone(), selections)?) {
+          in_range = false;
+          break;
+        }
+      }
+      if in_range {
+        let changes = if i > 0 { changes + 1 } else { changes };
+        self.search_sys_clk(target, rest, fixed, selections, changes, best)?;
+      }
+    }
+    selections.remove(*name);
 
     Ok(())
   }
 
+  // The configurable multiplexers, dividers and multipliers that a component's
+  // frequency depends on, with the names of their options, the default first.
+  fn collect_configurables(&self, name: &str, found: &mut Vec<(String, Vec<String>)>) {
+    if found.iter().any(|(n, _)| n == name) {
+      return;
+    }
+
+    if let Some(m) = self.multiplexers.get(name) {
+      let inputs = default_first(m.inputs.keys(), &m.default);
+      found.push((name.to_owned(), inputs.clone()));
+      for input in inputs.iter() {
+        self.collect_configurables(input, found);
+      }
+    } else if let Some(d) = self.dividers.get(name) {
+      if let Ok(default) = d.default_input() {
+        found.push((
+          name.to_owned(),
+          default_first(d.values.keys(), &default.name),
+        ));
+      }
+      self.collect_configurables(&d.input, found);
+    } else if let Some(m) = self.multipliers.get(name) {
+      if let (false, Ok(default)) = (m.is_conditional(), m.default_input()) {
+        found.push((
+          name.to_owned(),
+          default_first(m.values.keys(), &default.name),
+        ));
+      }
+      self.collect_configurables(&m.input, found);
+    } else if let Some(t) = self.taps.get(name) {
+      self.collect_configurables(&t.input, found);
+    }
+  }
+
+  // Raises the divisors between the system clock and any tap above its
+  // maximum, the divider closest to the tap first. Gives up when a tap is too
+  // fast with nothing left to divide it down that isn't in `fixed`.
+  fn fit_taps(
+    &self,
+    mut selections: BTreeMap<String, String>,
+    fixed: &[&str],
+  ) -> Result<Option<BTreeMap<String, String>>> {
+    loop {
+      let mut too_fast = None;
+      for tap in self.taps.values().filter(|t| t.max > 0) {
+        if self.frequency(tap.name.clone(), &selections)? > tap.max as f32 {
+          too_fast = Some(tap);
+          break;
+        }
+      }
+      let tap = match too_fast {
+        Some(t) => t,
+        None => {
+          // Raising a divisor can't bring a tap back up to its minimum.
+          for tap in self.taps.values().filter(|t| t.min > 0) {
+            if !tap.allows(self.frequency(tap.name.clone(), &selections)?) {
+              return Ok(None);
+            }
+          }
+          return Ok(Some(selections));
+        }
+      };
+
+      let mut name = tap.input.clone();
+      let raised = loop {
+        if let Some(m) = self.multiplexers.get(&name) {
+          name = selections.get(&name).unwrap_or(&m.default).clone();
+        } else if let Some(d) = self.dividers.get(&name) {
+          if !d.is_fixed() && !fixed.contains(&name.as_str()) {
+            let divisor = self.divisor(d, &selections)?;
+            let next = d
+              .values
+              .values()
+              .filter(|v| v.divisor > divisor)
+              .min_by(|a, b| {
+                a.divisor
+                  .partial_cmp(&b.divisor)
+                  .unwrap_or(std::cmp::Ordering::Equal)
+              });
+            if let Some(next) = next {
+              selections.insert(d.name.clone(), next.name.clone());
+              break true;
+            }
+          }
+          name = d.input.clone();
+        } else if let Some(m) = self.multipliers.get(&name) {
+          name = m.input.clone();
+        } else if let Some(t) = self.taps.get(&name) {
+          name = t.input.clone();
+        } else {
+          break false;
+        }
+      };
+
+      if !raised {
+        return Ok(None);
+      }
+    }
+  }
+
+  fn divisor(&self, divider: &Divider, selections: &BTreeMap<String, String>) -> Result<f32> {
+    match selections.get(&divider.name) {
+      Some(option) => match divider.values.get(option) {
+        Some(v) => Ok(v.divisor),
+        None => bail!("Divider {} has no option {}", divider.name, option),
+      },
+      None => Ok(divider.default),
+    }
+  }
+
+  fn divider_option_name(
+    &self,
+    divider: &Divider,
+    selections: &BTreeMap<String, String>,
+  ) -> Result<String> {
+    match selections.get(&divider.name) {
+      Some(option) => Ok(option.clone()),
+      None => Ok(divider.default_input()?.name.clone()),
+    }
+  }
+
   pub fn get_paths(&self) -> Vec<Vec<String>> {
-    const MAX_DEPTH: usize = 32;
+    // A path without a loop can't be longer than the number of components, and
+    // one with a loop repeats a component by then, so this is always deep
+    // enough while still stopping if there are loops.
+    let max_depth = self.component_count();
 
     // Each oscillator is the start of a path.
     let mut paths: Vec<Vec<String>> = vec![self.oscillators.keys().map(|n| n.clone()).collect()];
 
-    // Loop to a pre-determined depth so this doesn't run forever if there are loops.
-    for _ in 0..MAX_DEPTH {
+    for _ in 0..max_depth {
       // Add a step to each existing and replace `paths` with these new ones.
       // If the paths fork, the the number of items in `paths` will increase
       // because we make unique non-forking copies of each possible path.
@@ -460,12 +896,36 @@ impl ClockSchematic {
       for path in paths.iter() {
         new_paths.extend(self.make_paths(path));
       }
+
+      // Every path has reached its end.
+      if new_paths == paths {
+        return paths;
+      }
       paths = new_paths;
     }
 
+    // Paths with loops keep growing, but one without a loop that's still
+    // growing has been cut short.
+    if paths.iter().any(|p| {
+      p.last().map_or(false, |l| !self.get_next(l).is_empty()) && Self::find_loop(p).is_none()
+    }) {
+      warn!(
+        "Clock paths were cut off at a depth of {}, loops may have been missed",
+        max_depth
+      );
+    }
+
     paths
   }
 
+  fn component_count(&self) -> usize {
+    self.oscillators.len()
+      + self.multiplexers.len()
+      + self.dividers.len()
+      + self.multipliers.len()
+      + self.taps.len()
+  }
+
   fn make_paths(&self, path: &Vec<String>) -> Vec<Vec<String>> {
     match path.iter().last() {
       Some(l) => {
@@ -496,7 +956,7 @@ impl ClockSchematic {
     }
   }
 
-  fn check_no_loops(&self) -> Result<()> {
+  fn check_no_loops(&self) -> Vec<Error> {
     // Look for loops inside all the paths.
     let mut loops: Vec<Vec<String>> = Vec::new();
     for path in self.get_paths().iter() {
@@ -516,13 +976,13 @@ impl ClockSchematic {
     loop_descriptions.sort();
     loop_descriptions.dedup();
 
-    // Throw an error if any paths were found.
+    // Report an error if any loops were found.
     match loop_descriptions.len() > 0 {
-      true => Err(anyhow!(
+      true => vec![anyhow!(
         "Loop(s) detected: {}",
         loop_descriptions.join(", ")
-      )),
-      false => Ok(()),
+      )],
+      false => vec![],
     }
   }
 
@@ -555,10 +1015,16 @@ impl ClockSchematic {
   }
 }
 
+fn default_first<'a, I: Iterator<Item = &'a String>>(options: I, default: &str) -> Vec<String> {
+  let mut options = options.cloned().collect::<Vec<String>>();
+  options.sort_by_key(|o| o != default);
+  options
+}
+
 #[derive(Deserialize, Debug, Clone)]
 pub struct FlashLatency {
   pub path: String,
-  pub ranges: HashMap<String, FlashLatencyRange>,
+  pub ranges: BTreeMap<String, FlashLatencyRange>,
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -570,6 +1036,26 @@ pub struct FlashLatencyRange {
   pub bit_value: u32,
 }
 
+// The flash accelerator's enable bits, set along with the wait states when the
+// clocks are applied.
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct FlashAcceleration {
+  #[serde(default)]
+  pub prefetch: Option<String>,
+  #[serde(default)]
+  pub instruction_cache: Option<String>,
+  #[serde(default)]
+  pub data_cache: Option<String>,
+}
+impl FlashAcceleration {
+  pub fn paths(&self) -> Vec<&String> {
+    vec![&self.prefetch, &self.instruction_cache, &self.data_cache]
+      .into_iter()
+      .flatten()
+      .collect()
+  }
+}
+
 #[derive(Deserialize, Debug, Clone)]
 pub struct Pll {
   pub power: String,
@@ -590,7 +1076,7 @@ impl Oscillator {}
 pub struct Multiplexer {
   #[serde(default)]
   pub name: String,
-  pub inputs: HashMap<String, MultiplexerInput>,
+  pub inputs: BTreeMap<String, MultiplexerInput>,
   pub default: String,
   pub path: String,
   #[serde(default)]
@@ -635,7 +1121,7 @@ pub struct Divider {
   pub input: String,
   pub default: f32,
   #[serde(default)]
-  pub values: HashMap<String, DividerOption>,
+  pub values: BTreeMap<String, DividerOption>,
   #[serde(default)]
   pub path: String,
 }
@@ -667,7 +1153,7 @@ pub struct Multiplier {
   pub input: String,
   pub default: f32,
   #[serde(default)]
-  pub values: HashMap<String, MultiplierOption>,
+  pub values: BTreeMap<String, MultiplierOption>,
   #[serde(default)]
   pub conditional_values: Vec<ConditionalMultiplierOption>,
   #[serde(default)]
@@ -709,9 +1195,17 @@ pub struct Tap {
   #[serde(default)]
   pub name: String,
   pub input: String,
+  #[serde(default)]
+  pub min: u64,
   pub max: u64,
   pub terminal: bool,
 }
+impl Tap {
+  // Whether a frequency is within the tap's range, where a limit of 0 is none.
+  pub fn allows(&self, freq: f32) -> bool {
+    (self.min == 0 || freq >= self.min as f32) && (self.max == 0 || freq <= self.max as f32)
+  }
+}
 
 #[cfg(test)]
 mod tests {
@@ -781,6 +1275,37 @@ mod tests {
       )
     "#;
 
+  #[test]
+  fn checks_spec_version() {
+    let versioned = |version: &str| {
+      BASIC_RON.replacen(
+        "ClockSchematic(",
+        &format!("ClockSchematic(\n        version: {},", version),
+        1,
+      )
+    };
+
+    assert_eq!(1, ClockSchematic::from_ron(BASIC_RON).unwrap().version);
+    assert_eq!(1, ClockSchematic::from_ron(versioned("1")).unwrap().version);
+
+    let err = ClockSchematic::from_ron(versioned("2")).unwrap_err();
+    assert!(err
+      .to_string()
+      .contains("the spec is version 2, but this generator only reads versions 1 to 1"));
+
+    // Reported ahead of fields the newer format changed.
+    let err = ClockSchematic::from_ron(versioned("2").replacen(
+      "frequency: 8000000",
+      "frequency: (hz: 8000000)",
+      1,
+    ))
+    .unwrap_err();
+    assert!(err.to_string().contains("the spec is version 2"));
+
+    let err = ClockSchematic::from_ron(versioned("0")).unwrap_err();
+    assert!(err.to_string().contains("the spec is version 0"));
+  }
+
   #[test]
   fn deserializes_spec_string() {
     let spec = ClockSchematic::from_ron(BASIC_RON).unwrap();
@@ -955,7 +1480,7 @@ mod tests {
       r#"
       ClockSchematic(
         oscillators: {
-          "Hse": (
+          "Hse ": (
             frequency: 8000000
           )
         },
@@ -1022,6 +1547,11 @@ mod tests {
         dividers: {},
         multipliers: {},
         taps: {
+          "Tap0": (
+            input: "Hse",
+            max: 0,
+            terminal: true
+          ),
           "Tap1": (
             input: "Bogus1",
             max: 0,
@@ -1344,6 +1874,42 @@ mod tests {
     );
   }
 
+  #[test]
+  fn follows_paths_longer_than_fixed_depth() {
+    let dividers = (0..40)
+      .map(|i| {
+        let input = match i {
+          0 => "Hse".to_owned(),
+          _ => format!("Div{}", i - 1),
+        };
+        format!(
+          r#""Div{}": (input: "{}", default: 1, path: "path", values: {{ "no_div": (divisor: 1, bit_value: 0) }}),"#,
+          i, input
+        )
+      })
+      .collect::<Vec<String>>()
+      .join("\n");
+
+    let spec = ClockSchematic::parse_ron(format!(
+      r#"
+      ClockSchematic(
+        oscillators: {{ "Hse": (frequency: 8000000) }},
+        multiplexers: {{}},
+        dividers: {{ {} }},
+        multipliers: {{}},
+        taps: {{ "Tap1": (input: "Div39", max: 0, terminal: true) }}
+      )
+    "#,
+      dividers
+    ))
+    .unwrap();
+
+    let paths = spec.get_paths();
+    assert_eq!(1, paths.len());
+    assert_eq!(42, paths[0].len());
+    assert_eq!("Tap1", paths[0].last().unwrap());
+  }
+
   #[test]
   fn rejects_loops() {
     let res = ClockSchematic::from_ron(
@@ -1424,4 +1990,95 @@ mod tests {
       res.unwrap_err().to_string()
     );
   }
+
+  #[test]
+  fn doubles_timer_clock_when_apb_prescaler_divides() {
+    let sch = ClockSchematic::from_ron_file("specs/clock/stm32f303.ron").unwrap();
+
+    let mut selections = BTreeMap::new();
+    selections.insert("apb1_prescaler".to_owned(), "div_2".to_owned());
+    let p_clk1 = sch.frequency("p_clk1", &selections).unwrap();
+    assert_eq!(4_000_000f32, p_clk1);
+    assert_eq!(
+      2f32 * p_clk1,
+      sch.frequency("to_tim2", &selections).unwrap()
+    );
+    assert_eq!(
+      2f32 * p_clk1,
+      sch.frequency("to_tim6", &selections).unwrap()
+    );
+
+    selections.insert("apb1_prescaler".to_owned(), "no_div".to_owned());
+    let p_clk1 = sch.frequency("p_clk1", &selections).unwrap();
+    assert_eq!(p_clk1, sch.frequency("to_tim2", &selections).unwrap());
+  }
+
+  #[test]
+  fn rejects_multiplier_conditions_not_in_input_divider() {
+    let ron = BASIC_RON.replace(
+      r#"default: 2,"#,
+      r#"default: 2, conditional_values: [ ( factor: 1, when: "div_3" ) ],"#,
+    );
+
+    assert!(ClockSchematic::from_ron(ron).is_err());
+  }
+
+  #[test]
+  fn reports_every_standalone_problem() {
+    let ron = BASIC_RON
+      .replace(r#"default: 1,"#, r#"default: 3,"#)
+      .replace(r#"default: 2,"#, r#"default: 5,"#);
+
+    let errors = ClockSchematic::parse_ron(ron)
+      .unwrap()
+      .validate_standalone();
+
+    assert_eq!(2, errors.len());
+    assert!(errors[0]
+      .to_string()
+      .starts_with("Dividers have default values"));
+    assert!(errors[1]
+      .to_string()
+      .starts_with("Multipliers have default values"));
+  }
+
+  #[test]
+  fn lists_every_problem_in_one_error() {
+    let ron = BASIC_RON
+      .replace(
+        "\"hse\": (\n            frequency",
+        "\"Hs e\": (\n            frequency",
+      )
+      .replace(r#"default: 2,"#, r#"default: 5,"#);
+
+    let res = ClockSchematic::from_ron(ron);
+
+    assert!(res.is_err());
+    assert_eq!(
+      "4 problems in clock spec:\n  \
+       Name 'Hs e' contains invalid character: ' '\n  \
+       Nonexistent inputs: hse (maybe these are terminal taps?)\n  \
+       Unused outputs: Hs e (maybe these are non-terminal taps?)\n  \
+       Multipliers have default values not in their value lists: pll_mul",
+      res.unwrap_err().to_string()
+    );
+  }
+
+  #[test]
+  fn keeps_sys_clk_solutions_within_tap_ranges() {
+    let ron = fs::read_to_string("specs/clock/stm32f303.ron").unwrap();
+
+    let spec = ClockSchematic::from_ron(ron.clone()).unwrap();
+    let selections = spec.solve_sys_clk(64_000_000f32).unwrap();
+    assert_eq!("pll_mul", selections["system_clock_mux"]);
+    assert_eq!("hsi_pll", selections["pll_source_mux"]);
+    assert_eq!("mul_16", selections["pll_mul"]);
+
+    // HSI / 2 is below this PLL input minimum, so HSE is the only way there.
+    let spec = ClockSchematic::from_ron(ron.replace("min: 1000000", "min: 5000000")).unwrap();
+    let selections = spec.solve_sys_clk(64_000_000f32).unwrap();
+    assert_eq!("hse_pll", selections["pll_source_mux"]);
+    assert_eq!("no_div", selections["hse_pll"]);
+    assert_eq!("mul_8", selections["pll_mul"]);
+  }
 }
@@ -0,0 +1,141 @@
+use std::{
+  collections::BTreeMap,
+  env, fs,
+  path::{Path, PathBuf},
+  process::Command,
+};
+
+use glob::glob;
+use svd_expander::DeviceSpec;
+
+use crate::{file::OutputDirectory, fixup::Fixups};
+
+const TARGET: &str = "thumbv7m-none-eabi";
+
+#[test]
+fn generated_crates_pass_cargo_check() {
+  let out_root = env::temp_dir().join(format!("stm32-api-generator-{}", std::process::id()));
+  let out_dir = OutputDirectory::new(&out_root.to_string_lossy()).unwrap();
+
+  let mut failures = vec![];
+
+  for entry in glob("specs/svd/*.svd*").unwrap() {
+    let svd_path = entry.unwrap();
+    let svd_name = svd_path.to_string_lossy().into_owned();
+
+    let device = match DeviceSpec::from_file(&svd_name).and_then(|d| Fixups::builtin().apply(d)) {
+      Ok(d) => d,
+      Err(err) => {
+        failures.push(f!("{svd_name}: could not parse SVD: {err:?}"));
+        continue;
+      }
+    };
+
+    let crate_dir =
+      match super::generate(false, &device, &out_dir, &super::GenerateOptions::default()) {
+        Ok(d) => d.get_path().unwrap(),
+        Err(err) => {
+          failures.push(f!("{svd_name}: could not generate crate: {err:?}"));
+          continue;
+        }
+      };
+
+    let output = Command::new("cargo")
+      .current_dir(&crate_dir)
+      .args(&["check", "--all-features", "--target", TARGET])
+      .output()
+      .unwrap();
+
+    if output.status.success() {
+      fs::remove_dir_all(&crate_dir).ok();
+    } else {
+      // Leave the crate in place so the offending file can be inspected.
+      let stderr = String::from_utf8_lossy(&output.stderr);
+      failures.push(f!(
+        "{svd_name}: generated crate at {crate_dir} failed to check:\n{stderr}"
+      ));
+    }
+  }
+
+  if failures.is_empty() {
+    fs::remove_dir_all(&out_root).ok();
+  }
+
+  assert!(
+    failures.is_empty(),
+    "{} generated crate(s) failed:\n\n{}",
+    failures.len(),
+    failures.join("\n\n")
+  );
+}
+
+// Runs the generator twice, each in its own process so nothing is shared
+// between them, and checks that the two output trees are byte for byte the
+// same.
+#[test]
+fn regenerated_crates_are_identical() {
+  let out_root = env::temp_dir().join(format!("stm32-api-generator-repro-{}", std::process::id()));
+
+  let trees = ["first", "second"]
+    .iter()
+    .map(|run| {
+      let run_dir = out_root.join(run);
+      fs::create_dir_all(&run_dir).unwrap();
+
+      let output = Command::new("cargo")
+        .args(&["run", "--quiet", "--", "--no-fix", "--no-check"])
+        .args(&["-f", "specs/svd/stm32f303.svd.patched"])
+        .args(&["-o", &run_dir.to_string_lossy()])
+        .output()
+        .unwrap();
+      assert!(
+        output.status.success(),
+        "Generator failed:\n{}",
+        String::from_utf8_lossy(&output.stderr)
+      );
+
+      read_tree(&run_dir)
+    })
+    .collect::<Vec<BTreeMap<PathBuf, Vec<u8>>>>();
+
+  let differing = trees[0]
+    .keys()
+    .chain(trees[1].keys())
+    .filter(|path| trees[0].get(*path) != trees[1].get(*path))
+    .map(|path| path.to_string_lossy().into_owned())
+    .collect::<Vec<String>>();
+
+  if differing.is_empty() {
+    fs::remove_dir_all(&out_root).ok();
+  }
+
+  assert!(
+    differing.is_empty(),
+    "Output differs between runs in {}:\n{}",
+    out_root.display(),
+    differing.join("\n")
+  );
+}
+
+// Contents of every file under `root`, keyed by path relative to it. Build
+// output is left out since it isn't generated.
+fn read_tree(root: &Path) -> BTreeMap<PathBuf, Vec<u8>> {
+  let mut files = BTreeMap::new();
+  let mut dirs = vec![root.to_path_buf()];
+
+  while let Some(dir) = dirs.pop() {
+    for entry in fs::read_dir(&dir).unwrap() {
+      let path = entry.unwrap().path();
+      if path.is_dir() {
+        if path.file_name().map_or(true, |n| n != "target") {
+          dirs.push(path);
+        }
+      } else if path.file_name().map_or(true, |n| n != "Cargo.lock") {
+        let contents = fs::read(&path).unwrap();
+        files.insert(path.strip_prefix(root).unwrap().to_path_buf(), contents);
+      }
+    }
+  }
+
+  files
+}
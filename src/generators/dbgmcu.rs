@@ -0,0 +1,99 @@
+use crate::{clear_bit, set_bit, write_val};
+use crate::{
+  file::OutputDirectory,
+  generators::{render_or_skip, ReadWrite},
+  system::{dbgmcu::Dbgmcu, SystemInfo},
+};
+use anyhow::Result;
+use askama::Template;
+use svd_expander::DeviceSpec;
+
+pub fn generate(
+  dry_run: bool,
+  sys_info: &mut SystemInfo,
+  src_dir: &OutputDirectory,
+  api_path: String,
+) -> Result<()> {
+  if let Some(ref dbgmcu) = sys_info.dbgmcu {
+    let rendered = PeripheralTemplate {
+      api_path: api_path.clone(),
+      dbgmcu,
+      d: &sys_info.device,
+    }
+    .render();
+    match render_or_skip(&dbgmcu.name, rendered)? {
+      Some(rendered) => src_dir.publish(
+        dry_run,
+        &format!("dbgmcu/{}.rs", dbgmcu.name.snake()),
+        &rendered,
+      )?,
+      None => sys_info.dbgmcu = None,
+    }
+  }
+
+  src_dir.publish(
+    dry_run,
+    &f!("dbgmcu/mod.rs"),
+    &ModTemplate { s: sys_info }.render()?,
+  )?;
+
+  Ok(())
+}
+
+#[derive(Template)]
+#[template(path = "dbgmcu/mod.rs.askama", escape = "none")]
+struct ModTemplate<'a> {
+  s: &'a SystemInfo<'a>,
+}
+
+#[derive(Template)]
+#[template(path = "dbgmcu/peripheral.rs.askama", escape = "none")]
+struct PeripheralTemplate<'a> {
+  api_path: String,
+  dbgmcu: &'a Dbgmcu,
+  d: &'a DeviceSpec,
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{fixup::load_fixed, generators::find_method};
+
+  use super::*;
+
+  #[test]
+  fn renders_timer_and_watchdog_freeze_bits() {
+    let device = load_fixed("specs/svd/stm32f303.svd.patched");
+    let peripheral = device
+      .peripherals
+      .iter()
+      .find(|p| p.name == "DBGMCU")
+      .unwrap();
+    let dbgmcu = Dbgmcu::new(&device, peripheral).unwrap().unwrap();
+
+    let names = dbgmcu
+      .freeze_fields
+      .iter()
+      .map(|f| f.name.snake())
+      .collect::<Vec<String>>();
+    assert!(names.contains(&"tim2".to_owned()));
+    assert!(names.contains(&"tim14".to_owned()));
+    assert!(names.contains(&"iwdg".to_owned()));
+    assert!(names.contains(&"wwdg".to_owned()));
+    assert!(!names.contains(&"rtc".to_owned()));
+    assert!(!dbgmcu.has_peripheral_enable_field());
+
+    let rendered = PeripheralTemplate {
+      api_path: "crate".to_owned(),
+      dbgmcu: &dbgmcu,
+      d: &device,
+    }
+    .render()
+    .unwrap();
+
+    let tim14 = find_method(&rendered, "pub fn freeze_tim14_on_halt(");
+    assert!(tim14.contains("/* Set dbgmcu.apb1_fz.dbg_timer14_stop = freeze as u32 */"));
+    let iwdg = find_method(&rendered, "pub fn freeze_iwdg_on_halt(");
+    assert!(iwdg.contains("/* Set dbgmcu.apb1_fz.dbg_iwdg_stop = freeze as u32 */"));
+    assert!(!rendered.contains("freeze_rtc"));
+  }
+}
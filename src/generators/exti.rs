@@ -0,0 +1,86 @@
+use crate::{clear_bit, set_bit};
+use crate::{
+  file::OutputDirectory,
+  generators::{render_or_skip, ReadWrite},
+  system::{exti::Exti, SystemInfo},
+};
+use anyhow::Result;
+use askama::Template;
+use svd_expander::DeviceSpec;
+
+pub fn generate(
+  dry_run: bool,
+  sys_info: &mut SystemInfo,
+  src_dir: &OutputDirectory,
+  api_path: String,
+) -> Result<()> {
+  let exti = match sys_info.exti {
+    Some(ref e) => e,
+    None => return Ok(()),
+  };
+
+  let rendered = PeripheralTemplate {
+    api_path: api_path.clone(),
+    exti,
+    d: &sys_info.device,
+  }
+  .render();
+  match render_or_skip(&exti.name, rendered)? {
+    Some(rendered) => src_dir.publish(
+      dry_run,
+      &format!("exti/{}.rs", exti.name.snake()),
+      &rendered,
+    )?,
+    None => {
+      sys_info.exti = None;
+      return Ok(());
+    }
+  }
+
+  src_dir.publish(
+    dry_run,
+    &f!("exti/mod.rs"),
+    &ModTemplate { s: sys_info }.render()?,
+  )?;
+
+  Ok(())
+}
+
+#[derive(Template)]
+#[template(path = "exti/mod.rs.askama", escape = "none")]
+struct ModTemplate<'a> {
+  s: &'a SystemInfo<'a>,
+}
+
+#[derive(Template)]
+#[template(path = "exti/peripheral.rs.askama", escape = "none")]
+struct PeripheralTemplate<'a> {
+  api_path: String,
+  exti: &'a Exti,
+  d: &'a DeviceSpec,
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::fixup::load_fixed;
+
+  use super::*;
+
+  #[test]
+  fn enables_events_on_gpio_lines() {
+    let device = load_fixed("specs/svd/stm32f303.svd.patched");
+    let sys_info = SystemInfo::new(&device).unwrap();
+    let rendered = PeripheralTemplate {
+      api_path: "crate".to_owned(),
+      exti: sys_info.exti.as_ref().unwrap(),
+      d: &device,
+    }
+    .render()
+    .unwrap();
+
+    assert!(rendered.contains("pub fn enable_event0(&mut self, edge: super::Edge)"));
+    assert!(rendered.contains("pub fn disable_event15(&mut self)"));
+    assert!(rendered.contains("/* Set exti.emr1.mr15 */"));
+    assert!(!rendered.contains("exti.imr1"));
+  }
+}
@@ -0,0 +1,89 @@
+use crate::{clear_bit, clear_flag, is_set, set_bit, wait_for_clear, write_val};
+use crate::{
+  file::OutputDirectory,
+  generators::{render_or_skip, ReadWrite},
+  system::{flash::Flash, SystemInfo},
+};
+use anyhow::Result;
+use askama::Template;
+use svd_expander::DeviceSpec;
+
+pub fn generate(
+  dry_run: bool,
+  sys_info: &mut SystemInfo,
+  src_dir: &OutputDirectory,
+  api_path: String,
+) -> Result<()> {
+  if let Some(ref flash) = sys_info.flash {
+    let rendered = PeripheralTemplate {
+      api_path: api_path.clone(),
+      flash,
+      d: &sys_info.device,
+    }
+    .render();
+    match render_or_skip(&flash.name, rendered)? {
+      Some(rendered) => src_dir.publish(
+        dry_run,
+        &format!("flash/{}.rs", flash.name.snake()),
+        &rendered,
+      )?,
+      None => sys_info.flash = None,
+    }
+  }
+
+  src_dir.publish(
+    dry_run,
+    &f!("flash/mod.rs"),
+    &ModTemplate { s: sys_info }.render()?,
+  )?;
+
+  Ok(())
+}
+
+#[derive(Template)]
+#[template(path = "flash/mod.rs.askama", escape = "none")]
+struct ModTemplate<'a> {
+  s: &'a SystemInfo<'a>,
+}
+
+#[derive(Template)]
+#[template(path = "flash/peripheral.rs.askama", escape = "none")]
+struct PeripheralTemplate<'a> {
+  api_path: String,
+  flash: &'a Flash,
+  d: &'a DeviceSpec,
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{fixup::load_fixed, generators::find_method};
+
+  use super::*;
+
+  #[test]
+  fn checks_errors_and_clears_only_eop() {
+    let device = load_fixed("specs/svd/stm32f303.svd.patched");
+    let peripheral = device
+      .peripherals
+      .iter()
+      .find(|p| p.name == "FLASH")
+      .unwrap();
+    let flash = Flash::new(peripheral).unwrap().unwrap();
+
+    let rendered = PeripheralTemplate {
+      api_path: "crate".to_owned(),
+      flash: &flash,
+      d: &device,
+    }
+    .render()
+    .unwrap();
+
+    let body = find_method(&rendered, "fn finish_operation(");
+    assert!(body.contains("wait_for_clear(0x4002200c, "));
+    assert!(body.contains(&f!(", {} /* Block until flash.sr.bsy", Flash::BUSY_MAX_LOOPS)));
+    assert!(body.contains("/* Check if flash.sr.pgerr is 1 */"));
+    assert!(body.contains("/* Check if flash.sr.wrprterr is 1 */"));
+    assert!(body.contains("/* Clear flash.sr.eop by writing 1 */"));
+    assert!(!body.contains("set_bit"));
+  }
+}
@@ -1,33 +1,47 @@
-use crate::{clear_bit, is_set, reset, set_bit, write_val};
+use crate::{clear_bit, is_set, lock_sequence, reset, set_bit, store_val, write_val};
 use crate::{file::OutputDirectory, system::SystemInfo};
-use crate::{generators::ReadWrite, system::gpio::Gpio};
+use crate::{
+  generators::{render_or_skip, ReadWrite},
+  system::gpio::{Gpio, OutputSpeeds},
+};
 use anyhow::Result;
 use askama::Template;
 use svd_expander::DeviceSpec;
 
 pub fn generate(
   dry_run: bool,
-  sys_info: &SystemInfo,
+  sys_info: &mut SystemInfo,
   src_dir: &OutputDirectory,
   api_path: String,
 ) -> Result<()> {
+  let mut skipped = vec![];
   for gpio in sys_info.gpios.iter() {
-    src_dir.publish(
-      dry_run,
-      &format!("gpio/{}.rs", gpio.name.snake()),
-      &PeripheralTemplate {
-        api_path: api_path.clone(),
-        g: &gpio,
-        d: sys_info.device,
-      }
-      .render()?,
-    )?;
+    let rendered = PeripheralTemplate {
+      api_path: api_path.clone(),
+      g: &gpio,
+      d: sys_info.device,
+    }
+    .render();
+    match render_or_skip(&gpio.name, rendered)? {
+      Some(rendered) => src_dir.publish(
+        dry_run,
+        &format!("gpio/{}.rs", gpio.name.snake()),
+        &rendered,
+      )?,
+      None => skipped.push(gpio.name.clone()),
+    }
   }
+  sys_info.gpios.retain(|g| !skipped.contains(&g.name));
+  sys_info.board.retain(|pin| !skipped.contains(&pin.port));
 
   src_dir.publish(
     dry_run,
     &f!("gpio/mod.rs"),
-    &ModTemplate { s: sys_info }.render()?,
+    &ModTemplate {
+      s: sys_info,
+      speeds: OutputSpeeds::for_device(&sys_info.device.name),
+    }
+    .render()?,
   )?;
 
   Ok(())
@@ -37,6 +51,7 @@ pub fn generate(
 #[template(path = "gpio/mod.rs.askama", escape = "none")]
 struct ModTemplate<'a> {
   s: &'a SystemInfo<'a>,
+  speeds: OutputSpeeds,
 }
 
 #[derive(Template)]
@@ -46,3 +61,131 @@ struct PeripheralTemplate<'a> {
   g: &'a Gpio,
   d: &'a DeviceSpec,
 }
+
+#[cfg(test)]
+mod tests {
+  use svd_expander::DeviceSpec;
+
+  use crate::{
+    fixup::load_fixed,
+    generators::{find_item, find_method},
+  };
+
+  use super::*;
+
+  fn render_gpio_a(device: &DeviceSpec) -> String {
+    let peripheral = device
+      .peripherals
+      .iter()
+      .find(|p| p.name == "GPIOA")
+      .unwrap();
+    let gpio = Gpio::new(device, peripheral).unwrap();
+
+    PeripheralTemplate {
+      api_path: "crate".to_owned(),
+      g: &gpio,
+      d: device,
+    }
+    .render()
+    .unwrap()
+  }
+
+  #[test]
+  fn marks_pin_mode_changes_must_use() {
+    let device = load_fixed("specs/svd/stm32f303.svd.patched");
+    let rendered = render_gpio_a(&device);
+
+    for method in &[
+      "pub fn as_input(self",
+      "pub fn as_output(",
+      "pub fn as_analog(self)",
+      "pub fn as_alt_func<AltFunc>(self",
+      "pub fn as_alt_func_push_pull<AltFunc>(self",
+      "pub fn as_alt_func_open_drain<AltFunc>(self",
+      "pub fn teardown(self)",
+      "pub fn free(self)",
+    ] {
+      let unmarked = rendered
+        .lines()
+        .zip(rendered.lines().skip(1))
+        .filter(|(_, l)| l.trim().starts_with(method))
+        .filter(|(prev, _)| prev.trim() != "#[must_use]")
+        .count();
+      assert_eq!(0, unmarked, "{} is missing #[must_use]", method);
+      assert!(rendered.contains(method));
+    }
+  }
+
+  #[test]
+  fn sets_output_type_for_alt_func_variants() {
+    let device = load_fixed("specs/svd/stm32f303.svd.patched");
+    let rendered = render_gpio_a(&device);
+
+    let body = find_method(&rendered, "pub fn as_alt_func_open_drain");
+    assert!(body.contains("self.as_alt_func(pull_dir, OutputType::OpenDrain, output_speed)"));
+
+    let body = find_method(&rendered, "pub fn as_alt_func_push_pull");
+    assert!(body.contains("self.as_alt_func(pull_dir, OutputType::PushPull, output_speed)"));
+  }
+
+  #[test]
+  fn toggles_with_a_single_bsrr_write() {
+    let device = load_fixed("specs/svd/stm32f303.svd.patched");
+    let rendered = render_gpio_a(&device);
+
+    let body = find_method(&rendered, "pub fn toggle(&mut self)");
+    assert!(body.contains("/* Store gpioa.bsrr.br0 = 1 */"));
+    assert!(body.contains("/* Store gpioa.bsrr.bs0 = 1 */"));
+    assert!(!body.contains("set_bit"));
+  }
+
+  #[test]
+  fn skips_ports_whose_template_needs_a_missing_field() {
+    let mut device = load_fixed("specs/svd/stm32f303.svd.patched");
+    device
+      .peripherals
+      .iter_mut()
+      .find(|p| p.name == "GPIOA")
+      .unwrap()
+      .registers
+      .iter_mut()
+      .find(|r| r.name == "BSRR")
+      .unwrap()
+      .fields
+      .retain(|f| f.name != "BS0");
+
+    let mut sys_info = SystemInfo::new(&device).unwrap();
+    let ports = sys_info.gpios.len();
+    generate(true, &mut sys_info, &OutputDirectory::sink(), "crate".to_owned()).unwrap();
+
+    assert_eq!(ports - 1, sys_info.gpios.len());
+    assert!(!sys_info.gpios.iter().any(|g| g.name.snake() == "gpio_a"));
+  }
+
+  #[test]
+  fn locks_configuration_through_lckr() {
+    let device = load_fixed("specs/svd/stm32f303.svd.patched");
+    let rendered = render_gpio_a(&device);
+
+    let lock = &rendered[rendered.find("impl GpioALock {").unwrap()..];
+    let body = find_method(lock, "pub fn lock_configuration(&mut self, pins: u16)").to_lowercase();
+    assert!(body.contains("lock_sequence(0x4800001c,"));
+    assert!(body.contains("/* lock with key gpioa.lckr.lckk */"));
+
+    // Pins can still be locked once the port is split.
+    let parts = find_item(&rendered, "pub struct Parts {");
+    assert!(parts.contains("pub lock: GpioALock,"));
+    let split = find_method(&rendered, "pub fn split(mut self)");
+    assert!(split.contains("lock: GpioALock { _no_construct: () },"));
+  }
+
+  #[test]
+  fn constructs_port_token_in_const_fn() {
+    let device = load_fixed("specs/svd/stm32f303.svd.patched");
+    let rendered = render_gpio_a(&device);
+
+    assert!(rendered.contains("pub const unsafe fn new() -> Self"));
+    assert!(rendered.contains("Ok(unsafe { Self::new() })"));
+    assert!(rendered.contains("pub fn enable(&mut self)"));
+  }
+}
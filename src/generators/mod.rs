@@ -1,85 +1,282 @@
-use crate::{file::OutputDirectory, system::SystemInfo};
-use anyhow::Result;
+use std::{
+  cell::RefCell,
+  collections::{BTreeMap, BTreeSet},
+  fs,
+};
+
+use crate::{
+  error::GeneratorError,
+  file::{self, OutputDirectory},
+  system::{
+    board::{BoardPins, BoardSpecLocation},
+    probe, FieldAccess, FlagClear, Name, SystemInfo,
+  },
+};
+use crate::{set_bit, write_val};
+use anyhow::{anyhow, bail, Result};
 use askama::Template;
-use heck::KebabCase;
-use svd_expander::DeviceSpec;
+use clocks::ClockSpecLocation;
+use heck::{KebabCase, SnakeCase};
+use svd_expander::{DeviceSpec, FieldSpec};
 
+pub mod afio;
+pub mod board;
 pub mod clocks;
+pub mod dbgmcu;
+pub mod exti;
+pub mod flash;
 pub mod gpio;
+pub mod pac;
+pub mod pwr;
+pub mod qspi;
+pub mod raw;
+pub mod regs;
+pub mod reset;
+pub mod rng;
+pub mod sai;
 pub mod spi;
+pub mod syscfg;
+pub mod systick;
 pub mod timer;
 
+#[cfg(all(test, feature = "compile-tests"))]
+mod compile_tests;
+
+pub fn parse_device(xml: &str) -> std::result::Result<DeviceSpec, GeneratorError> {
+  DeviceSpec::from_xml(xml).map_err(|err| GeneratorError::SvdParse {
+    message: err.to_string(),
+  })
+}
+
 pub fn generate(
   dry_run: bool,
   device_spec: &DeviceSpec,
   out_dir: &OutputDirectory,
-  as_source: bool,
+  options: &GenerateOptions,
+) -> std::result::Result<OutputDirectory, GeneratorError> {
+  take_field_error();
+  generate_crate(dry_run, device_spec, out_dir, options).map_err(|err| {
+    // A failed field access only reaches here as a render error, so the error
+    // it logged is the actual cause.
+    GeneratorError::from_anyhow(take_field_error().unwrap_or(err))
+  })
+}
+
+fn generate_crate(
+  dry_run: bool,
+  device_spec: &DeviceSpec,
+  out_dir: &OutputDirectory,
+  options: &GenerateOptions,
 ) -> Result<OutputDirectory> {
-  let sys_info = SystemInfo::new(device_spec)?;
+  let as_source = options.as_source;
+  let deps = &options.deps;
+
+  reset::configure(device_spec, options.strict_reset)?;
+  let mut sys_info = SystemInfo::new(device_spec)?;
+  sys_info.assign_board(&BoardPins::for_device(device_spec, &options.board_specs)?)?;
+  if options.validate_svd {
+    sys_info.check_address_overlaps()?;
+  }
+  if let Some(families) = &options.families {
+    sys_info.retain_families(families)?;
+  }
+
+  let clock_spec = options.clock_specs.find(device_spec)?;
+  if clock_spec.is_none() {
+    warn!(
+      "No clock spec found for device {}, skipping the clocks and the peripherals that need them.",
+      device_spec.name
+    );
+    sys_info.remove_clocked_submodules();
+  }
 
-  let (base_dir, src_dir, includes_dir, api_path) = match as_source {
+  let (base_dir, src_dir, api_path) = match as_source {
+    // `out_dir` is the `src` directory of an existing crate, which will include
+    // the API with `mod <device>_api;` in its crate root.
     true => {
-      let api_name = format!("{}_api", device_spec.name.to_kebab_case());
-      let base_dir = out_dir.to_owned();
-      let src_dir = out_dir.new_in_subdir(&format!("src/{}", api_name))?;
-      let includes_dir = out_dir.clone();
+      let api_name = module_name(device_spec);
+      let base_dir = out_dir.parent()?;
+      let src_dir = out_dir.new_in_subdir(&api_name)?;
       let api_path = format!("crate::{}", api_name);
-      (base_dir, src_dir, includes_dir, api_path)
+      (base_dir, src_dir, api_path)
     }
     false => {
-      let base_dir = out_dir.new_in_subdir(&format!("{}-api", device_spec.name.to_kebab_case()))?;
+      let base_dir = out_dir.new_in_subdir(&options.crate_name_for(device_spec))?;
       let src_dir = base_dir.new_in_subdir("src")?;
-      let includes_dir = base_dir.new_in_subdir("includes")?;
       let api_path = "crate".to_owned();
-      (base_dir, src_dir, includes_dir, api_path)
+      (base_dir, src_dir, api_path)
     }
   };
 
-  clocks::generate(dry_run, device_spec, &src_dir, api_path.clone())?;
-  gpio::generate(dry_run, &sys_info, &src_dir, api_path.clone())?;
-  timer::generate(dry_run, &sys_info, &src_dir, api_path.clone())?;
-  spi::generate(dry_run, &sys_info, &src_dir, api_path.clone())?;
+  regs::configure(match options.named_registers {
+    true => Some(api_path.clone()),
+    false => None,
+  });
 
-  let lib_template = LibTemplate {
-    as_source,
-    device: &device_spec,
-    sys: &sys_info,
+  let clock_generator = match clock_spec {
+    Some(ref clock_spec_filepath) => Some(clocks::generate(
+      dry_run,
+      clock_spec_filepath,
+      device_spec,
+      &src_dir,
+      api_path.clone(),
+    )?),
+    None => None,
   };
+  let clock_taps = match clock_generator {
+    Some(ref generator) => generator.tap_names(),
+    None => vec![],
+  };
+
+  sys_info.assign_bus_clocks(&clock_taps);
 
-  includes_dir.publish(dry_run, "memory.x", &IncludeMemoryXTemplate {}.render()?)?;
-  includes_dir.publish(
+  let has_systick = clock_taps.iter().any(|t| t == systick::CLOCK_TAP);
+  if has_systick {
+    systick::generate(
+      dry_run,
+      &src_dir,
+      api_path.clone(),
+      clock_generator
+        .as_ref()
+        .and_then(|g| g.divided_input(systick::CLOCK_TAP)),
+    )?;
+  } else {
+    info!(
+      "Skipping SysTick, the clocks have no {} output.",
+      systick::CLOCK_TAP
+    );
+  }
+  gpio::generate(dry_run, &mut sys_info, &src_dir, api_path.clone())?;
+  board::generate(dry_run, &sys_info, &src_dir, api_path.clone())?;
+  afio::generate(dry_run, &mut sys_info, &src_dir, api_path.clone())?;
+  syscfg::generate(dry_run, &mut sys_info, &src_dir, api_path.clone())?;
+  exti::generate(dry_run, &mut sys_info, &src_dir, api_path.clone())?;
+  timer::generate(
     dry_run,
-    "openocd.cfg",
-    &IncludeOpenOcdCfgTemplate {}.render()?,
+    &mut sys_info,
+    &src_dir,
+    api_path.clone(),
+    options.async_api,
   )?;
-  includes_dir.publish(
+  spi::generate(
     dry_run,
-    "openocd.gdb",
-    &IncludeOpenOcdGdbTemplate {}.render()?,
+    &mut sys_info,
+    &src_dir,
+    api_path.clone(),
+    deps.embedded_hal_0_2(),
   )?;
-  includes_dir.publish(dry_run, "build.rs", &IncludeBuildRsTemplate {}.render()?)?;
+  sai::generate(dry_run, &mut sys_info, &src_dir, api_path.clone())?;
+  flash::generate(dry_run, &mut sys_info, &src_dir, api_path.clone())?;
+  qspi::generate(dry_run, &mut sys_info, &src_dir, api_path.clone())?;
+  pwr::generate(dry_run, &mut sys_info, &src_dir, api_path.clone())?;
+  dbgmcu::generate(dry_run, &mut sys_info, &src_dir, api_path.clone())?;
+  rng::generate(dry_run, &mut sys_info, &src_dir, api_path.clone())?;
+  raw::generate(dry_run, device_spec, &src_dir)?;
+  pac::generate(dry_run, &sys_info, &src_dir)?;
 
-  if !as_source {
-    includes_dir.publish(
-      dry_run,
-      "Cargo.toml",
-      &IncludeCargoTomlTemplate {}.render()?,
-    )?;
+  let has_user_code = src_dir.contains(&format!("{}/mod.rs", file::USER_DIR));
+  if has_user_code {
+    info!("Including the hand-written {} module.", file::USER_DIR);
   }
 
+  let lib_template = LibTemplate {
+    as_source,
+    allow_warnings: options.allow_warnings,
+    has_user_code,
+    has_clocks: clock_spec.is_some(),
+    has_systick,
+    wait_strategy: &options.wait_strategy,
+    async_api: options.async_api,
+    gpio_low_power: options.gpio_low_power,
+    device: &device_spec,
+    sys: &sys_info,
+    register_constants: regs::take_constants(),
+  };
+
   if as_source {
+    info!("Skipping linker script and debugger includes, the host crate provides its own.");
+    if options.emit_skeleton {
+      warn!("Skipping the firmware skeleton, the host crate has its own entry point.");
+    }
     src_dir.publish(dry_run, "mod.rs", &lib_template.render()?)?;
+    check_module_declared(out_dir, &module_name(device_spec));
   } else {
+    let includes_dir = base_dir.new_in_subdir("includes")?;
+    includes_dir.publish(dry_run, "memory.x", &IncludeMemoryXTemplate {}.render()?)?;
+    includes_dir.publish(
+      dry_run,
+      "openocd.cfg",
+      &IncludeOpenOcdCfgTemplate {}.render()?,
+    )?;
+    includes_dir.publish(
+      dry_run,
+      "openocd.gdb",
+      &IncludeOpenOcdGdbTemplate {}.render()?,
+    )?;
+    includes_dir.publish(dry_run, "build.rs", &IncludeBuildRsTemplate {}.render()?)?;
+
+    let chip = probe::probe_rs_chip(&device_spec.name);
+    if chip.is_none() {
+      warn!(
+        "No probe-rs chip is known for {}, set it in the Embed.toml and .cargo/config.toml includes.",
+        device_spec.name
+      );
+    }
+    let target = match &options.target {
+      Some(target) => Some(target.as_str()),
+      None => probe::rust_target(&device_spec.name),
+    };
+    includes_dir.publish(
+      dry_run,
+      "Embed.toml",
+      &IncludeEmbedTomlTemplate {
+        device_name: &device_spec.name,
+        chip,
+      }
+      .render()?,
+    )?;
+    includes_dir.publish(
+      dry_run,
+      ".cargo/config.toml",
+      &IncludeCargoConfigTemplate {
+        device_name: &device_spec.name,
+        chip,
+        target,
+      }
+      .render()?,
+    )?;
+    includes_dir.publish(
+      dry_run,
+      "Cargo.toml",
+      &IncludeCargoTomlTemplate {
+        crate_name: options.crate_name_for(device_spec),
+        deps,
+      }
+      .render()?,
+    )?;
+    if options.emit_skeleton && includes_dir.contains("src/main.rs") {
+      warn!("Keeping includes/src/main.rs, delete it to get a fresh skeleton.");
+    } else if options.emit_skeleton {
+      includes_dir.publish(
+        dry_run,
+        "src/main.rs",
+        &IncludeMainRsTemplate {
+          crate_module: options.crate_name_for(device_spec).to_snake_case(),
+          has_clocks: clock_spec.is_some(),
+        }
+        .render()?,
+      )?;
+    }
+
     src_dir.publish(dry_run, "lib.rs", &lib_template.render()?)?;
-  }
 
-  if !as_source {
     base_dir.publish(dry_run, ".rustfmt.toml", &RustFmtTemplate {}.render()?)?;
     base_dir.publish(
       dry_run,
       "Cargo.toml",
       &CargoTemplate {
-        crate_name: format!("{}-api", &device_spec.name.to_kebab_case()),
+        crate_name: options.crate_name_for(device_spec),
+        deps,
       }
       .render()?,
     )?;
@@ -88,6 +285,220 @@ pub fn generate(
   Ok(base_dir)
 }
 
+// Runs everything `generate` does on the device and its clock spec, rendering
+// every template but writing nothing, so the fields the templates look up are
+// checked too.
+pub fn check(device_spec: &DeviceSpec, options: &GenerateOptions) -> Result<()> {
+  generate(true, device_spec, &OutputDirectory::sink(), options)?;
+  Ok(())
+}
+
+pub fn crate_name(device_spec: &DeviceSpec) -> String {
+  format!("{}-api", device_spec.name.to_kebab_case())
+}
+
+// The module the API is put in with `--as-module`.
+pub fn module_name(device_spec: &DeviceSpec) -> String {
+  format!("{}_api", device_spec.name.to_snake_case())
+}
+
+// The host crate's root is never written to, so it has to declare the module
+// itself. Says how when neither `lib.rs` nor `main.rs` does yet.
+fn check_module_declared(crate_src_dir: &OutputDirectory, module: &str) {
+  let declared = ["lib.rs", "main.rs"]
+    .iter()
+    .filter_map(|root| crate_src_dir.read(root))
+    .any(|content| declares_module(&content, module));
+
+  if !declared {
+    warn!(
+      "The crate root doesn't include the API yet, add `mod {};` to its lib.rs or main.rs.",
+      module
+    );
+  }
+}
+
+fn declares_module(crate_root: &str, module: &str) -> bool {
+  let declaration = format!("mod {};", module);
+  crate_root.lines().map(|l| l.trim()).any(|l| {
+    let l = l
+      .strip_prefix("pub(crate) ")
+      .or_else(|| l.strip_prefix("pub "))
+      .unwrap_or(l);
+    l == declaration
+  })
+}
+
+// Writes a workspace manifest in `out_dir` listing the given crates, plus any
+// generated into the same directory by earlier runs.
+pub fn generate_workspace(
+  dry_run: bool,
+  out_dir: &OutputDirectory,
+  crate_names: &[String],
+) -> Result<()> {
+  let mut members: BTreeSet<String> = crate_names.iter().cloned().collect();
+  for entry in fs::read_dir(out_dir.get_path()?)? {
+    let path = entry?.path();
+    if let Some(name) = path.file_name().and_then(|n| n.to_str()) {
+      if name.ends_with("-api") && path.join("Cargo.toml").is_file() {
+        members.insert(name.to_owned());
+      }
+    }
+  }
+
+  info!(
+    "Listing {} crate(s) in the workspace manifest.",
+    members.len()
+  );
+  out_dir.publish(
+    dry_run,
+    "Cargo.toml",
+    &WorkspaceTemplate {
+      members: members.into_iter().collect(),
+    }
+    .render()?,
+  )
+}
+
+// Prints the peripherals and clock outputs that would be generated for the
+// device. Peripherals that get skipped are reported by warnings while the
+// system info is loaded.
+pub fn list(device_spec: &DeviceSpec, clock_specs: &ClockSpecLocation) -> Result<()> {
+  let sys_info = SystemInfo::new(device_spec)?;
+
+  let mut families: BTreeMap<String, Vec<String>> = BTreeMap::new();
+  for submodule in sys_info.submodules() {
+    families
+      .entry(submodule.parent_path)
+      .or_default()
+      .push(submodule.name.snake());
+  }
+
+  println!("{}", device_spec.name);
+  for (family, instances) in families.iter() {
+    println!("  {}: {}", family, instances.join(", "));
+  }
+
+  match clocks::list_terminal_taps(device_spec, clock_specs) {
+    Ok(taps) => println!("  clocks: {}", taps.join(", ")),
+    Err(err) => {
+      warn!("No clocks for device {}: {}", device_spec.name, err);
+      return Ok(());
+    }
+  }
+
+  // One `name = Hz` line per tap, easy to pick out of the output in a build
+  // script.
+  match clocks::resolved_frequencies(device_spec, clock_specs) {
+    Ok(frequencies) => {
+      for (tap, freq) in frequencies.iter() {
+        println!("    {} = {}", tap, freq);
+      }
+    }
+    Err(err) => warn!(
+      "Could not resolve the clock frequencies for device {}: {}",
+      device_spec.name, err
+    ),
+  }
+
+  Ok(())
+}
+
+#[derive(Default)]
+pub struct GenerateOptions {
+  pub as_source: bool,
+  pub deps: DependencyVersions,
+  pub strict_reset: bool,
+  pub validate_svd: bool,
+  pub named_registers: bool,
+  pub clock_specs: ClockSpecLocation,
+  pub board_specs: BoardSpecLocation,
+  pub wait_strategy: WaitStrategy,
+  pub async_api: bool,
+  pub allow_warnings: bool,
+  pub gpio_low_power: bool,
+  pub emit_skeleton: bool,
+  // Overrides for a single device, from a generation manifest.
+  pub crate_name: Option<String>,
+  pub families: Option<Vec<String>>,
+  pub target: Option<String>,
+}
+impl GenerateOptions {
+  pub fn crate_name_for(&self, device_spec: &DeviceSpec) -> String {
+    match &self.crate_name {
+      Some(name) => name.clone(),
+      None => crate_name(device_spec),
+    }
+  }
+}
+
+// What the generated `wait_for_*` helpers do between polls of the register.
+pub enum WaitStrategy {
+  Spin,
+  Nop,
+  Wfe,
+}
+impl WaitStrategy {
+  pub fn from_name(name: &str) -> Result<Self> {
+    match name {
+      "spin" => Ok(WaitStrategy::Spin),
+      "nop" => Ok(WaitStrategy::Nop),
+      "wfe" => Ok(WaitStrategy::Wfe),
+      _ => bail!("Unknown wait strategy '{}'", name),
+    }
+  }
+
+  pub fn statement(&self) -> &'static str {
+    match self {
+      WaitStrategy::Spin => "",
+      WaitStrategy::Nop => "cortex_m::asm::nop();",
+      WaitStrategy::Wfe => "cortex_m::asm::wfe();",
+    }
+  }
+
+  // Interrupts are masked inside a critical section, so the event WFE would
+  // wait for may never come.
+  pub fn critical_section_statement(&self) -> &'static str {
+    match self {
+      WaitStrategy::Wfe => WaitStrategy::Nop.statement(),
+      other => other.statement(),
+    }
+  }
+}
+impl Default for WaitStrategy {
+  fn default() -> Self {
+    WaitStrategy::Spin
+  }
+}
+
+pub struct DependencyVersions {
+  pub cortex_m: String,
+  pub cortex_m_rt: String,
+  pub embedded_hal: Option<String>,
+  pub defmt: Option<String>,
+}
+impl DependencyVersions {
+  // Whether the configured embedded-hal is a 0.2 release, whose traits are
+  // the only ones implemented so far. Requirements like `^0.2` and `=0.2.7`
+  // count too.
+  pub fn embedded_hal_0_2(&self) -> bool {
+    self.embedded_hal.as_ref().map_or(false, |v| {
+      let version = v.trim_start_matches(|c| c == '^' || c == '~' || c == '=' || c == ' ');
+      version == "0.2" || version.starts_with("0.2.")
+    })
+  }
+}
+impl Default for DependencyVersions {
+  fn default() -> Self {
+    Self {
+      cortex_m: "0.7.0".to_owned(),
+      cortex_m_rt: "0.6.8".to_owned(),
+      embedded_hal: None,
+      defmt: None,
+    }
+  }
+}
+
 #[derive(Template)]
 #[template(path = "includes/memory.x.askama", escape = "none")]
 struct IncludeMemoryXTemplate {}
@@ -100,20 +511,53 @@ struct IncludeOpenOcdCfgTemplate {}
 #[template(path = "includes/openocd.gdb.askama", escape = "none")]
 struct IncludeOpenOcdGdbTemplate {}
 
+#[derive(Template)]
+#[template(path = "includes/Embed.toml.askama", escape = "none")]
+struct IncludeEmbedTomlTemplate<'a> {
+  pub device_name: &'a str,
+  pub chip: Option<&'a str>,
+}
+
+#[derive(Template)]
+#[template(path = "includes/cargo-config.toml.askama", escape = "none")]
+struct IncludeCargoConfigTemplate<'a> {
+  pub device_name: &'a str,
+  pub chip: Option<&'a str>,
+  pub target: Option<&'a str>,
+}
+
 #[derive(Template)]
 #[template(path = "includes/build.rs.askama", escape = "none")]
 struct IncludeBuildRsTemplate {}
 
 #[derive(Template)]
 #[template(path = "includes/Cargo.toml.askama", escape = "none")]
-struct IncludeCargoTomlTemplate {}
+struct IncludeCargoTomlTemplate<'a> {
+  pub crate_name: String,
+  pub deps: &'a DependencyVersions,
+}
+
+#[derive(Template)]
+#[template(path = "includes/main.rs.askama", escape = "none")]
+struct IncludeMainRsTemplate {
+  pub crate_module: String,
+  pub has_clocks: bool,
+}
 
 #[derive(Template)]
 #[template(path = "lib.rs.askama", escape = "none")]
 struct LibTemplate<'a> {
   pub as_source: bool,
+  pub allow_warnings: bool,
+  pub has_user_code: bool,
+  pub has_clocks: bool,
+  pub has_systick: bool,
+  pub wait_strategy: &'a WaitStrategy,
+  pub async_api: bool,
+  pub gpio_low_power: bool,
   pub device: &'a DeviceSpec,
   pub sys: &'a SystemInfo<'a>,
+  pub register_constants: Vec<regs::RegisterConstant>,
 }
 
 #[derive(Template)]
@@ -122,8 +566,15 @@ struct RustFmtTemplate {}
 
 #[derive(Template)]
 #[template(path = "Cargo.toml.askama", escape = "none")]
-struct CargoTemplate {
+struct CargoTemplate<'a> {
   pub crate_name: String,
+  pub deps: &'a DependencyVersions,
+}
+
+#[derive(Template)]
+#[template(path = "Cargo.workspace.toml.askama", escape = "none")]
+struct WorkspaceTemplate {
+  pub members: Vec<String>,
 }
 
 fn itf(interrupt_free: bool) -> &'static str {
@@ -134,37 +585,68 @@ fn itf(interrupt_free: bool) -> &'static str {
 }
 
 pub trait ReadWrite {
-  fn write_val(&self, path: &str, expr: &str, interrupt_free: bool) -> String;
-  fn reset(&self, path: &str, interrupt_free: bool) -> String;
-  fn set_bit(&self, path: &str, interrupt_free: bool) -> String;
-  fn clear_bit(&self, path: &str, interrupt_free: bool) -> String;
-  fn read_val(&self, path: &str) -> String;
-  fn is_set(&self, path: &str) -> String;
-  fn is_clear(&self, path: &str) -> String;
-  fn wait_for_val(&self, path: &str, expr: &str, max_loops: u32, interrupt_free: bool) -> String;
-  fn wait_for_clear(&self, path: &str, max_loops: u32, interrupt_free: bool) -> String;
-  fn wait_for_set(&self, path: &str, max_loops: u32, interrupt_free: bool) -> String;
+  fn write_val(&self, path: &str, expr: &str, interrupt_free: bool) -> Result<String>;
+  fn reset(&self, path: &str, interrupt_free: bool) -> Result<String>;
+  fn set_bit(&self, path: &str, interrupt_free: bool) -> Result<String>;
+  fn clear_bit(&self, path: &str, interrupt_free: bool) -> Result<String>;
+  fn read_val(&self, path: &str) -> Result<String>;
+  fn is_set(&self, path: &str) -> Result<String>;
+  fn is_clear(&self, path: &str) -> Result<String>;
+  fn wait_for_val(
+    &self,
+    path: &str,
+    expr: &str,
+    max_loops: &str,
+    interrupt_free: bool,
+  ) -> Result<String>;
+  fn wait_for_clear(&self, path: &str, max_loops: &str, interrupt_free: bool) -> Result<String>;
+  fn wait_for_set(&self, path: &str, max_loops: &str, interrupt_free: bool) -> Result<String>;
+  fn clear_flag(&self, path: &str, clear: FlagClear) -> Result<String>;
+  fn store_val(&self, path: &str, expr: &str) -> Result<String>;
+  fn lock_sequence(&self, key_path: &str, expr: &str) -> Result<String>;
+  fn write_val_with(
+    &self,
+    path: &str,
+    expr: &str,
+    other_path: &str,
+    other_expr: &str,
+  ) -> Result<String>;
 }
 impl ReadWrite for DeviceSpec {
-  fn write_val(&self, path: &str, expr: &str, interrupt_free: bool) -> String {
-    let field = self.get_field(path).unwrap();
+  fn write_val(&self, path: &str, expr: &str, interrupt_free: bool) -> Result<String> {
+    let field = self
+      .get_field(path)
+      .map_err(|_| field_not_found(self, path))?;
+    check_fits(self, &field, path)?;
+    check_writable(&field, path)?;
 
-    let address = field.address();
+    let address = regs::address_expr(path, field.address());
     let mask = field.mask();
     let offset = field.offset;
     let itf = itf(interrupt_free);
 
-    f!("write_val{itf}({address:#010x}, {mask:#034b}, {offset}, {expr}) /* Set {path} = {expr} */")
+    Ok(f!("write_val{itf}({address}, {mask:#034b}, {offset}, {expr}) /* Set {path} = {expr} */"))
   }
 
-  fn reset(&self, path: &str, interrupt_free: bool) -> String {
-    let field = self.get_field(path).unwrap();
+  fn reset(&self, path: &str, interrupt_free: bool) -> Result<String> {
+    let field = self
+      .get_field(path)
+      .map_err(|_| field_not_found(self, path))?;
+    check_fits(self, &field, path)?;
+    check_writable(&field, path)?;
 
-    let address = field.address();
+    let address = regs::address_expr(path, field.address());
     let offset = field.offset;
+    let itf = itf(interrupt_free);
+
+    if let Some(reset_value) = reset::override_for(path) {
+      let mask = field.mask();
+      return Ok(f!("write_val{itf}({address}, {mask:#034b}, {offset}, {reset_value}) /* Reset {path} (overridden) */"));
+    }
 
     let reset_mask = match field.reset_mask {
       Some(rm) => rm,
+      None if reset::is_strict() => bail!("No reset mask configured for field {}", path),
       None => {
         warn!(
           "No reset mask configured for field {}, defaulting to field mask.",
@@ -176,6 +658,7 @@ impl ReadWrite for DeviceSpec {
 
     let reset_value = match field.reset_value {
       Some(rv) => rv,
+      None if reset::is_strict() => bail!("No reset value configured for field {}", path),
       None => {
         warn!(
           "No reset value configured for field {}, defaulting to 0.",
@@ -185,202 +668,1038 @@ impl ReadWrite for DeviceSpec {
       }
     };
 
-    let itf = itf(interrupt_free);
-
-    f!("write_val{itf}({address:#010x}, {reset_mask:#034b}, {offset}, {reset_value}) /* Reset {path} */")
+    Ok(f!(
+      "write_val{itf}({address}, {reset_mask:#034b}, {offset}, {reset_value}) /* Reset {path} */"
+    ))
   }
 
-  fn set_bit(&self, path: &str, interrupt_free: bool) -> String {
-    let field = self.get_field(path).unwrap();
+  fn set_bit(&self, path: &str, interrupt_free: bool) -> Result<String> {
+    let field = self
+      .get_field(path)
+      .map_err(|_| field_not_found(self, path))?;
+    check_fits(self, &field, path)?;
+    check_writable(&field, path)?;
     if field.width != 1 {
-      panic!("Cannot set single bit for a multi-bit field");
+      bail!("Cannot set single bit for multi-bit field '{}'", path);
     }
 
-    let address = field.address();
+    let address = regs::address_expr(path, field.address());
     let mask = field.mask();
     let itf = itf(interrupt_free);
 
-    f!("set_bit{itf}({address:#010x}, {mask:#034b}) /* Set {path} */")
+    Ok(f!("set_bit{itf}({address}, {mask:#034b}) /* Set {path} */"))
   }
 
-  fn clear_bit(&self, path: &str, interrupt_free: bool) -> String {
-    let field = self.get_field(path).unwrap();
+  fn clear_bit(&self, path: &str, interrupt_free: bool) -> Result<String> {
+    let field = self
+      .get_field(path)
+      .map_err(|_| field_not_found(self, path))?;
+    check_fits(self, &field, path)?;
+    check_writable(&field, path)?;
     if field.width != 1 {
-      panic!("Cannot clear single bit for a multi-bit field");
+      bail!("Cannot clear single bit for multi-bit field '{}'", path);
     }
 
     let itf = itf(interrupt_free);
-    let address = field.address();
+    let address = regs::address_expr(path, field.address());
     let mask = field.mask();
 
-    f!("clear_bit{itf}({address:#010x}, {mask:#034b}) /* Clear {path} */")
+    Ok(f!("clear_bit{itf}({address}, {mask:#034b}) /* Clear {path} */"))
   }
 
-  fn read_val(&self, path: &str) -> String {
-    let field = self.get_field(path).unwrap();
+  fn read_val(&self, path: &str) -> Result<String> {
+    let field = self
+      .get_field(path)
+      .map_err(|_| field_not_found(self, path))?;
+    check_fits(self, &field, path)?;
+    check_readable(&field, path)?;
 
-    let address = field.address();
+    let address = regs::address_expr(path, field.address());
     let mask = field.mask();
     let offset = field.offset;
 
-    f!("read_val({address:#010x}, {mask:#034b}, {offset}) /* Read {path} */")
+    Ok(f!("read_val({address}, {mask:#034b}, {offset}) /* Read {path} */"))
   }
 
-  fn is_set(&self, path: &str) -> String {
-    let field = self.get_field(path).unwrap();
+  fn is_set(&self, path: &str) -> Result<String> {
+    let field = self
+      .get_field(path)
+      .map_err(|_| field_not_found(self, path))?;
+    check_fits(self, &field, path)?;
+    check_readable(&field, path)?;
 
-    let address = field.address();
+    let address = regs::address_expr(path, field.address());
     let mask = field.mask();
 
-    f!("is_set({address:#010x}, {mask:#034b}) /* Check if {path} is 1 */")
+    Ok(f!("is_set({address}, {mask:#034b}) /* Check if {path} is 1 */"))
   }
 
-  fn is_clear(&self, path: &str) -> String {
-    let field = self.get_field(path).unwrap();
+  fn is_clear(&self, path: &str) -> Result<String> {
+    let field = self
+      .get_field(path)
+      .map_err(|_| field_not_found(self, path))?;
+    check_fits(self, &field, path)?;
+    check_readable(&field, path)?;
 
-    let address = field.address();
+    let address = regs::address_expr(path, field.address());
     let mask = field.mask();
 
-    f!("is_clear({address:#010x}, {mask:#034b}) /* Check if {path} is 0 */")
+    Ok(f!("is_clear({address}, {mask:#034b}) /* Check if {path} is 0 */"))
   }
 
-  fn wait_for_val(&self, path: &str, expr: &str, max_loops: u32, interrupt_free: bool) -> String {
-    let field = self.get_field(path).unwrap();
+  fn wait_for_val(
+    &self,
+    path: &str,
+    expr: &str,
+    max_loops: &str,
+    interrupt_free: bool,
+  ) -> Result<String> {
+    let field = self
+      .get_field(path)
+      .map_err(|_| field_not_found(self, path))?;
+    check_fits(self, &field, path)?;
+    check_readable(&field, path)?;
 
     let itf = itf(interrupt_free);
-    let address = field.address();
+    let address = regs::address_expr(path, field.address());
     let mask = field.mask();
     let offset = field.offset;
 
-    f!("wait_for_val{itf}({address:#010x}, {mask:#034b}, {offset}, {expr}, {max_loops}) /* Block until {path} == {expr} */")
+    Ok(f!("wait_for_val{itf}({address}, {mask:#034b}, {offset}, {expr}, {max_loops}) /* Block until {path} == {expr} */"))
   }
 
-  fn wait_for_clear(&self, path: &str, max_loops: u32, interrupt_free: bool) -> String {
-    let field = self.get_field(path).unwrap();
+  fn wait_for_clear(&self, path: &str, max_loops: &str, interrupt_free: bool) -> Result<String> {
+    let field = self
+      .get_field(path)
+      .map_err(|_| field_not_found(self, path))?;
+    check_fits(self, &field, path)?;
+    check_readable(&field, path)?;
 
     let itf = itf(interrupt_free);
-    let address = field.address();
+    let address = regs::address_expr(path, field.address());
     let mask = field.mask();
 
-    f!("wait_for_clear{itf}({address:#010x}, {mask:#034b}, {max_loops}) /* Block until {path} is cleared */")
+    Ok(f!("wait_for_clear{itf}({address}, {mask:#034b}, {max_loops}) /* Block until {path} is cleared */"))
   }
 
-  fn wait_for_set(&self, path: &str, max_loops: u32, interrupt_free: bool) -> String {
-    let field = self.get_field(path).unwrap();
+  fn wait_for_set(&self, path: &str, max_loops: &str, interrupt_free: bool) -> Result<String> {
+    let field = self
+      .get_field(path)
+      .map_err(|_| field_not_found(self, path))?;
+    check_fits(self, &field, path)?;
+    check_readable(&field, path)?;
 
     let itf = itf(interrupt_free);
-    let address = field.address();
+    let address = regs::address_expr(path, field.address());
+    let mask = field.mask();
+
+    Ok(f!(
+      "wait_for_set{itf}({address}, {mask:#034b}, {max_loops}) /* Block until {path} is set */"
+    ))
+  }
+
+  // A single write (or read) of the whole register, so unlike `clear_bit` it
+  // can't lose a flag that gets set between the read and the write.
+  fn clear_flag(&self, path: &str, clear: FlagClear) -> Result<String> {
+    let field = self
+      .get_field(path)
+      .map_err(|_| field_not_found(self, path))?;
+    check_fits(self, &field, path)?;
+    if field.width != 1 {
+      bail!("Cannot clear flag for multi-bit field '{}'", path);
+    }
+
+    let address = regs::address_expr(path, field.address());
+    let mask = field.mask();
+
+    match clear {
+      FlagClear::WriteZero => {
+        check_writable(&field, path)?;
+        Ok(f!(
+          "clear_flag_w0({address}, {mask:#034b}) /* Clear {path} by writing 0 */"
+        ))
+      }
+      FlagClear::WriteOne => {
+        check_writable(&field, path)?;
+        Ok(f!(
+          "clear_flag_w1({address}, {mask:#034b}) /* Clear {path} by writing 1 */"
+        ))
+      }
+      FlagClear::Read => {
+        check_readable(&field, path)?;
+        Ok(f!("clear_flag_r({address}) /* Clear {path} by reading */"))
+      }
+    }
+  }
+
+  // A single write that leaves the rest of the register 0, for registers the
+  // read in `write_val` would disturb: a data register whose read pops a FIFO,
+  // or a write-only one like GPIO BSRR.
+  fn store_val(&self, path: &str, expr: &str) -> Result<String> {
+    let field = self
+      .get_field(path)
+      .map_err(|_| field_not_found(self, path))?;
+    check_fits(self, &field, path)?;
+    check_writable(&field, path)?;
+
+    let address = regs::address_expr(path, field.address());
+    let mask = field.mask();
+    let offset = field.offset;
+
+    Ok(f!("store_val({address}, {mask:#034b}, {offset}, {expr}) /* Store {path} = {expr} */"))
+  }
+
+  fn lock_sequence(&self, key_path: &str, expr: &str) -> Result<String> {
+    let field = self
+      .get_field(key_path)
+      .map_err(|_| field_not_found(self, key_path))?;
+    check_fits(self, &field, key_path)?;
+    check_writable(&field, key_path)?;
+    check_readable(&field, key_path)?;
+    if field.width != 1 {
+      bail!("Cannot use multi-bit field '{}' as a lock key", key_path);
+    }
+
+    let address = regs::address_expr(key_path, field.address());
     let mask = field.mask();
 
-    f!("wait_for_set{itf}({address:#010x}, {mask:#034b}, {max_loops}) /* Block until {path} is set */")
+    Ok(f!(
+      "lock_sequence({address}, {mask:#034b}, {expr}) /* Lock with key {key_path} */"
+    ))
+  }
+
+  // Sets a second field of the same register in the same write, for registers
+  // with write-only fields that read back undefined, so writing `path` alone
+  // would write back garbage to them.
+  fn write_val_with(
+    &self,
+    path: &str,
+    expr: &str,
+    other_path: &str,
+    other_expr: &str,
+  ) -> Result<String> {
+    let field = self
+      .get_field(path)
+      .map_err(|_| field_not_found(self, path))?;
+    let other = self
+      .get_field(other_path)
+      .map_err(|_| field_not_found(self, other_path))?;
+    check_fits(self, &field, path)?;
+    check_fits(self, &other, other_path)?;
+    check_writable(&field, path)?;
+    check_writable(&other, other_path)?;
+    if field.address() != other.address() {
+      bail!(
+        "Cannot write '{}' and '{}' together because they're in different registers",
+        path,
+        other_path
+      );
+    }
+
+    let address = regs::address_expr(path, field.address());
+    let mask = field.mask();
+    let offset = field.offset;
+    let other_mask = other.mask();
+    let other_offset = other.offset;
+
+    Ok(f!(
+      "write_val_with_itf({address}, {mask:#034b}, {offset}, {expr}, {other_mask:#034b}, {other_offset}, {other_expr}) /* Set {path} = {expr}, {other_path} = {other_expr} */"
+    ))
+  }
+}
+
+fn check_writable(field: &FieldSpec, path: &str) -> Result<()> {
+  match FieldAccess::of(field).writable {
+    true => Ok(()),
+    false => bail!("Cannot write read-only field '{}'", path),
+  }
+}
+
+fn check_readable(field: &FieldSpec, path: &str) -> Result<()> {
+  match FieldAccess::of(field).readable {
+    true => Ok(()),
+    false => bail!("Cannot read write-only field '{}'", path),
+  }
+}
+
+// A field past the end of its register would corrupt the neighboring bits, so
+// the SVD isn't trusted on this.
+fn check_fits(device: &DeviceSpec, field: &FieldSpec, path: &str) -> Result<()> {
+  let register_path = match path.rfind('.') {
+    Some(i) => &path[..i],
+    None => path,
+  };
+  let size = device
+    .peripherals
+    .iter()
+    .flat_map(|p| p.iter_registers())
+    .find(|r| r.path().eq_ignore_ascii_case(register_path))
+    .and_then(|r| r.size)
+    .unwrap_or(32);
+
+  match field.offset + field.width <= size {
+    true => Ok(()),
+    false => bail!(
+      "Field '{}' at bits {}..{} does not fit in its {}-bit register",
+      path,
+      field.offset,
+      field.offset + field.width,
+      size
+    ),
+  }
+}
+
+fn field_not_found(device: &DeviceSpec, path: &str) -> anyhow::Error {
+  GeneratorError::FieldNotFound {
+    device: device.name.clone(),
+    path: path.to_owned(),
+  }
+  .into()
+}
+
+thread_local! {
+  static FIELD_ERROR: RefCell<Option<anyhow::Error>> = RefCell::new(None);
+}
+
+fn take_field_error() -> Option<anyhow::Error> {
+  FIELD_ERROR.with(|field_error| field_error.borrow_mut().take())
+}
+
+// Askama's error type can't carry our error message, so the first one is kept
+// here for `generate` to return, or for `render_or_skip` to skip the peripheral.
+pub fn field_access(result: Result<String>) -> askama::Result<String> {
+  result.map_err(|err| {
+    FIELD_ERROR.with(|field_error| {
+      let mut field_error = field_error.borrow_mut();
+      if field_error.is_none() {
+        *field_error = Some(err);
+      }
+    });
+    askama::Error::Fmt(std::fmt::Error)
+  })
+}
+
+// Takes a peripheral's rendered module. When the template looked up a field the
+// SVD doesn't have, the peripheral is skipped with a warning rather than failing
+// the whole crate, and `None` tells the caller to drop it from the `SystemInfo`
+// before the modules that list it are rendered.
+pub fn render_or_skip(name: &Name, rendered: askama::Result<String>) -> Result<Option<String>> {
+  if let Ok(rendered) = rendered {
+    return Ok(Some(rendered));
+  }
+
+  match take_field_error().map(GeneratorError::from_anyhow) {
+    Some(GeneratorError::FieldNotFound { path, .. }) => {
+      warn!(
+        "Skipping peripheral {} because the SVD has no field {}.",
+        name.camel(),
+        path
+      );
+      Ok(None)
+    }
+    Some(field_err) => Err(field_err.into()),
+    None => bail!("Could not generate peripheral {}", name.camel()),
   }
 }
 
 #[macro_export]
 macro_rules! write_val {
   ($device:ident, $path:expr, $val:expr) => {
-    $device.write_val(&$path, &$val.to_string(), true);
+    $crate::generators::field_access($device.write_val(&$path, &$val.to_string(), true))?;
   };
   ($device:ident, $path:expr, $val:expr, $interrupt_free:expr) => {
-    $device.write_val(&$path, &$val.to_string(), $interrupt_free);
+    $crate::generators::field_access($device.write_val(&$path, &$val.to_string(), $interrupt_free))?;
   };
 }
 
 #[macro_export]
 macro_rules! reset {
   ($device:ident, $path:expr) => {
-    $device.reset(&$path, true);
+    $crate::generators::field_access($device.reset(&$path, true))?;
   };
   ($device:ident, $path:expr, $interrupt_free:expr) => {
-    $device.reset(&$path, $interrupt_free);
+    $crate::generators::field_access($device.reset(&$path, $interrupt_free))?;
   };
 }
 
 #[macro_export]
 macro_rules! set_bit {
   ($device:ident, $path:expr) => {
-    $device.set_bit(&$path, true);
+    $crate::generators::field_access($device.set_bit(&$path, true))?;
   };
   ($device:ident, $path:expr, $interrupt_free:expr) => {
-    $device.set_bit(&$path, $interrupt_free);
+    $crate::generators::field_access($device.set_bit(&$path, $interrupt_free))?;
   };
 }
 
 #[macro_export]
 macro_rules! clear_bit {
   ($device:ident, $path:expr) => {
-    $device.clear_bit(&$path, true);
+    $crate::generators::field_access($device.clear_bit(&$path, true))?;
   };
   ($device:ident, $path:expr, $interrupt_free:expr) => {
-    $device.clear_bit(&$path, $interrupt_free);
+    $crate::generators::field_access($device.clear_bit(&$path, $interrupt_free))?;
   };
 }
 
 #[macro_export]
 macro_rules! read_val {
   ($device:ident, $path:expr) => {
-    $device.read_val(&$path);
+    $crate::generators::field_access($device.read_val(&$path))?;
   };
 }
 
 #[macro_export]
 macro_rules! is_set {
   ($device:ident, $path:expr) => {
-    $device.is_set(&$path);
+    $crate::generators::field_access($device.is_set(&$path))?;
   };
 }
 
 #[macro_export]
 macro_rules! is_clear {
   ($device:ident, $path:expr) => {
-    $device.is_clear(&$path);
+    $crate::generators::field_access($device.is_clear(&$path))?;
   };
 }
 
 #[macro_export]
 macro_rules! wait_for_val {
   ($device:ident, $path:expr, $val:expr) => {
-    $device.wait_for_val(&$path, &$val.to_string(), 1000, true);
+    $crate::generators::field_access($device.wait_for_val(&$path, &$val.to_string(), "1000", true))?;
   };
-  ($device:ident, $path:expr, $val:expr, $interrupt_free:expr) => {
-    $device.wait_for_val(&$path, &$val.to_string(), 1000, $interrupt_free);
+  // Named, since a bare loop count would be taken for `$interrupt_free`. It has
+  // to come first, because `max_loops = 500` also parses as an expression.
+  ($device:ident, $path:expr, $val:expr, max_loops = $max_loops:expr) => {
+    $crate::generators::field_access($device.wait_for_val(&$path, &$val.to_string(), &$max_loops.to_string(), true))?;
   };
-  ($device:ident, $path:expr, $val:expr, $max_loops:expr) => {
-    $device.wait_for_val(&$path, &$val.to_string(), $max_loops, true);
+  ($device:ident, $path:expr, $val:expr, $interrupt_free:expr) => {
+    $crate::generators::field_access($device.wait_for_val(&$path, &$val.to_string(), "1000", $interrupt_free))?;
   };
   ($device:ident, $path:expr, $val:expr, $max_loops:expr, $interrupt_free:expr) => {
-    $device.wait_for_val(&$path, &$val.to_string(), $max_loops, $interrupt_free);
+    $crate::generators::field_access($device.wait_for_val(&$path, &$val.to_string(), &$max_loops.to_string(), $interrupt_free))?;
   };
 }
 
 #[macro_export]
 macro_rules! wait_for_clear {
   ($device:ident, $path:expr) => {
-    $device.wait_for_clear(&$path, 1000, true);
+    $crate::generators::field_access($device.wait_for_clear(&$path, "1000", true))?;
   };
-  ($device:ident, $path:expr, $interrupt_free:expr) => {
-    $device.wait_for_clear(&$path, 1000, $interrupt_free);
+  ($device:ident, $path:expr, max_loops = $max_loops:expr) => {
+    $crate::generators::field_access($device.wait_for_clear(&$path, &$max_loops.to_string(), true))?;
   };
-  ($device:ident, $path:expr, $max_loops:expr) => {
-    $device.wait_for_clear(&$path, $max_loops, true);
+  ($device:ident, $path:expr, $interrupt_free:expr) => {
+    $crate::generators::field_access($device.wait_for_clear(&$path, "1000", $interrupt_free))?;
   };
-  ($device:ident, $path:expr, &max_loops:expr, $interrupt_free:expr) => {
-    $device.wait_for_clear(&$path, $max_loops, $interrupt_free);
+  ($device:ident, $path:expr, $max_loops:expr, $interrupt_free:expr) => {
+    $crate::generators::field_access($device.wait_for_clear(&$path, &$max_loops.to_string(), $interrupt_free))?;
   };
 }
 
 #[macro_export]
 macro_rules! wait_for_set {
   ($device:ident, $path:expr) => {
-    $device.wait_for_set(&$path, 1000, true);
+    $crate::generators::field_access($device.wait_for_set(&$path, "1000", true))?;
   };
-  ($device:ident, $path:expr, $interrupt_free:expr) => {
-    $device.wait_for_set(&$path, 1000, $interrupt_free);
+  ($device:ident, $path:expr, max_loops = $max_loops:expr) => {
+    $crate::generators::field_access($device.wait_for_set(&$path, &$max_loops.to_string(), true))?;
   };
-  ($device:ident, $path:expr, $max_loops:expr) => {
-    $device.wait_for_set(&$path, $max_loops, true);
+  ($device:ident, $path:expr, $interrupt_free:expr) => {
+    $crate::generators::field_access($device.wait_for_set(&$path, "1000", $interrupt_free))?;
   };
   ($device:ident, $path:expr, $max_loops:expr, $interrupt_free:expr) => {
-    $device.wait_for_set(&$path, $max_loops, $interrupt_free);
+    $crate::generators::field_access($device.wait_for_set(&$path, &$max_loops.to_string(), $interrupt_free))?;
   };
 }
+
+#[macro_export]
+macro_rules! lock_sequence {
+  ($device:ident, $key_path:expr, $val:expr) => {
+    $crate::generators::field_access($device.lock_sequence(&$key_path, &$val.to_string()))?;
+  };
+}
+
+#[macro_export]
+macro_rules! store_val {
+  ($device:ident, $path:expr, $val:expr) => {
+    $crate::generators::field_access($device.store_val(&$path, &$val.to_string()))?;
+  };
+}
+
+#[macro_export]
+macro_rules! write_val_with {
+  ($device:ident, $path:expr, $val:expr, $other_path:expr, $other_val:expr) => {
+    $crate::generators::field_access($device.write_val_with(
+      &$path,
+      &$val.to_string(),
+      &$other_path,
+      &$other_val.to_string(),
+    ))?;
+  };
+}
+
+#[macro_export]
+macro_rules! clear_flag {
+  ($device:ident, $flag:expr) => {
+    $crate::generators::field_access($device.clear_flag(&$flag.path, $flag.clear))?;
+  };
+}
+
+// The generated method starting at `start`, up to its closing brace, for
+// tests that check what a single method does.
+#[cfg(test)]
+pub fn find_method<'a>(rendered: &'a str, start: &str) -> &'a str {
+  find_block(rendered, start, "\n  }")
+}
+
+// Like `find_method`, for an item at the top level of the generated file.
+#[cfg(test)]
+pub fn find_item<'a>(rendered: &'a str, start: &str) -> &'a str {
+  find_block(rendered, start, "\n}")
+}
+
+#[cfg(test)]
+fn find_block<'a>(rendered: &'a str, start: &str, end: &str) -> &'a str {
+  let block = &rendered[rendered.find(start).unwrap()..];
+  &block[..block.find(end).unwrap()]
+}
+
+#[cfg(test)]
+mod tests {
+  use svd_expander::DeviceSpec;
+
+  use crate::fixup::load_fixed;
+
+  use super::*;
+  use crate::system::{Name, StatusFlag};
+
+  #[test]
+  fn writes_existing_field() {
+    let device = DeviceSpec::from_file("specs/svd/arm_device.svd").unwrap();
+
+    assert_eq!(
+      "write_val_itf(0x40010000, 0b00000000000000000000000001110000, 4, 3) /* Set timer0.cr.mode = 3 */",
+      device.write_val("timer0.cr.mode", "3", true).unwrap()
+    );
+  }
+
+  #[test]
+  fn parses_wait_strategies() {
+    assert_eq!("", WaitStrategy::from_name("spin").unwrap().statement());
+    assert_eq!(
+      "cortex_m::asm::wfe();",
+      WaitStrategy::from_name("wfe").unwrap().statement()
+    );
+    assert_eq!(
+      "cortex_m::asm::nop();",
+      WaitStrategy::from_name("wfe")
+        .unwrap()
+        .critical_section_statement()
+    );
+    assert!(WaitStrategy::from_name("sleep").is_err());
+  }
+
+  // Renders the crate root with the default options, after `overrides` has set
+  // the ones a test is about.
+  fn render_lib(
+    device: &DeviceSpec,
+    sys: &SystemInfo,
+    overrides: impl FnOnce(&mut LibTemplate),
+  ) -> String {
+    let mut template = LibTemplate {
+      as_source: false,
+      allow_warnings: true,
+      has_user_code: false,
+      has_clocks: false,
+      has_systick: false,
+      wait_strategy: &WaitStrategy::Spin,
+      async_api: false,
+      gpio_low_power: false,
+      device,
+      sys,
+      register_constants: vec![],
+    };
+    overrides(&mut template);
+    template.render().unwrap()
+  }
+
+  #[test]
+  fn passes_clocks_only_to_clocked_submodules() {
+    let device = load_fixed("specs/svd/stm32f303.svd.patched");
+    let sys_info = SystemInfo::new(&device).unwrap();
+    let rendered = render_lib(&device, &sys_info, |lib| lib.has_clocks = true);
+
+    let activate = |name: &str| {
+      let start = rendered
+        .find(&format!("pub fn activate_{}(", name))
+        .unwrap();
+      let end = rendered
+        .find(&format!("pub fn deactivate_{}(", name))
+        .unwrap();
+      rendered[start..end].to_owned()
+    };
+
+    assert!(activate("tim2").contains("&self.clocks"));
+    assert!(activate("spi_i2s_1").contains("&self.clocks"));
+    assert!(!activate("gpio_a").contains("&self.clocks"));
+  }
+
+  #[test]
+  fn steals_system_without_freezing_clocks() {
+    let device = load_fixed("specs/svd/stm32f303.svd.patched");
+    let sys_info = SystemInfo::new(&device).unwrap();
+    let render =
+      |has_clocks: bool| render_lib(&device, &sys_info, |lib| lib.has_clocks = has_clocks);
+
+    let with_clocks = render(true);
+    let body = find_method(
+      &with_clocks,
+      "pub unsafe fn steal(clock_config: ClockConfig) -> Self",
+    );
+    assert!(body.contains("clocks: Clocks::steal(clock_config),"));
+    assert!(body.contains("owns_gpio_a: true,"));
+    assert!(!body.contains("freeze"));
+
+    assert!(render(false).contains("pub unsafe fn steal() -> Self"));
+  }
+
+  #[test]
+  fn allows_generated_code_warnings_unless_asked() {
+    let device = load_fixed("specs/svd/stm32f303.svd.patched");
+    let sys_info = SystemInfo::new(&device).unwrap();
+    let render = |allow_warnings: bool| {
+      render_lib(&device, &sys_info, |lib| {
+        lib.allow_warnings = allow_warnings
+      })
+    };
+
+    let allow = "#![allow(dead_code, unused_imports, non_camel_case_types)]";
+    assert!(render(true).contains(allow));
+    assert!(!render(false).contains(allow));
+  }
+
+  #[test]
+  fn sets_pins_to_analog_except_debug_port() {
+    let device = load_fixed("specs/svd/stm32f303.svd.patched");
+    let sys_info = SystemInfo::new(&device).unwrap();
+    let rendered = render_lib(&device, &sys_info, |lib| lib.gpio_low_power = true);
+
+    let init = find_method(&rendered, "pub fn init_gpio_low_power");
+    assert!(init.contains("if !self.owns_gpio_a {"));
+    assert!(init.contains("/* Set rcc.ahbenr.iopaen */"));
+    assert!(init.contains("/* Set gpioa.moder.moder0 = 0b11 */"));
+    assert!(init.contains("/* Set gpiob.pupdr.pupdr15 = 0b00 */"));
+    assert!(!init.contains("gpioa.moder.moder13 "));
+    assert!(!init.contains("gpioa.pupdr.pupdr14 "));
+  }
+
+  #[test]
+  fn checks_device_clock_spec_and_templates() {
+    let device = load_fixed("specs/svd/stm32f303.svd.patched");
+    assert!(check(&device, &GenerateOptions::default()).is_ok());
+
+    let options = GenerateOptions {
+      clock_specs: ClockSpecLocation {
+        path: Some("specs/clock/missing.ron".into()),
+        search_dirs: vec![],
+      },
+      ..GenerateOptions::default()
+    };
+    assert!(check(&device, &options).is_err());
+
+    let options = GenerateOptions {
+      families: Some(vec!["uart".to_owned()]),
+      ..GenerateOptions::default()
+    };
+    assert!(check(&device, &options).is_err());
+
+    // BS0 is only looked up when the GPIOA template is rendered
+    let mut device = device;
+    let bs0 = device
+      .peripherals
+      .iter_mut()
+      .find(|p| p.name == "GPIOA")
+      .unwrap()
+      .registers
+      .iter_mut()
+      .find(|r| r.name == "BSRR")
+      .unwrap()
+      .fields
+      .iter_mut()
+      .find(|f| f.name == "BS0")
+      .unwrap();
+    bs0.offset = 31;
+    bs0.width = 2;
+    assert!(check(&device, &GenerateOptions::default()).is_err());
+  }
+
+  #[test]
+  fn rejects_missing_field() {
+    let device = DeviceSpec::from_file("specs/svd/arm_device.svd").unwrap();
+    let res = device.write_val("timer0.cr.bogus", "3", true);
+
+    assert!(res.is_err());
+    assert_eq!(
+      "Field 'timer0.cr.bogus' not found in device ARM_Example",
+      res.unwrap_err().to_string()
+    );
+  }
+
+  #[test]
+  fn rejects_setting_bit_of_multi_bit_field() {
+    let device = DeviceSpec::from_file("specs/svd/arm_device.svd").unwrap();
+    let res = device.set_bit("timer0.cr.mode", true);
+
+    assert!(res.is_err());
+    assert_eq!(
+      "Cannot set single bit for multi-bit field 'timer0.cr.mode'",
+      res.unwrap_err().to_string()
+    );
+  }
+
+  // The macros end in `?`, like they do in the templates.
+  fn expand<F: FnOnce(&DeviceSpec) -> askama::Result<String>>(f: F) -> String {
+    let device = DeviceSpec::from_file("specs/svd/arm_device.svd").unwrap();
+    f(&device).unwrap()
+  }
+
+  const EN: &str = "0x40010000, 0b00000000000000000000000000000001";
+  const MODE: &str = "0x40010000, 0b00000000000000000000000001110000, 4";
+
+  #[test]
+  fn expands_write_val() {
+    assert_eq!(
+      f!("write_val_itf({MODE}, 3) /* Set timer0.cr.mode = 3 */"),
+      expand(|d| Ok(write_val!(d, "timer0.cr.mode", 3)))
+    );
+    assert_eq!(
+      f!("write_val({MODE}, 3) /* Set timer0.cr.mode = 3 */"),
+      expand(|d| Ok(write_val!(d, "timer0.cr.mode", 3, false)))
+    );
+  }
+
+  #[test]
+  fn expands_set_and_clear_bit() {
+    assert_eq!(
+      f!("set_bit_itf({EN}) /* Set timer0.cr.en */"),
+      expand(|d| Ok(set_bit!(d, "timer0.cr.en")))
+    );
+    assert_eq!(
+      f!("set_bit({EN}) /* Set timer0.cr.en */"),
+      expand(|d| Ok(set_bit!(d, "timer0.cr.en", false)))
+    );
+    assert_eq!(
+      f!("clear_bit_itf({EN}) /* Clear timer0.cr.en */"),
+      expand(|d| Ok(clear_bit!(d, "timer0.cr.en")))
+    );
+    assert_eq!(
+      f!("clear_bit({EN}) /* Clear timer0.cr.en */"),
+      expand(|d| Ok(clear_bit!(d, "timer0.cr.en", false)))
+    );
+  }
+
+  #[test]
+  fn expands_reads() {
+    assert_eq!(
+      f!("read_val({MODE}) /* Read timer0.cr.mode */"),
+      expand(|d| Ok(read_val!(d, "timer0.cr.mode")))
+    );
+    assert_eq!(
+      f!("is_set({EN}) /* Check if timer0.cr.en is 1 */"),
+      expand(|d| Ok(is_set!(d, "timer0.cr.en")))
+    );
+    assert_eq!(
+      f!("is_clear({EN}) /* Check if timer0.cr.en is 0 */"),
+      expand(|d| Ok(is_clear!(d, "timer0.cr.en")))
+    );
+  }
+
+  #[test]
+  fn expands_wait_for_val() {
+    assert_eq!(
+      f!("wait_for_val_itf({MODE}, 3, 1000) /* Block until timer0.cr.mode == 3 */"),
+      expand(|d| Ok(wait_for_val!(d, "timer0.cr.mode", 3)))
+    );
+    assert_eq!(
+      f!("wait_for_val({MODE}, 3, 1000) /* Block until timer0.cr.mode == 3 */"),
+      expand(|d| Ok(wait_for_val!(d, "timer0.cr.mode", 3, false)))
+    );
+    assert_eq!(
+      f!("wait_for_val_itf({MODE}, 3, 500) /* Block until timer0.cr.mode == 3 */"),
+      expand(|d| Ok(wait_for_val!(d, "timer0.cr.mode", 3, max_loops = 500)))
+    );
+    assert_eq!(
+      f!("wait_for_val({MODE}, 3, 500) /* Block until timer0.cr.mode == 3 */"),
+      expand(|d| Ok(wait_for_val!(d, "timer0.cr.mode", 3, 500, false)))
+    );
+  }
+
+  #[test]
+  fn expands_wait_for_set_and_clear() {
+    assert_eq!(
+      f!("wait_for_set_itf({EN}, 1000) /* Block until timer0.cr.en is set */"),
+      expand(|d| Ok(wait_for_set!(d, "timer0.cr.en")))
+    );
+    assert_eq!(
+      f!("wait_for_set({EN}, 1000) /* Block until timer0.cr.en is set */"),
+      expand(|d| Ok(wait_for_set!(d, "timer0.cr.en", false)))
+    );
+    assert_eq!(
+      f!("wait_for_set_itf({EN}, 500) /* Block until timer0.cr.en is set */"),
+      expand(|d| Ok(wait_for_set!(d, "timer0.cr.en", max_loops = 500)))
+    );
+    assert_eq!(
+      f!("wait_for_set_itf({EN}, 500) /* Block until timer0.cr.en is set */"),
+      expand(|d| Ok(wait_for_set!(d, "timer0.cr.en", 500, true)))
+    );
+    assert_eq!(
+      f!("wait_for_clear_itf({EN}, 1000) /* Block until timer0.cr.en is cleared */"),
+      expand(|d| Ok(wait_for_clear!(d, "timer0.cr.en")))
+    );
+    assert_eq!(
+      f!("wait_for_clear({EN}, 1000) /* Block until timer0.cr.en is cleared */"),
+      expand(|d| Ok(wait_for_clear!(d, "timer0.cr.en", false)))
+    );
+    assert_eq!(
+      f!("wait_for_clear_itf({EN}, 500) /* Block until timer0.cr.en is cleared */"),
+      expand(|d| Ok(wait_for_clear!(d, "timer0.cr.en", max_loops = 500)))
+    );
+    assert_eq!(
+      f!("wait_for_clear_itf({EN}, 500) /* Block until timer0.cr.en is cleared */"),
+      expand(|d| Ok(wait_for_clear!(d, "timer0.cr.en", 500, true)))
+    );
+  }
+
+  #[test]
+  fn expands_wait_for_clear_with_max_loops_outside_critical_section() {
+    assert_eq!(
+      f!("wait_for_clear({EN}, 500) /* Block until timer0.cr.en is cleared */"),
+      expand(|d| Ok(wait_for_clear!(d, "timer0.cr.en", 500, false)))
+    );
+  }
+
+  #[test]
+  fn rejects_writes_to_read_only_fields() {
+    let device = DeviceSpec::from_file("specs/svd/arm_device.svd").unwrap();
+
+    assert!(device.set_bit("timer0.sr.run", true).is_err());
+    assert!(device.reset("timer0.sr.run", true).is_err());
+    assert!(device.is_set("timer0.sr.run").is_ok());
+  }
+
+  #[test]
+  fn rejects_reads_of_write_only_fields() {
+    let device = DeviceSpec::from_file("specs/svd/arm_device.svd").unwrap();
+
+    assert!(device.read_val("timer0.cr.rst").is_err());
+    assert!(device.wait_for_set("timer0.cr.rst", "10", true).is_err());
+    assert!(device.set_bit("timer0.cr.rst", true).is_ok());
+  }
+
+  #[test]
+  fn rejects_fields_past_the_end_of_their_register() {
+    let mut device = DeviceSpec::from_file("specs/svd/arm_device.svd").unwrap();
+    for field in device
+      .peripherals
+      .iter_mut()
+      .filter(|p| p.name == "TIMER0")
+      .flat_map(|p| p.registers.iter_mut())
+      .filter(|r| r.name == "CR")
+      .flat_map(|r| r.fields.iter_mut())
+      .filter(|f| f.name == "EN")
+    {
+      field.offset = 31;
+      field.width = 2;
+    }
+
+    let err = device.set_bit("timer0.cr.en", false).unwrap_err();
+    assert!(err
+      .to_string()
+      .contains("Field 'timer0.cr.en' at bits 31..33 does not fit in its 32-bit register"));
+    assert!(device.set_bit("timer0.cr.rst", false).is_ok());
+  }
+
+  #[test]
+  fn lists_earlier_crates_in_workspace() {
+    let dir_path = std::env::temp_dir().join(format!(
+      "stm32-api-generator-workspace-{}",
+      std::process::id()
+    ));
+    let out_dir = OutputDirectory::new(&dir_path.to_string_lossy()).unwrap();
+    out_dir
+      .new_in_subdir("stm32f303-api")
+      .unwrap()
+      .publish(false, "Cargo.toml", "")
+      .unwrap();
+    out_dir.new_in_subdir("notes").unwrap();
+
+    generate_workspace(false, &out_dir, &vec!["stm32f405-api".to_owned()]).unwrap();
+
+    let manifest = fs::read_to_string(dir_path.join("Cargo.toml")).unwrap();
+    fs::remove_dir_all(&dir_path).ok();
+    assert_eq!(
+      "[workspace]\nmembers = [\n  \"stm32f303-api\",\n  \"stm32f405-api\",\n]",
+      manifest.trim_end()
+    );
+  }
+
+  #[test]
+  fn comments_out_probe_config_for_unknown_chips() {
+    let known = IncludeCargoConfigTemplate {
+      device_name: "STM32F303",
+      chip: probe::probe_rs_chip("STM32F303"),
+      target: probe::rust_target("STM32F303"),
+    }
+    .render()
+    .unwrap();
+    assert!(known.contains("[target.thumbv7em-none-eabihf]"));
+    assert!(known.contains("\nrunner = \"probe-rs run --chip STM32F303VCTx\""));
+
+    let unknown = IncludeEmbedTomlTemplate {
+      device_name: "STM32F302",
+      chip: None,
+    }
+    .render()
+    .unwrap();
+    assert!(unknown.contains("# chip = \"STM32F302...\""));
+    assert!(!unknown.contains("\nchip ="));
+  }
+
+  #[test]
+  fn emits_skeleton_for_generated_crate() {
+    let with_clocks = IncludeMainRsTemplate {
+      crate_module: "stm32f303_api".to_owned(),
+      has_clocks: true,
+    }
+    .render()
+    .unwrap();
+    assert!(with_clocks.contains("use stm32f303_api::{ clocks::ClockConfig, System };"));
+    assert!(with_clocks.contains("#[entry]\nfn main() -> ! {"));
+    assert!(with_clocks.contains("System::with_clocks(clock_config).unwrap();"));
+
+    let without_clocks = IncludeMainRsTemplate {
+      crate_module: "stm32f303_api".to_owned(),
+      has_clocks: false,
+    }
+    .render()
+    .unwrap();
+    assert!(without_clocks.contains("System::new().unwrap();"));
+    assert!(!without_clocks.contains("ClockConfig"));
+
+    let deps = DependencyVersions::default();
+    let cargo_toml = IncludeCargoTomlTemplate {
+      crate_name: "stm32f303-api".to_owned(),
+      deps: &deps,
+    }
+    .render()
+    .unwrap();
+    assert!(cargo_toml.contains("name = \"stm32f303-api-app\""));
+    assert!(cargo_toml.contains("[dependencies.stm32f303-api]"));
+    assert!(cargo_toml.contains("path = \"..\""));
+  }
+
+  #[test]
+  fn expands_clear_flag() {
+    assert_eq!(
+      f!("clear_flag_w0({EN}) /* Clear timer0.cr.en by writing 0 */"),
+      expand(|d| Ok(clear_flag!(
+        d,
+        StatusFlag {
+          name: Name::from("EN"),
+          path: "timer0.cr.en".to_owned(),
+          clear: FlagClear::WriteZero,
+        }
+      )))
+    );
+    assert_eq!(
+      f!("clear_flag_w1({EN}) /* Clear timer0.cr.en by writing 1 */"),
+      expand(|d| Ok(clear_flag!(
+        d,
+        StatusFlag {
+          name: Name::from("EN"),
+          path: "timer0.cr.en".to_owned(),
+          clear: FlagClear::WriteOne,
+        }
+      )))
+    );
+  }
+
+  #[test]
+  fn clears_read_only_flags_only_by_reading() {
+    let device = DeviceSpec::from_file("specs/svd/arm_device.svd").unwrap();
+
+    assert!(device
+      .clear_flag("timer0.sr.run", FlagClear::WriteOne)
+      .is_err());
+    assert!(device.clear_flag("timer0.sr.run", FlagClear::Read).is_ok());
+  }
+
+  #[test]
+  fn keeps_field_errors_swallowed_by_askama() {
+    let device = DeviceSpec::from_file("specs/svd/arm_device.svd").unwrap();
+    take_field_error();
+
+    assert!(field_access(device.set_bit("timer0.cr.bogus", true)).is_err());
+    assert!(field_access(device.set_bit("timer0.cr.other", true)).is_err());
+
+    match GeneratorError::from_anyhow(take_field_error().unwrap()) {
+      GeneratorError::FieldNotFound { path, .. } => assert_eq!("timer0.cr.bogus", path),
+      other => panic!("Expected FieldNotFound, got {:?}", other),
+    }
+    assert!(take_field_error().is_none());
+  }
+
+  #[test]
+  fn expands_lock_sequence() {
+    assert_eq!(
+      f!("lock_sequence({EN}, pins as u32) /* Lock with key timer0.cr.en */"),
+      expand(|d| Ok(lock_sequence!(d, "timer0.cr.en", "pins as u32")))
+    );
+  }
+
+  #[test]
+  fn recognizes_embedded_hal_0_2_requirements() {
+    let with = |version: Option<&str>| DependencyVersions {
+      embedded_hal: version.map(|v| v.to_owned()),
+      ..DependencyVersions::default()
+    };
+
+    assert!(with(Some("0.2.7")).embedded_hal_0_2());
+    assert!(with(Some("^0.2")).embedded_hal_0_2());
+    assert!(with(Some("=0.2.3")).embedded_hal_0_2());
+    assert!(!with(Some("1.0")).embedded_hal_0_2());
+    assert!(!with(Some("0.20")).embedded_hal_0_2());
+    assert!(!with(None).embedded_hal_0_2());
+  }
+
+  #[test]
+  fn expands_store_val() {
+    assert_eq!(
+      f!("store_val({MODE}, 3) /* Store timer0.cr.mode = 3 */"),
+      expand(|d| Ok(store_val!(d, "timer0.cr.mode", 3)))
+    );
+  }
+
+  #[test]
+  fn expands_write_val_with() {
+    assert_eq!(
+      f!("write_val_with_itf({MODE}, 3, 0b00000000000000000000000000000001, 0, 1) /* Set timer0.cr.mode = 3, timer0.cr.en = 1 */"),
+      expand(|d| Ok(write_val_with!(d, "timer0.cr.mode", 3, "timer0.cr.en", 1)))
+    );
+
+    let device = DeviceSpec::from_file("specs/svd/arm_device.svd").unwrap();
+    assert!(device
+      .write_val_with("timer0.cr.mode", "3", "timer0.sr.match", "0")
+      .is_err());
+  }
+
+  #[test]
+  fn finds_module_declaration_in_crate_root() {
+    let root = "#![no_std]\n\npub mod stm32f303_api;\nmod app;\n";
+    assert!(declares_module(root, "stm32f303_api"));
+    assert!(declares_module("  mod stm32f303_api;", "stm32f303_api"));
+    assert!(!declares_module(root, "stm32f103_api"));
+    assert!(!declares_module("// mod stm32f303_api;", "stm32f303_api"));
+  }
+}
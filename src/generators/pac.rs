@@ -0,0 +1,236 @@
+use crate::{
+  file::OutputDirectory,
+  generators::{raw, reset},
+  system::{FieldAccess, Name, SystemInfo},
+};
+use anyhow::{anyhow, Result};
+use askama::Template;
+use heck::SnakeCase;
+use svd_expander::{PeripheralSpec, RegisterSpec};
+
+// Only the GPIO ports for now. The register blocks have the same layout as the
+// ones in `raw`, with each 32-bit register behind a typed proxy.
+pub fn generate(dry_run: bool, sys_info: &SystemInfo, src_dir: &OutputDirectory) -> Result<()> {
+  let blocks = sys_info
+    .gpios
+    .iter()
+    .filter_map(|g| {
+      let svd_name = g.name.original.replace('_', "");
+      sys_info
+        .device
+        .peripherals
+        .iter()
+        .find(|p| p.name.to_lowercase() == svd_name)
+    })
+    .map(ProxyBlock::new)
+    .collect::<Vec<ProxyBlock>>();
+
+  for block in blocks.iter() {
+    src_dir.publish(
+      dry_run,
+      &format!("pac/{}.rs", block.name.snake()),
+      &PeripheralTemplate { block }.render().map_err(|_| {
+        anyhow!(
+          "Could not generate register proxies for {}",
+          block.name.camel()
+        )
+      })?,
+    )?;
+  }
+
+  src_dir.publish(
+    dry_run,
+    &f!("pac/mod.rs"),
+    &ModTemplate { blocks: &blocks }.render()?,
+  )?;
+
+  Ok(())
+}
+
+pub struct ProxyBlock {
+  pub name: Name,
+  pub base_address: u32,
+  pub span: u32,
+  pub members: Vec<raw::Member>,
+  pub registers: Vec<ProxyRegister>,
+}
+impl ProxyBlock {
+  pub fn new(peripheral: &PeripheralSpec) -> Self {
+    let raw_block = raw::RegisterBlock::new(peripheral);
+    let span = raw_block.span();
+
+    let mut members = raw_block.members;
+    let mut registers = Vec::new();
+    for member in members.iter_mut().filter(|m| !m.is_reserved) {
+      let register = match peripheral
+        .iter_registers()
+        .find(|r| raw::register_member_name(&r.path()) == member.name)
+      {
+        Some(r) if member.size == 4 => r,
+        // Proxies are all 32 bits wide, anything else stays a raw register.
+        _ => {
+          member.ty = format!("super::super::raw::{}", member.ty);
+          continue;
+        }
+      };
+
+      let proxy = ProxyRegister::new(&member.name, register);
+      member.ty = format!("Reg<{}::{}>", proxy.name, proxy.type_name());
+      registers.push(proxy);
+    }
+
+    Self {
+      name: raw_block.name,
+      base_address: raw_block.base_address,
+      span,
+      members,
+      registers,
+    }
+  }
+}
+
+pub struct ProxyRegister {
+  pub name: String,
+  pub reset_value: u32,
+  pub fields: Vec<ProxyField>,
+}
+impl ProxyRegister {
+  fn new(name: &str, register: &RegisterSpec) -> Self {
+    let mut fields = register
+      .fields
+      .iter()
+      .map(|f| {
+        let path = f.path().to_lowercase();
+        ProxyField {
+          name: f.name.to_snake_case(),
+          offset: f.offset,
+          width: f.width,
+          reset_value: reset::override_for(&path).or(f.reset_value).unwrap_or(0),
+          access: FieldAccess::of(f),
+        }
+      })
+      .collect::<Vec<ProxyField>>();
+    fields.sort_by_key(|f| f.offset);
+
+    Self {
+      name: name.to_owned(),
+      reset_value: fields
+        .iter()
+        .fold(0, |acc, f| acc | ((f.reset_value & f.mask()) << f.offset)),
+      fields,
+    }
+  }
+
+  pub fn type_name(&self) -> String {
+    self.name.to_uppercase()
+  }
+
+  // A register is readable or writable when any of its fields is.
+  pub fn access(&self) -> &'static str {
+    let readable = self.fields.iter().any(|f| f.access.readable);
+    let writable = self.fields.iter().any(|f| f.access.writable);
+    match (readable, writable) {
+      (true, false) => "RO",
+      (false, true) => "WO",
+      _ => "RW",
+    }
+  }
+
+  pub fn readable_fields(&self) -> Vec<&ProxyField> {
+    self.fields.iter().filter(|f| f.access.readable).collect()
+  }
+
+  pub fn writable_fields(&self) -> Vec<&ProxyField> {
+    self.fields.iter().filter(|f| f.access.writable).collect()
+  }
+}
+
+pub struct ProxyField {
+  pub name: String,
+  pub offset: u32,
+  pub width: u32,
+  pub reset_value: u32,
+  pub access: FieldAccess,
+}
+impl ProxyField {
+  // The mask before shifting into place.
+  pub fn mask(&self) -> u32 {
+    (((1u64 << self.width) - 1) & 0xFFFF_FFFF) as u32
+  }
+
+  pub fn ty(&self) -> &'static str {
+    match self.width {
+      1 => "bool",
+      2..=8 => "u8",
+      9..=16 => "u16",
+      _ => "u32",
+    }
+  }
+}
+
+#[derive(Template)]
+#[template(path = "pac/mod.rs.askama", escape = "none")]
+struct ModTemplate<'a> {
+  blocks: &'a Vec<ProxyBlock>,
+}
+
+#[derive(Template)]
+#[template(path = "pac/peripheral.rs.askama", escape = "none")]
+struct PeripheralTemplate<'a> {
+  block: &'a ProxyBlock,
+}
+
+#[cfg(test)]
+mod tests {
+  use svd_expander::DeviceSpec;
+
+  use crate::{fixup::load_fixed, generators::find_item};
+
+  use super::*;
+
+  fn gpio_a_block(device: &DeviceSpec) -> ProxyBlock {
+    ProxyBlock::new(
+      device
+        .peripherals
+        .iter()
+        .find(|p| p.name == "GPIOA")
+        .unwrap(),
+    )
+  }
+
+  #[test]
+  fn proxies_registers_by_access() {
+    let device = load_fixed("specs/svd/stm32f303.svd.patched");
+    let block = gpio_a_block(&device);
+    let register = |name: &str| block.registers.iter().find(|r| r.name == name).unwrap();
+
+    assert_eq!("RW", register("moder").access());
+    assert_eq!("RO", register("idr").access());
+    assert_eq!("WO", register("bsrr").access());
+    assert_eq!(0x2800_0000, register("moder").reset_value);
+
+    let rendered = PeripheralTemplate { block: &block }.render().unwrap();
+    assert!(rendered.contains("pub moder: Reg<moder::MODER>,"));
+    assert!(rendered.contains("pub fn moder13(&self) -> u8"));
+    assert!(rendered.contains("pub fn moder13(&mut self, value: u8) -> &mut Self"));
+    assert!(rendered.contains("pub fn idr0(&self) -> bool"));
+    assert!(!rendered.contains("pub fn idr0(&mut self"));
+    assert!(!rendered.contains("pub fn bs0(&self)"));
+  }
+
+  #[test]
+  fn reads_and_writes_whole_registers() {
+    let device = load_fixed("specs/svd/stm32f303.svd.patched");
+    let rendered = ModTemplate {
+      blocks: &vec![gpio_a_block(&device)],
+    }
+    .render()
+    .unwrap();
+
+    let readable = find_item(&rendered, "REG::Access: Readable,");
+    assert!(readable.contains("pub fn read_raw(&self) -> u32"));
+
+    let writable = find_item(&rendered, "REG::Access: Writable,");
+    assert!(writable.contains("pub unsafe fn write_raw(&self, value: u32)"));
+  }
+}
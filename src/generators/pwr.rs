@@ -0,0 +1,102 @@
+use crate::{clear_bit, set_bit, write_val};
+use crate::{
+  file::OutputDirectory,
+  generators::{render_or_skip, ReadWrite},
+  system::{pwr::Pwr, SystemInfo},
+};
+use anyhow::Result;
+use askama::Template;
+use svd_expander::DeviceSpec;
+
+pub fn generate(
+  dry_run: bool,
+  sys_info: &mut SystemInfo,
+  src_dir: &OutputDirectory,
+  api_path: String,
+) -> Result<()> {
+  if let Some(ref pwr) = sys_info.pwr {
+    let rendered = PeripheralTemplate {
+      api_path: api_path.clone(),
+      pwr,
+      d: &sys_info.device,
+    }
+    .render();
+    match render_or_skip(&pwr.name, rendered)? {
+      Some(rendered) => src_dir.publish(
+        dry_run,
+        &format!("pwr/{}.rs", pwr.name.snake()),
+        &rendered,
+      )?,
+      None => sys_info.pwr = None,
+    }
+  }
+
+  src_dir.publish(
+    dry_run,
+    &f!("pwr/mod.rs"),
+    &ModTemplate { s: sys_info }.render()?,
+  )?;
+
+  Ok(())
+}
+
+#[derive(Template)]
+#[template(path = "pwr/mod.rs.askama", escape = "none")]
+struct ModTemplate<'a> {
+  s: &'a SystemInfo<'a>,
+}
+
+#[derive(Template)]
+#[template(path = "pwr/peripheral.rs.askama", escape = "none")]
+struct PeripheralTemplate<'a> {
+  api_path: String,
+  pwr: &'a Pwr,
+  d: &'a DeviceSpec,
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{fixup::load_fixed, generators::find_method};
+
+  use super::*;
+
+  fn render_pwr() -> String {
+    let device = load_fixed("specs/svd/stm32f303.svd.patched");
+    let peripheral = device
+      .peripherals
+      .iter()
+      .find(|p| p.name == "PWR")
+      .unwrap();
+    let pwr = Pwr::new(&device, peripheral).unwrap().unwrap();
+
+    PeripheralTemplate {
+      api_path: "crate".to_owned(),
+      pwr: &pwr,
+      d: &device,
+    }
+    .render()
+    .unwrap()
+  }
+
+  #[test]
+  fn selects_stop_mode_with_pdds_and_lpds() {
+    let rendered = render_pwr();
+
+    let stop = find_method(&rendered, "pub fn enter_stop(");
+    assert!(stop.contains("/* Clear pwr.cr.pdds */"));
+    assert!(stop.contains("/* Set pwr.cr.lpds = low_power_regulator as u32 */"));
+  }
+
+  #[test]
+  fn clears_wakeup_flag_before_selecting_standby() {
+    let rendered = render_pwr();
+
+    let standby = find_method(&rendered, "pub fn enter_standby(");
+    let cwuf = standby.find("/* Set pwr.cr.cwuf */").unwrap();
+    let pdds = standby.find("/* Set pwr.cr.pdds */").unwrap();
+    assert!(cwuf < pdds);
+    assert!(!standby.contains("pwr.cr.lpds"));
+
+    assert!(!rendered.contains("pub fn set_voltage_scaling("));
+  }
+}
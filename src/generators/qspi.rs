@@ -0,0 +1,112 @@
+use crate::{clear_bit, set_bit, wait_for_clear, wait_for_set, write_val};
+use crate::{
+  file::OutputDirectory,
+  generators::{render_or_skip, ReadWrite},
+  system::{qspi::Qspi, SystemInfo},
+};
+use anyhow::Result;
+use askama::Template;
+use svd_expander::DeviceSpec;
+
+pub fn generate(
+  dry_run: bool,
+  sys_info: &mut SystemInfo,
+  src_dir: &OutputDirectory,
+  api_path: String,
+) -> Result<()> {
+  let mut skipped = vec![];
+  for qspi in sys_info.qspis.iter() {
+    let rendered = PeripheralTemplate {
+      api_path: api_path.clone(),
+      qspi,
+      d: &sys_info.device,
+    }
+    .render();
+    match render_or_skip(&qspi.name, rendered)? {
+      Some(rendered) => src_dir.publish(
+        dry_run,
+        &format!("qspi/{}.rs", qspi.name.snake()),
+        &rendered,
+      )?,
+      None => skipped.push(qspi.name.clone()),
+    }
+  }
+  sys_info.qspis.retain(|q| !skipped.contains(&q.name));
+
+  if sys_info.qspis.is_empty() {
+    return Ok(());
+  }
+
+  src_dir.publish(
+    dry_run,
+    &f!("qspi/mod.rs"),
+    &ModTemplate { s: sys_info }.render()?,
+  )?;
+
+  Ok(())
+}
+
+#[derive(Template)]
+#[template(path = "qspi/mod.rs.askama", escape = "none")]
+struct ModTemplate<'a> {
+  s: &'a SystemInfo<'a>,
+}
+
+#[derive(Template)]
+#[template(path = "qspi/peripheral.rs.askama", escape = "none")]
+struct PeripheralTemplate<'a> {
+  api_path: String,
+  qspi: &'a Qspi,
+  d: &'a DeviceSpec,
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{fixup::load_fixed, generators::find_method};
+
+  use super::*;
+
+  fn render_quadspi() -> (Qspi, String) {
+    let device = load_fixed("specs/svd/stm32f446.svd.patched");
+    let peripheral = device
+      .peripherals
+      .iter()
+      .find(|p| p.name == "QUADSPI")
+      .unwrap();
+    let qspi = Qspi::new(&device, peripheral).unwrap().unwrap();
+
+    let rendered = PeripheralTemplate {
+      api_path: "crate".to_owned(),
+      qspi: &qspi,
+      d: &device,
+    }
+    .render()
+    .unwrap();
+
+    (qspi, rendered)
+  }
+
+  #[test]
+  fn resolves_quadspi_fields_from_the_dcr_register() {
+    let (qspi, _) = render_quadspi();
+
+    assert_eq!("rcc.ahb3enr.qspien", qspi.peripheral_enable_field);
+    assert_eq!("quadspi.cr.en", qspi.en_field);
+    assert_eq!("quadspi.cr.prescaler", qspi.prescaler_field.path);
+    assert_eq!("quadspi.dcr.fsize", qspi.flash_size_field.path);
+    assert_eq!("quadspi.ccr.dcyc", qspi.dcyc_field.path);
+    assert_eq!(0xA000_1020, qspi.data_address);
+  }
+
+  #[test]
+  fn waits_for_transfer_complete_before_clearing_it() {
+    let (_, rendered) = render_quadspi();
+
+    let finish = find_method(&rendered, "fn finish_indirect(");
+    let wait = finish.find("/* Block until quadspi.sr.tcf is set */").unwrap();
+    let clear = finish.find("/* Set quadspi.fcr.ctcf */").unwrap();
+    assert!(wait < clear);
+
+    assert!(rendered.contains("ptr::read_volatile(2684358688 as *const u8)"));
+  }
+}
@@ -0,0 +1,183 @@
+use crate::{file::OutputDirectory, system::Name};
+use anyhow::{anyhow, Result};
+use askama::Template;
+use heck::SnakeCase;
+use svd_expander::{DeviceSpec, PeripheralSpec};
+
+pub fn generate(dry_run: bool, device: &DeviceSpec, src_dir: &OutputDirectory) -> Result<()> {
+  let blocks = device
+    .peripherals
+    .iter()
+    .map(RegisterBlock::new)
+    .collect::<Vec<RegisterBlock>>();
+
+  for block in blocks.iter() {
+    src_dir.publish(
+      dry_run,
+      &format!("raw/{}.rs", block.name.snake()),
+      &PeripheralTemplate { block }
+        .render()
+        .map_err(|_| anyhow!("Could not generate register block {}", block.name.camel()))?,
+    )?;
+  }
+
+  src_dir.publish(
+    dry_run,
+    &f!("raw/mod.rs"),
+    &ModTemplate { blocks: &blocks }.render()?,
+  )?;
+
+  Ok(())
+}
+
+pub struct RegisterBlock {
+  pub name: Name,
+  pub base_address: u32,
+  pub members: Vec<Member>,
+}
+impl RegisterBlock {
+  pub fn new(peripheral: &PeripheralSpec) -> Self {
+    let base_address = peripheral.base_address;
+
+    let mut registers = peripheral
+      .iter_registers()
+      .filter_map(|r| {
+        let size = r.size.unwrap_or(32) / 8;
+        let offset = r.address() - base_address;
+        match size {
+          // Misaligned registers would make `repr(C)` add padding of its own.
+          1 | 2 | 4 | 8 if offset % size == 0 => {
+            Some((offset, size, register_member_name(&r.path())))
+          }
+          _ => {
+            info!(
+              "Leaving register {} out of the {} register block, it has an unsupported size or alignment.",
+              r.name, peripheral.name
+            );
+            None
+          }
+        }
+      })
+      .collect::<Vec<(u32, u32, String)>>();
+    registers.sort_by_key(|(offset, _, _)| *offset);
+
+    let mut members: Vec<Member> = Vec::new();
+    let mut end = 0;
+    for (offset, size, name) in registers {
+      // Alternate views of the same register (CCMR1_Output and CCMR1_Input) share
+      // an offset. svd2rust would make a union, only the first one is kept here.
+      if offset < end || members.iter().any(|m| m.name == name) {
+        info!(
+          "Leaving register {} out of the {} register block, it overlaps another register.",
+          name, peripheral.name
+        );
+        continue;
+      }
+
+      if offset > end {
+        members.push(Member::reserved(members.len(), offset - end));
+      }
+
+      members.push(Member {
+        name,
+        ty: format!("Reg<u{}>", size * 8),
+        size,
+        is_reserved: false,
+      });
+      end = offset + size;
+    }
+
+    // Round up to the largest register so the span matches the struct's size.
+    let align = members
+      .iter()
+      .map(|m| m.size)
+      .filter(|s| *s <= 8)
+      .max()
+      .unwrap_or(1);
+    if end % align != 0 {
+      members.push(Member::reserved(members.len(), align - end % align));
+    }
+
+    Self {
+      name: Name::from(&peripheral.name),
+      base_address,
+      members,
+    }
+  }
+
+  pub fn span(&self) -> u32 {
+    self.members.iter().map(|m| m.size).sum()
+  }
+}
+
+pub struct Member {
+  pub name: String,
+  pub ty: String,
+  pub size: u32,
+  pub is_reserved: bool,
+}
+impl Member {
+  fn reserved(index: usize, size: u32) -> Self {
+    Self {
+      name: f!("_reserved{index}"),
+      ty: f!("[u8; {size}]"),
+      size,
+      is_reserved: true,
+    }
+  }
+}
+
+// `tim1.ccr[1]` becomes `ccr1`, and registers in clusters keep the cluster name,
+// so `sai1.cha.cr1` becomes `cha_cr1`.
+pub fn register_member_name(path: &str) -> String {
+  let relative_path = match path.find('.') {
+    Some(i) => &path[i + 1..],
+    None => path,
+  };
+
+  relative_path
+    .replace(|c| c == '[' || c == ']', "")
+    .replace('.', "_")
+    .to_snake_case()
+}
+
+#[derive(Template)]
+#[template(path = "raw/mod.rs.askama", escape = "none")]
+struct ModTemplate<'a> {
+  blocks: &'a Vec<RegisterBlock>,
+}
+
+#[derive(Template)]
+#[template(path = "raw/peripheral.rs.askama", escape = "none")]
+struct PeripheralTemplate<'a> {
+  block: &'a RegisterBlock,
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::fixup::load_fixed;
+
+  use super::*;
+
+  #[test]
+  fn pads_register_block_to_peripheral_span() {
+    let device = load_fixed("specs/svd/stm32f303.svd.patched");
+    let tim2 = device
+      .peripherals
+      .iter()
+      .find(|p| p.name == "TIM2")
+      .unwrap();
+    let block = RegisterBlock::new(tim2);
+
+    // DMAR at 0x4C is the last TIM2 register.
+    assert_eq!(0x50, block.span());
+
+    let ccr1_offset: u32 = block
+      .members
+      .iter()
+      .take_while(|m| m.name != "ccr1")
+      .map(|m| m.size)
+      .sum();
+    assert_eq!(0x34, ccr1_offset);
+  }
+}
@@ -0,0 +1,82 @@
+use std::{cell::RefCell, collections::BTreeMap, mem};
+
+pub struct RegisterConstant {
+  pub name: String,
+  pub address: u32,
+}
+
+#[derive(Default)]
+struct RegsConfig {
+  // Path to the API crate root, set when register names are enabled.
+  api_path: Option<String>,
+  addresses: BTreeMap<String, u32>,
+}
+
+thread_local! {
+  static REGS_CONFIG: RefCell<RegsConfig> = RefCell::new(RegsConfig::default());
+}
+
+// With `api_path` set, the access code refers to registers by the constants in
+// the crate's `regs` module instead of by raw address.
+pub fn configure(api_path: Option<String>) {
+  REGS_CONFIG.with(|config| {
+    *config.borrow_mut() = RegsConfig {
+      api_path,
+      addresses: BTreeMap::new(),
+    }
+  });
+}
+
+// Returns the expression the access code should use for the address of the
+// register holding the field at `path`.
+pub fn address_expr(path: &str, address: u32) -> String {
+  REGS_CONFIG.with(|config| {
+    let mut config = config.borrow_mut();
+    let api_path = match config.api_path {
+      Some(ref p) => p.clone(),
+      None => return f!("{address:#010x}"),
+    };
+
+    let name = constant_name(path);
+    config.addresses.insert(name.clone(), address);
+    f!("{api_path}::regs::{name}")
+  })
+}
+
+pub fn take_constants() -> Vec<RegisterConstant> {
+  REGS_CONFIG.with(|config| {
+    mem::take(&mut config.borrow_mut().addresses)
+      .into_iter()
+      .map(|(name, address)| RegisterConstant { name, address })
+      .collect()
+  })
+}
+
+// `gpioa.odr.odr3` is in register `GPIOA_ODR`. Clusters become part of the
+// name, so `sai1.cha.cr1.mode` is in `SAI1_CHA_CR1`.
+fn constant_name(path: &str) -> String {
+  let register_path = match path.rfind('.') {
+    Some(i) => &path[..i],
+    None => path,
+  };
+
+  register_path
+    .chars()
+    .filter(|c| *c != '[' && *c != ']')
+    .map(|c| match c {
+      '.' => '_',
+      c => c.to_ascii_uppercase(),
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn names_register_after_field_path() {
+    assert_eq!("GPIOA_ODR", constant_name("gpioa.odr.odr3"));
+    assert_eq!("SAI1_CHA_CR1", constant_name("sai1.cha.cr1.mode"));
+  }
+}
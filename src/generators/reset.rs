@@ -0,0 +1,122 @@
+use std::{cell::RefCell, collections::BTreeMap, fs, path::Path};
+
+use anyhow::{bail, Result};
+use serde::Deserialize;
+use svd_expander::DeviceSpec;
+
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct ResetOverrides {
+  fields: BTreeMap<String, u32>,
+}
+impl ResetOverrides {
+  pub fn from_ron_file<P: AsRef<Path>>(path: P) -> Result<ResetOverrides> {
+    info!(
+      "Parsing reset overrides from file '{}'",
+      match path.as_ref().to_str() {
+        Some(s) => s,
+        None => "(could not create string from path)",
+      }
+    );
+    Ok(ron::from_str(&fs::read_to_string(path)?)?)
+  }
+}
+
+#[derive(Default)]
+struct ResetConfig {
+  strict: bool,
+  overrides: BTreeMap<String, u32>,
+}
+
+thread_local! {
+  static RESET_CONFIG: RefCell<ResetConfig> = RefCell::new(ResetConfig::default());
+}
+
+// Loads the reset value overrides for the device from `specs/reset/<device>.ron`
+// if there are any. They're consulted before the SVD's reset values.
+pub fn configure(device: &DeviceSpec, strict: bool) -> Result<()> {
+  let overrides_filepath = format!("specs/reset/{}.ron", device.name.to_lowercase());
+
+  let mut overrides = BTreeMap::new();
+  if Path::new(&overrides_filepath).exists() {
+    for (path, value) in ResetOverrides::from_ron_file(&overrides_filepath)?.fields {
+      if device.try_get_field(&path).is_none() {
+        bail!(
+          "Reset override in {} is for field '{}', which doesn't exist in device {}",
+          overrides_filepath,
+          path,
+          device.name
+        );
+      }
+      overrides.insert(path.to_lowercase(), value);
+    }
+  }
+
+  RESET_CONFIG.with(|config| *config.borrow_mut() = ResetConfig { strict, overrides });
+  Ok(())
+}
+
+pub fn is_strict() -> bool {
+  RESET_CONFIG.with(|config| config.borrow().strict)
+}
+
+pub fn override_for(path: &str) -> Option<u32> {
+  RESET_CONFIG.with(|config| config.borrow().overrides.get(&path.to_lowercase()).copied())
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::generators::ReadWrite;
+
+  use super::*;
+
+  // The example device with no reset values or masks on CR.CNT and CR.MODE.
+  fn device_without_resets() -> DeviceSpec {
+    let mut device = DeviceSpec::from_file("specs/svd/arm_device.svd").unwrap();
+    for field in device
+      .peripherals
+      .iter_mut()
+      .filter(|p| p.name == "TIMER0")
+      .flat_map(|p| p.registers.iter_mut())
+      .filter(|r| r.name == "CR")
+      .flat_map(|r| r.fields.iter_mut())
+      .filter(|f| f.name == "CNT" || f.name == "MODE")
+    {
+      field.reset_value = None;
+      field.reset_mask = None;
+    }
+    device
+  }
+
+  #[test]
+  fn falls_back_to_field_mask_and_zero() {
+    let device = device_without_resets();
+    configure(&device, false).unwrap();
+
+    assert_eq!(
+      "write_val_itf(0x40010000, 0b00000000000000000000000001110000, 4, 0) /* Reset timer0.cr.mode */",
+      device.reset("timer0.cr.mode", true).unwrap()
+    );
+  }
+
+  #[test]
+  fn rejects_missing_reset_values_in_strict_mode() {
+    let device = device_without_resets();
+    configure(&device, true).unwrap();
+
+    let err = device.reset("timer0.cr.mode", true).unwrap_err();
+    assert_eq!("No reset mask configured for field timer0.cr.mode", err.to_string());
+  }
+
+  #[test]
+  fn uses_overrides_from_reset_spec() {
+    // specs/reset/arm_example.ron sets CR.CNT to 2
+    let device = device_without_resets();
+    configure(&device, true).unwrap();
+
+    assert_eq!(Some(2), override_for("TIMER0.CR.CNT"));
+    assert_eq!(
+      "write_val_itf(0x40010000, 0b00000000000000000000000000001100, 2, 2) /* Reset timer0.cr.cnt (overridden) */",
+      device.reset("timer0.cr.cnt", true).unwrap()
+    );
+  }
+}
@@ -0,0 +1,99 @@
+use crate::{clear_bit, is_set, read_val, set_bit};
+use crate::{
+  file::OutputDirectory,
+  generators::{render_or_skip, ReadWrite},
+  system::{rng::Rng, SystemInfo},
+};
+use anyhow::Result;
+use askama::Template;
+use svd_expander::DeviceSpec;
+
+pub fn generate(
+  dry_run: bool,
+  sys_info: &mut SystemInfo,
+  src_dir: &OutputDirectory,
+  api_path: String,
+) -> Result<()> {
+  let rng = match sys_info.rng {
+    Some(ref r) => r,
+    None => return Ok(()),
+  };
+
+  let rendered = PeripheralTemplate {
+    api_path: api_path.clone(),
+    rng,
+    d: &sys_info.device,
+  }
+  .render();
+  match render_or_skip(&rng.name, rendered)? {
+    Some(rendered) => src_dir.publish(
+      dry_run,
+      &format!("rng/{}.rs", rng.name.snake()),
+      &rendered,
+    )?,
+    None => {
+      sys_info.rng = None;
+      return Ok(());
+    }
+  }
+
+  src_dir.publish(
+    dry_run,
+    &f!("rng/mod.rs"),
+    &ModTemplate { s: sys_info }.render()?,
+  )?;
+
+  Ok(())
+}
+
+#[derive(Template)]
+#[template(path = "rng/mod.rs.askama", escape = "none")]
+struct ModTemplate<'a> {
+  s: &'a SystemInfo<'a>,
+}
+
+#[derive(Template)]
+#[template(path = "rng/peripheral.rs.askama", escape = "none")]
+struct PeripheralTemplate<'a> {
+  api_path: String,
+  rng: &'a Rng,
+  d: &'a DeviceSpec,
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{fixup::load_fixed, generators::find_method};
+
+  use super::*;
+
+  #[test]
+  fn checks_error_flags_before_reading_data() {
+    let device = load_fixed("specs/svd/stm32f407.svd.patched");
+    let peripheral = device
+      .peripherals
+      .iter()
+      .find(|p| p.name == "RNG")
+      .unwrap();
+    let rng = Rng::new(&device, peripheral).unwrap().unwrap();
+
+    let rendered = PeripheralTemplate {
+      api_path: "crate".to_owned(),
+      rng: &rng,
+      d: &device,
+    }
+    .render()
+    .unwrap();
+
+    let body = find_method(&rendered, "pub fn next_u32(&mut self) -> core::result::Result");
+    let secs = body.find("/* Check if rng.sr.secs is 1 */").unwrap();
+    let cecs = body.find("/* Check if rng.sr.cecs is 1 */").unwrap();
+    let drdy = body.find("/* Check if rng.sr.drdy is 1 */").unwrap();
+    assert!(secs < cecs && cecs < drdy);
+
+    assert!(body[secs..cecs].contains("/* Clear rng.sr.seis */"));
+    assert!(body[secs..cecs].contains("return Err(RngError::SeedError);"));
+    assert!(body[cecs..drdy].contains("return Err(RngError::ClockError);"));
+    assert!(body[drdy..].contains("return Ok(read_val("));
+    assert!(body[drdy..].contains("/* Read rng.dr.rndata */"));
+  }
+}
@@ -0,0 +1,101 @@
+use crate::{clear_bit, is_set, read_val, set_bit, store_val, write_val};
+use crate::{
+  file::OutputDirectory,
+  generators::{render_or_skip, ReadWrite},
+  system::{sai::Sai, SystemInfo},
+};
+use anyhow::Result;
+use askama::Template;
+use svd_expander::DeviceSpec;
+
+pub fn generate(
+  dry_run: bool,
+  sys_info: &mut SystemInfo,
+  src_dir: &OutputDirectory,
+  api_path: String,
+) -> Result<()> {
+  let mut skipped = vec![];
+  for sai in sys_info.sais.iter() {
+    let rendered = PeripheralTemplate {
+      api_path: api_path.clone(),
+      sai,
+      d: &sys_info.device,
+    }
+    .render();
+    match render_or_skip(&sai.name, rendered)? {
+      Some(rendered) => src_dir.publish(
+        dry_run,
+        &format!("sai/{}.rs", sai.name.snake()),
+        &rendered,
+      )?,
+      None => skipped.push(sai.name.clone()),
+    }
+  }
+  sys_info.sais.retain(|s| !skipped.contains(&s.name));
+
+  src_dir.publish(
+    dry_run,
+    &f!("sai/mod.rs"),
+    &ModTemplate { s: sys_info }.render()?,
+  )?;
+
+  Ok(())
+}
+
+#[derive(Template)]
+#[template(path = "sai/mod.rs.askama", escape = "none")]
+struct ModTemplate<'a> {
+  s: &'a SystemInfo<'a>,
+}
+
+#[derive(Template)]
+#[template(path = "sai/peripheral.rs.askama", escape = "none")]
+struct PeripheralTemplate<'a> {
+  api_path: String,
+  sai: &'a Sai,
+  d: &'a DeviceSpec,
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{fixup::load_fixed, generators::find_method};
+
+  use super::*;
+
+  fn render_sai1() -> String {
+    let device = load_fixed("specs/svd/stm32f446.svd.patched");
+    let peripheral = device
+      .peripherals
+      .iter()
+      .find(|p| p.name == "SAI1")
+      .unwrap();
+    let sai = Sai::new(&device, peripheral).unwrap().unwrap();
+
+    PeripheralTemplate {
+      api_path: "crate".to_owned(),
+      sai: &sai,
+      d: &device,
+    }
+    .render()
+    .unwrap()
+  }
+
+  #[test]
+  fn writes_each_blocks_own_data_register_directly() {
+    let rendered = render_sai1();
+
+    let block_a = &rendered[rendered.find("impl Sai1BlockA {").unwrap()..];
+    let block_a = &block_a[..block_a.find("impl Sai1BlockB {").unwrap()];
+    let block_b = &rendered[rendered.find("impl Sai1BlockB {").unwrap()..];
+
+    let write_a = find_method(block_a, "pub fn write_sample(");
+    assert!(write_a.contains("store_val("));
+    assert!(write_a.contains("/* Store sai1.cha.dr.data = sample */"));
+    assert!(!write_a.contains("write_val"));
+    assert!(!block_a.contains("sai1.chb."));
+
+    let write_b = find_method(block_b, "pub fn write_sample(");
+    assert!(write_b.contains("/* Store sai1.chb.dr.data = sample */"));
+    assert!(!block_b.contains("sai1.cha."));
+  }
+}
@@ -1,7 +1,9 @@
-use crate::{clear_bit, is_set, read_val, reset, set_bit, wait_for_clear, wait_for_set, write_val};
+use crate::{
+  clear_bit, clear_flag, is_set, read_val, reset, set_bit, wait_for_clear, wait_for_set, write_val,
+};
 use crate::{
   file::OutputDirectory,
-  generators::ReadWrite,
+  generators::{render_or_skip, ReadWrite},
   system::{spi::Spi, SystemInfo},
 };
 use anyhow::Result;
@@ -10,22 +12,30 @@ use svd_expander::DeviceSpec;
 
 pub fn generate(
   dry_run: bool,
-  sys_info: &SystemInfo,
+  sys_info: &mut SystemInfo,
   src_dir: &OutputDirectory,
   api_path: String,
+  embedded_hal_0_2: bool,
 ) -> Result<()> {
+  let mut skipped = vec![];
   for spi in sys_info.spis.iter() {
-    src_dir.publish(
-      dry_run,
-      &format!("spi/{}.rs", spi.struct_name.snake()),
-      &PeripheralTemplate {
-        api_path: api_path.clone(),
-        spi: &spi,
-        d: &sys_info.device,
-      }
-      .render()?,
-    )?;
+    let rendered = PeripheralTemplate {
+      api_path: api_path.clone(),
+      spi: &spi,
+      d: &sys_info.device,
+      embedded_hal_0_2,
+    }
+    .render();
+    match render_or_skip(&spi.struct_name, rendered)? {
+      Some(rendered) => src_dir.publish(
+        dry_run,
+        &format!("spi/{}.rs", spi.struct_name.snake()),
+        &rendered,
+      )?,
+      None => skipped.push(spi.struct_name.clone()),
+    }
   }
+  sys_info.spis.retain(|s| !skipped.contains(&s.struct_name));
 
   src_dir.publish(
     dry_run,
@@ -48,4 +58,74 @@ struct PeripheralTemplate<'a> {
   api_path: String,
   spi: &'a Spi,
   d: &'a DeviceSpec,
+  // `spi::FullDuplex` is gone from embedded-hal 1.0, so it's only implemented
+  // for 0.2.
+  embedded_hal_0_2: bool,
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{fixup::load_fixed, generators::find_method};
+
+  use super::*;
+
+  fn render_spi1(embedded_hal_0_2: bool) -> String {
+    let device = load_fixed("specs/svd/stm32f303.svd.patched");
+    let peripheral = device
+      .peripherals
+      .iter()
+      .find(|p| p.name == "SPI1")
+      .unwrap();
+    let spi = Spi::new(&device, peripheral).unwrap();
+
+    PeripheralTemplate {
+      api_path: "crate".to_owned(),
+      spi: &spi,
+      d: &device,
+      embedded_hal_0_2,
+    }
+    .render()
+    .unwrap()
+  }
+
+  #[test]
+  fn converts_transfer_frames_as_little_endian() {
+    let rendered = render_spi1(false);
+
+    assert!(rendered.contains("self.write(u16::from_le_bytes([frame[0], frame[1]]));"));
+    assert!(rendered.contains("frame.copy_from_slice(&self.read().to_le_bytes());"));
+  }
+
+  #[test]
+  fn keeps_slave_methods_off_the_master_api() {
+    let rendered = render_spi1(false);
+
+    let slave_impl = &rendered[rendered.find("impl<P, F> Spi<P, F, SlaveRole>").unwrap()..];
+    assert!(slave_impl.contains("pub fn configure_slave(&mut self, select: SlaveSelect)"));
+    assert!(slave_impl.contains("pub fn slave_transfer(&mut self, buf: &mut [u16])"));
+    assert_eq!(1, rendered.matches("pub fn slave_transfer(").count());
+  }
+
+  #[test]
+  fn returns_would_block_until_flags_are_set() {
+    let rendered = render_spi1(false);
+
+    let write_nb = find_method(&rendered, "pub fn write_nb(");
+    assert!(write_nb
+      .contains("/* Check if spi1.sr.txe is 0 */ {\n      return Err(nb::Error::WouldBlock);"));
+
+    let read_nb = find_method(&rendered, "pub fn read_nb(");
+    assert!(read_nb
+      .contains("/* Check if spi1.sr.rxne is 0 */ {\n      return Err(nb::Error::WouldBlock);"));
+
+    assert!(!rendered.contains("embedded_hal::spi::FullDuplex"));
+  }
+
+  #[test]
+  fn implements_full_duplex_only_for_embedded_hal_0_2() {
+    assert!(render_spi1(true).contains(
+      "#[cfg(all(feature = \"nb\", feature = \"embedded-hal\"))]\nimpl<P, F, R> embedded_hal::spi::FullDuplex<u16> for Spi<P, F, R>"
+    ));
+    assert!(!render_spi1(false).contains("embedded_hal::spi::FullDuplex"));
+  }
 }
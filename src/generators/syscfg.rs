@@ -0,0 +1,85 @@
+use crate::{clear_bit, set_bit, write_val};
+use crate::{
+  file::OutputDirectory,
+  generators::{render_or_skip, ReadWrite},
+  system::{syscfg::Syscfg, SystemInfo},
+};
+use anyhow::Result;
+use askama::Template;
+use svd_expander::DeviceSpec;
+
+pub fn generate(
+  dry_run: bool,
+  sys_info: &mut SystemInfo,
+  src_dir: &OutputDirectory,
+  api_path: String,
+) -> Result<()> {
+  let syscfg = match sys_info.syscfg {
+    Some(ref s) => s,
+    None => return Ok(()),
+  };
+
+  let rendered = PeripheralTemplate {
+    api_path: api_path.clone(),
+    syscfg,
+    d: &sys_info.device,
+  }
+  .render();
+  match render_or_skip(&syscfg.name, rendered)? {
+    Some(rendered) => src_dir.publish(
+      dry_run,
+      &format!("syscfg/{}.rs", syscfg.name.snake()),
+      &rendered,
+    )?,
+    None => {
+      sys_info.syscfg = None;
+      return Ok(());
+    }
+  }
+
+  src_dir.publish(
+    dry_run,
+    &f!("syscfg/mod.rs"),
+    &ModTemplate { s: sys_info }.render()?,
+  )?;
+
+  Ok(())
+}
+
+#[derive(Template)]
+#[template(path = "syscfg/mod.rs.askama", escape = "none")]
+struct ModTemplate<'a> {
+  s: &'a SystemInfo<'a>,
+}
+
+#[derive(Template)]
+#[template(path = "syscfg/peripheral.rs.askama", escape = "none")]
+struct PeripheralTemplate<'a> {
+  api_path: String,
+  syscfg: &'a Syscfg,
+  d: &'a DeviceSpec,
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::fixup::load_fixed;
+
+  use super::*;
+
+  #[test]
+  fn routes_exti_lines_to_ports() {
+    let device = load_fixed("specs/svd/stm32f303.svd.patched");
+    let sys_info = SystemInfo::new(&device).unwrap();
+    let rendered = PeripheralTemplate {
+      api_path: "crate".to_owned(),
+      syscfg: sys_info.syscfg.as_ref().unwrap(),
+      d: &device,
+    }
+    .render()
+    .unwrap();
+
+    assert!(rendered.contains("pub fn set_memory_remap(&mut self, remap: super::MemoryRemap)"));
+    assert!(rendered.contains("pub fn route_exti0(&mut self, port: crate::gpio::ExtiPort)"));
+    assert!(rendered.contains("pub fn route_exti15(&mut self, port: crate::gpio::ExtiPort)"));
+  }
+}
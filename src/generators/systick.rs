@@ -0,0 +1,64 @@
+use crate::file::OutputDirectory;
+use anyhow::Result;
+use askama::Template;
+
+// The clock tap feeding SysTick, which has to be in the device's clock schematic.
+pub const CLOCK_TAP: &str = "to_cortex_system_timer";
+
+// `core_clock` is the clock the tap divides down, if it's behind a divider.
+// SysTick then counts that divided clock as its external reference.
+pub fn generate(
+  dry_run: bool,
+  src_dir: &OutputDirectory,
+  api_path: String,
+  core_clock: Option<String>,
+) -> Result<()> {
+  src_dir.publish(
+    dry_run,
+    &f!("systick.rs"),
+    &SysTickTemplate {
+      api_path,
+      clock_tap: CLOCK_TAP,
+      core_clock,
+    }
+    .render()?,
+  )?;
+
+  Ok(())
+}
+
+#[derive(Template)]
+#[template(path = "systick/mod.rs.askama", escape = "none")]
+struct SysTickTemplate<'a> {
+  api_path: String,
+  clock_tap: &'a str,
+  core_clock: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn counts_the_clock_the_reload_comes_from() {
+    let divided = SysTickTemplate {
+      api_path: "crate".to_owned(),
+      clock_tap: CLOCK_TAP,
+      core_clock: Some("h_clk".to_owned()),
+    }
+    .render()
+    .unwrap();
+    assert!(divided.contains("match config.to_cortex_system_timer_freq() < config.h_clk_freq() {"));
+    assert!(divided.contains("true => SystClkSource::External,"));
+
+    let undivided = SysTickTemplate {
+      api_path: "crate".to_owned(),
+      clock_tap: CLOCK_TAP,
+      core_clock: None,
+    }
+    .render()
+    .unwrap();
+    assert!(undivided.contains("syst.set_clock_source(SystClkSource::Core);"));
+    assert!(!undivided.contains("External"));
+  }
+}
@@ -1,6 +1,6 @@
-use crate::{clear_bit, is_set, read_val, reset, set_bit, write_val};
+use crate::{clear_bit, clear_flag, is_set, read_val, reset, set_bit, wait_for_set, write_val};
 use crate::{
-  generators::ReadWrite,
+  generators::{render_or_skip, ReadWrite},
   system::{timer::Timer, SystemInfo},
 };
 use anyhow::Result;
@@ -11,22 +11,30 @@ use crate::file::OutputDirectory;
 
 pub fn generate(
   dry_run: bool,
-  sys_info: &SystemInfo,
+  sys_info: &mut SystemInfo,
   src_dir: &OutputDirectory,
   api_path: String,
+  async_api: bool,
 ) -> Result<()> {
+  let mut skipped = vec![];
   for timer in sys_info.timers.iter() {
-    src_dir.publish(
-      dry_run,
-      &format!("timer/{}.rs", timer.name.snake()),
-      &PeripheralTemplate {
-        api_path: api_path.clone(),
-        t: &timer,
-        d: &sys_info.device,
-      }
-      .render()?,
-    )?;
+    let rendered = PeripheralTemplate {
+      api_path: api_path.clone(),
+      async_api,
+      t: &timer,
+      d: &sys_info.device,
+    }
+    .render();
+    match render_or_skip(&timer.name, rendered)? {
+      Some(rendered) => src_dir.publish(
+        dry_run,
+        &format!("timer/{}.rs", timer.name.snake()),
+        &rendered,
+      )?,
+      None => skipped.push(timer.name.clone()),
+    }
   }
+  sys_info.timers.retain(|t| !skipped.contains(&t.name));
 
   src_dir.publish(
     dry_run,
@@ -52,6 +60,276 @@ struct ModTemplate<'a> {
 #[template(path = "timer/peripheral.rs.askama", escape = "none")]
 struct PeripheralTemplate<'a> {
   api_path: String,
+  async_api: bool,
   t: &'a Timer,
   d: &'a DeviceSpec,
 }
+
+#[cfg(test)]
+mod tests {
+  use svd_expander::DeviceSpec;
+
+  use crate::{
+    fixup::load_fixed,
+    generators::{find_item, find_method},
+  };
+
+  use super::*;
+
+  fn render_timer(device: &DeviceSpec, name: &str) -> String {
+    render_timer_with_async(device, name, false)
+  }
+
+  fn render_timer_with_async(device: &DeviceSpec, name: &str, async_api: bool) -> String {
+    let peripheral = device.peripherals.iter().find(|p| p.name == name).unwrap();
+    let timer = Timer::new(device, peripheral).unwrap().unwrap();
+
+    PeripheralTemplate {
+      api_path: "crate".to_owned(),
+      async_api,
+      t: &timer,
+      d: device,
+    }
+    .render()
+    .unwrap()
+  }
+
+  #[test]
+  fn clears_update_flag_by_writing_zero() {
+    let device = load_fixed("specs/svd/stm32f303.svd.patched");
+    let rendered = render_timer(&device, "TIM2");
+
+    let clear_fn = find_method(&rendered, "fn clear_update_flag(&mut self)");
+    assert!(clear_fn.contains("/* Clear tim2.sr.uif by writing 0 */"));
+    assert!(!rendered.contains("fn clear_uif("));
+  }
+
+  #[test]
+  fn uses_u32_counter_for_32_bit_timers() {
+    let device = load_fixed("specs/svd/stm32f303.svd.patched");
+    let rendered = render_timer(&device, "TIM2");
+
+    assert!(rendered.contains("type Counter = u32;"));
+    assert!(rendered.contains("fn count(&self) -> u32"));
+  }
+
+  #[test]
+  fn uses_u16_counter_for_16_bit_timers() {
+    let device = load_fixed("specs/svd/stm32f303.svd.patched");
+    let rendered = render_timer(&device, "TIM1");
+
+    assert!(rendered.contains("type Counter = u16;"));
+    assert!(rendered.contains("fn count(&self) -> u16"));
+  }
+
+  #[test]
+  fn splits_frequency_ticks_by_field_widths() {
+    let device = load_fixed("specs/svd/stm32f303.svd.patched");
+    let rendered = render_timer(&device, "TIM2");
+
+    assert!(rendered.contains("pub fn set_frequency(&mut self, hz: u32) -> f32"));
+    assert!(rendered.contains("ticks,\n      65535,\n      4294967295,\n"));
+  }
+
+  #[test]
+  fn rejects_values_wider_than_ranged_fields() {
+    let device = load_fixed("specs/svd/stm32f303.svd.patched");
+    let rendered = render_timer(&device, "TIM1");
+
+    assert!(rendered
+      .contains(r#"v if v > 65535 => Err(Error::new("Prescaler value must be at most 65535")),"#));
+    assert!(rendered.contains(
+      r#"v if v > 65535 => Err(Error::new("Auto-reload value must be at most 65535")),"#
+    ));
+  }
+
+  #[test]
+  fn reads_back_enum_fields() {
+    let device = load_fixed("specs/svd/stm32f303.svd.patched");
+    let rendered = render_timer(&device, "TIM2");
+
+    assert!(rendered.contains("pub fn get_compare_mode(&self) -> Ch1CompareMode {"));
+    assert!(rendered.contains("pub fn get_capture_filter(&self) -> Ch1CaptureFilter {"));
+    assert!(!rendered.contains("bits => Err(bits)"));
+  }
+
+  #[test]
+  fn configures_break_input_only_on_advanced_timers() {
+    let device = load_fixed("specs/svd/stm32f303.svd.patched");
+
+    let advanced = render_timer(&device, "TIM1");
+    assert!(advanced.contains(
+      "pub fn configure_break(&mut self, polarity: super::BreakPolarity, enabled: bool) -> Result<()>"
+    ));
+    assert!(advanced.contains("pub fn set_automatic_output_enable(&mut self, enabled: bool)"));
+
+    let general = render_timer(&device, "TIM2");
+    assert!(!general.contains("fn configure_break("));
+    assert!(!general.contains("fn set_automatic_output_enable("));
+  }
+
+  #[test]
+  fn sets_dead_time_in_nanoseconds_on_advanced_timers() {
+    let device = load_fixed("specs/svd/stm32f303.svd.patched");
+
+    let advanced = render_timer(&device, "TIM1");
+    let body = find_method(&advanced, "pub fn set_dead_time_ns(&mut self, ns: u32) -> Result<()>");
+    assert!(body.contains("let bits = dead_time_bits(cycles)?;"));
+    assert!(body.contains("/* Set tim1.bdtr.dtg = bits */"));
+    assert!(find_method(&advanced, "pub fn set_deadtime(").contains("dead_time_bits("));
+
+    let general = render_timer(&device, "TIM2");
+    assert!(!general.contains("fn set_dead_time_ns("));
+  }
+
+  #[test]
+  fn reads_count_from_counter_field() {
+    let device = load_fixed("specs/svd/stm32f303.svd.patched");
+    let rendered = render_timer(&device, "TIM2");
+
+    let count_fn = &rendered[rendered.find("fn count(&self)").unwrap()..];
+    let body = count_fn[..count_fn.find('}').unwrap()].to_lowercase();
+    assert!(body.contains("read_val("));
+    assert!(body.contains("/* Read tim2.cnt.cnt */"));
+  }
+
+  #[test]
+  fn emits_slave_mode_only_for_timers_with_smcr() {
+    let device = load_fixed("specs/svd/stm32f303.svd.patched");
+
+    let general = render_timer(&device, "TIM2");
+    assert!(general.contains("pub fn set_master_mode(&mut self, mode: super::MasterMode)"));
+    assert!(general.contains("pub fn set_slave_mode("));
+
+    let basic = render_timer(&device, "TIM6");
+    assert!(basic.contains("pub fn set_master_mode(&mut self, mode: super::MasterMode)"));
+    assert!(!basic.contains("pub fn set_slave_mode("));
+  }
+
+  #[test]
+  fn emits_async_methods_only_when_asked() {
+    let device = load_fixed("specs/svd/stm32f303.svd.patched");
+
+    let blocking = render_timer(&device, "TIM2");
+    assert!(blocking.contains("fn wait_for_update(&mut self) -> Result<()>"));
+    assert!(!blocking.contains("async fn"));
+    assert!(!blocking.contains("WakerSlot"));
+
+    let with_async = render_timer_with_async(&device, "TIM2", true);
+    assert!(with_async.contains("fn wait_for_update(&mut self) -> Result<()>"));
+    assert!(with_async.contains("pub async fn wait_for_update_async(&mut self)"));
+    assert!(with_async.contains("pub fn on_interrupt()"));
+    assert!(with_async.contains("static WAKER: WakerSlot = WakerSlot::new();"));
+
+    let wait_fn = find_method(&with_async, "pub async fn wait_for_update_async");
+    assert!(wait_fn.contains("/* Clear tim2.sr.uif by writing 0 */"));
+  }
+
+  #[test]
+  fn measures_period_from_fresh_captures() {
+    let device = load_fixed("specs/svd/stm32f303.svd.patched");
+    let rendered = render_timer(&device, "TIM2");
+
+    let body = find_method(&rendered, "pub fn measure_period(&mut self) -> Result<u32>");
+    let discard = body.find("self.read_capture();").unwrap();
+    let first_wait = body.find("/* Block until tim2.sr.cc1if is set */").unwrap();
+    assert!(discard < first_wait);
+    assert!(body.contains("/* Clear tim2.sr.cc1of by writing 0 */"));
+    assert!(body.contains(r#"Err(Error::new("An edge was missed while measuring the period."))"#));
+    assert!(body.contains("let max_loops = period_max_loops();"));
+    assert!(body.contains("max_loops) /* Block until tim2.sr.cc1if is set */"));
+  }
+
+  #[test]
+  fn bounds_update_wait_by_the_counter_period() {
+    let device = load_fixed("specs/svd/stm32f303.svd.patched");
+    let rendered = render_timer(&device, "TIM2");
+
+    let body = find_item(&rendered, "fn period_max_loops() -> u32");
+    assert!(body.contains("/* Read tim2.psc.psc */"));
+    assert!(body.contains("/* Read tim2.arr.arr */"));
+
+    let body = find_method(&rendered, "fn wait_for_update(&mut self) -> Result<()>");
+    assert!(body.contains("let max_loops = period_max_loops();"));
+    assert!(body.contains("wait_for_set(0x4000001"));
+    assert!(body.contains("max_loops) /* Block until tim2.sr.uif is set */"));
+  }
+
+  #[test]
+  fn constructs_token_without_reading_clocks() {
+    let device = load_fixed("specs/svd/stm32f303.svd.patched");
+    let rendered = render_timer(&device, "TIM2");
+
+    let body = find_method(
+      &rendered,
+      "pub const unsafe fn new(source_freq: f32) -> Self",
+    );
+    assert!(!body.contains("clocks"));
+
+    let create_body = find_method(&rendered, "pub(crate) fn create(clocks: &Clocks)");
+    assert!(create_body.contains("Self::new("));
+    assert!(create_body.contains(r#"Err(Error::new("Tim2's clock is stopped."))"#));
+  }
+
+  #[test]
+  fn derives_defmt_format_for_enums() {
+    let device = load_fixed("specs/svd/stm32f303.svd.patched");
+    let rendered = render_timer(&device, "TIM2");
+
+    assert!(rendered.contains(
+      "#[cfg_attr(feature = \"defmt\", derive(defmt::Format))]\npub enum Ch1CompareMode {"
+    ));
+  }
+
+  #[test]
+  fn generates_only_the_channels_with_enable_bits() {
+    let device = load_fixed("specs/svd/stm32f303.svd.patched");
+    let peripheral = device
+      .peripherals
+      .iter()
+      .find(|p| p.name == "TIM2")
+      .unwrap();
+    let timer = Timer::new(&device, peripheral).unwrap().unwrap();
+
+    assert_eq!(
+      vec![1, 2, 3, 4],
+      timer
+        .channels
+        .iter()
+        .map(|c| c.number)
+        .collect::<Vec<u32>>()
+    );
+    assert!(!render_timer(&device, "TIM2").contains("Ch5"));
+  }
+
+  #[test]
+  fn ties_channels_to_their_timer() {
+    let device = load_fixed("specs/svd/stm32f303.svd.patched");
+    let rendered = render_timer(&device, "TIM3");
+
+    for ty in &["Ch2", "Ch2Output", "Ch2Input"] {
+      assert!(rendered.contains(&format!(
+        "impl super::Channel for {} {{\n  type Timer = Tim3;\n  const NUMBER: u8 = 2;\n}}",
+        ty
+      )));
+    }
+  }
+
+  #[test]
+  fn loads_buffered_auto_reload_with_update_event() {
+    let device = load_fixed("specs/svd/stm32f303.svd.patched");
+    let sys_info = SystemInfo::new(&device).unwrap();
+    let rendered = ModTemplate {
+      api_path: "crate".to_owned(),
+      s: &sys_info,
+    }
+    .render()
+    .unwrap();
+
+    let immediate = find_method(&rendered, "fn set_auto_reload_immediate(");
+    assert!(immediate.contains("self.apply();"));
+
+    let buffered = find_method(&rendered, "fn set_auto_reload_buffered(");
+    assert!(buffered.contains("self.enable_auto_reload_preload();"));
+  }
+}
@@ -1,29 +1,81 @@
+use std::sync::atomic::{AtomicU8, Ordering};
+
 // RED   \x1b[0;31m
 // GREEN  \x1b[0;32m
 // YELLOW  \x1b[0;33m
 // CYAN  \x1b[0;36m
 // NC    \x1b[0m
 
+#[derive(Copy, Clone, PartialEq, PartialOrd)]
+pub enum LogLevel {
+  Quiet = 0,
+  Normal = 1,
+  Verbose = 2,
+}
+
+static LOG_LEVEL: AtomicU8 = AtomicU8::new(LogLevel::Normal as u8);
+
+pub fn set_level(level: LogLevel) {
+  LOG_LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+pub fn is_enabled(level: LogLevel) -> bool {
+  level as u8 <= LOG_LEVEL.load(Ordering::Relaxed)
+}
+
+pub fn color(code: &'static str, stream: atty::Stream) -> &'static str {
+  match atty::is(stream) {
+    true => code,
+    false => "",
+  }
+}
+
 macro_rules! info {
   ($($arg:tt)*) => ({
-    println!("\x1b[0;36m   [INFO]\x1b[0m {}", format!($($arg)*));
+    if $crate::logging::is_enabled($crate::logging::LogLevel::Verbose) {
+      eprintln!(
+        "{}   [INFO]{} {}",
+        $crate::logging::color("\x1b[0;36m", atty::Stream::Stderr),
+        $crate::logging::color("\x1b[0m", atty::Stream::Stderr),
+        format!($($arg)*)
+      );
+    }
   })
 }
 
 macro_rules! error {
   ($($arg:tt)*) => ({
-    println!("\x1b[0;31m  [ERROR]\x1b[0m {}", format!($($arg)*));
+    eprintln!(
+      "{}  [ERROR]{} {}",
+      $crate::logging::color("\x1b[0;31m", atty::Stream::Stderr),
+      $crate::logging::color("\x1b[0m", atty::Stream::Stderr),
+      format!($($arg)*)
+    );
   })
 }
 
 macro_rules! warn {
   ($($arg:tt)*) => ({
-    println!("\x1b[0;33m   [WARN]\x1b[0m {}", format!($($arg)*));
+    if $crate::logging::is_enabled($crate::logging::LogLevel::Normal) {
+      eprintln!(
+        "{}   [WARN]{} {}",
+        $crate::logging::color("\x1b[0;33m", atty::Stream::Stderr),
+        $crate::logging::color("\x1b[0m", atty::Stream::Stderr),
+        format!($($arg)*)
+      );
+    }
   })
 }
 
 macro_rules! success {
   ($($arg:tt)*) => ({
-    println!("\x1b[0;32m[SUCCESS]\x1b[0m {}", format!($($arg)*));
+    if $crate::logging::is_enabled($crate::logging::LogLevel::Normal) {
+      println!(
+        "{}[SUCCESS]{} {}",
+        $crate::logging::color("\x1b[0;32m", atty::Stream::Stdout),
+        $crate::logging::color("\x1b[0m", atty::Stream::Stdout),
+        format!($($arg)*)
+      );
+    }
   })
 }
@@ -3,24 +3,42 @@ extern crate fstrings;
 #[macro_use]
 mod logging;
 
+use std::collections::BTreeMap;
 use std::fs::File;
 use std::io::Read;
+use std::path::{Path, PathBuf};
 
 use anyhow::{bail, Result};
 use clap::{App, Arg};
 use glob::glob;
 
 use file::OutputDirectory;
+use fixup::Fixups;
+use generators::{clocks::ClockSpecLocation, DependencyVersions, GenerateOptions, WaitStrategy};
+use logging::LogLevel;
+use manifest::{GenerationManifest, ManifestEntry};
+use patch::DevicePatch;
 use svd_expander::DeviceSpec;
+use system::board::BoardSpecLocation;
 
+mod compare;
+mod error;
 mod file;
+mod fixup;
 mod generators;
+mod manifest;
+mod patch;
+mod summary;
 mod system;
+mod tidy;
 
 fn main() {
   match run() {
     Ok(()) => {}
-    Err(err) => error!("{:?}", err),
+    Err(err) => {
+      error!("{:?}", err);
+      std::process::exit(1);
+    }
   }
 }
 
@@ -32,7 +50,18 @@ fn run() -> Result<()> {
         .long("files")
         .help("Glob pattern matching SVD files to generate APIs for.")
         .takes_value(true)
-        .required(true),
+        .required_unless_one(&["compare", "check-clock-spec", "manifest"]),
+    )
+    .arg(
+      Arg::with_name("manifest")
+        .long("manifest")
+        .value_name("file.ron")
+        .help(
+          "RON file listing the SVD files to generate APIs for, each with its own crate name, \
+           peripherals, clock spec, and target.",
+        )
+        .takes_value(true)
+        .conflicts_with_all(&["files", "clock-spec", "board-spec"]),
     )
     .arg(
       Arg::with_name("out")
@@ -40,7 +69,13 @@ fn run() -> Result<()> {
         .long("out")
         .help("Output directory path.")
         .takes_value(true)
-        .required(true),
+        .required_unless_one(&[
+          "as-module",
+          "list",
+          "compare",
+          "check-clock-spec",
+          "check-only",
+        ]),
     )
     .arg(
       Arg::with_name("no-fix")
@@ -51,7 +86,10 @@ fn run() -> Result<()> {
     .arg(
       Arg::with_name("no-fmt")
         .long("no-fmt")
-        .help("Don't run `cargo fmt` on the output crate(s).")
+        .help(
+          "Don't run `cargo fmt` on the output crate(s). The generated files are re-indented \
+           by the generator instead.",
+        )
         .takes_value(false),
     )
     .arg(
@@ -78,6 +116,154 @@ fn run() -> Result<()> {
         .help("Build documentation for the crate(s).")
         .takes_value(false),
     )
+    .arg(
+      Arg::with_name("post-command")
+        .long("post-command")
+        .value_name("command")
+        .help(
+          "Shell command to run in each output crate after the built-in steps. Can be given \
+           more than once, the commands run in order.",
+        )
+        .takes_value(true)
+        .multiple(true)
+        .number_of_values(1),
+    )
+    .arg(
+      Arg::with_name("strict-reset")
+        .long("strict-reset")
+        .help(
+          "Fail instead of guessing when a field has no reset value in the SVD or in \
+           `specs/reset/<device>.ron`.",
+        )
+        .takes_value(false),
+    )
+    .arg(
+      Arg::with_name("validate-svd")
+        .long("validate-svd")
+        .help("Fail when the SVD places two peripherals at overlapping addresses.")
+        .takes_value(false),
+    )
+    .arg(
+      Arg::with_name("emit-svd-summary")
+        .long("emit-svd-summary")
+        .help(
+          "Write the expanded SVD, with every peripheral, register and field the generator \
+           sees, to `<crate>.svd.ron` in the output directory.",
+        )
+        .takes_value(false),
+    )
+    .arg(
+      Arg::with_name("named-registers")
+        .long("named-registers")
+        .help(
+          "Emit register addresses as named constants in a `regs` module and refer to them \
+           from the access code.",
+        )
+        .takes_value(false),
+    )
+    .arg(
+      Arg::with_name("wait-strategy")
+        .long("wait-strategy")
+        .help(
+          "What the generated wait_for_* helpers do between polls: `spin` busy-waits, `nop` \
+           issues a NOP and `wfe` sleeps until the next event. `wfe` needs an event source \
+           (e.g. SEVONPEND) or the wait can stall.",
+        )
+        .takes_value(true)
+        .possible_values(&["spin", "nop", "wfe"])
+        .default_value("spin"),
+    )
+    .arg(
+      Arg::with_name("show-warnings")
+        .long("show-warnings")
+        .help(
+          "Leave out the crate-level `#![allow(...)]` that hides dead code, unused import and \
+           naming warnings in the generated code.",
+        )
+        .takes_value(false),
+    )
+    .arg(Arg::with_name("async").long("async").help(
+      "Also generate async methods that wait on peripheral interrupts. The application's \
+       interrupt handlers must call each peripheral's on_interrupt function.",
+    ))
+    .arg(
+      Arg::with_name("gpio-low-power")
+        .long("gpio-low-power")
+        .help(
+          "Generate `System::init_gpio_low_power`, which puts every GPIO pin in analog mode \
+           with no pull resistor.",
+        )
+        .takes_value(false),
+    )
+    .arg(
+      Arg::with_name("emit-skeleton")
+        .long("emit-skeleton")
+        .help(
+          "Also write a src/main.rs to the includes directory: a `#[entry]` that sets up the \
+           clocks and takes the peripherals, then loops.",
+        )
+        .takes_value(false),
+    )
+    .arg(
+      Arg::with_name("patch")
+        .long("patch")
+        .value_name("file.ron")
+        .help(
+          "RON file of fixes to apply to each SVD before generating. Can be given more than \
+           once, the patches are applied in order.",
+        )
+        .takes_value(true)
+        .multiple(true)
+        .number_of_values(1),
+    )
+    .arg(
+      Arg::with_name("clock-spec")
+        .long("clock-spec")
+        .value_name("path")
+        .help("Clock schematic to use instead of looking one up by device name.")
+        .takes_value(true),
+    )
+    .arg(
+      Arg::with_name("clock-spec-dir")
+        .long("clock-spec-dir")
+        .value_name("dir")
+        .help(
+          "Directory to search for `<device>.ron` clock schematics before `specs/clock`. Can be \
+           given more than once, the directories are searched in order.",
+        )
+        .takes_value(true)
+        .multiple(true)
+        .number_of_values(1),
+    )
+    .arg(
+      Arg::with_name("board-spec")
+        .long("board-spec")
+        .value_name("path")
+        .help("Board pins to use instead of looking them up by device name.")
+        .takes_value(true),
+    )
+    .arg(
+      Arg::with_name("board-spec-dir")
+        .long("board-spec-dir")
+        .value_name("dir")
+        .help(
+          "Directory to search for `<device>.ron` board pins before `specs/board`. Can be given \
+           more than once, the directories are searched in order.",
+        )
+        .takes_value(true)
+        .multiple(true)
+        .number_of_values(1),
+    )
+    .arg(
+      Arg::with_name("check-only")
+        .long("check-only")
+        .help(
+          "Check each SVD and its clock spec by rendering everything generating would, \
+           without writing anything. Fails if any device has problems.",
+        )
+        .takes_value(false)
+        .conflicts_with_all(&["out", "as-module", "list", "workspace", "dry-run"]),
+    )
     .arg(
       Arg::with_name("dry-run")
         .long("dry-run")
@@ -85,32 +271,274 @@ fn run() -> Result<()> {
         .takes_value(false),
     )
     .arg(
-      Arg::with_name("as-source")
-        .long("as-source")
-        .help("Put the files in an existing crate instead of making a new crate.")
+      Arg::with_name("quiet")
+        .short("q")
+        .long("quiet")
+        .help("Only print errors.")
+        .takes_value(false)
+        .conflicts_with("verbose"),
+    )
+    .arg(
+      Arg::with_name("verbose")
+        .short("v")
+        .long("verbose")
+        .help("Print informational messages as well as warnings and errors.")
         .takes_value(false),
     )
+    .arg(
+      Arg::with_name("as-module")
+        .long("as-module")
+        .value_name("crate-src-dir")
+        .help(
+          "Put the files in a module inside an existing crate's `src` directory instead of making \
+           a new crate. Include it with `mod <device>_api;` in the crate root.",
+        )
+        .takes_value(true)
+        .conflicts_with("out"),
+    )
+    .arg(
+      Arg::with_name("workspace")
+        .long("workspace")
+        .help(
+          "Write a Cargo.toml in the output directory that makes the generated crates members of \
+           one workspace.",
+        )
+        .takes_value(false)
+        .conflicts_with("as-module"),
+    )
+    .arg(
+      Arg::with_name("list")
+        .long("list")
+        .help(
+          "Print the peripherals and clock outputs found in the SVD file(s) without generating \
+           anything.",
+        )
+        .takes_value(false)
+        .conflicts_with_all(&["out", "as-module"]),
+    )
+    .arg(
+      Arg::with_name("compare")
+        .long("compare")
+        .value_names(&["old.svd", "new.svd"])
+        .help(
+          "Print the peripherals, registers, and fields that differ between two SVD files \
+           without generating anything.",
+        )
+        .takes_value(true)
+        .number_of_values(2)
+        .conflicts_with_all(&["files", "out", "as-module", "list"]),
+    )
+    .arg(
+      Arg::with_name("check-clock-spec")
+        .long("check-clock-spec")
+        .value_name("path")
+        .help(
+          "Check clock spec file(s) for problems that don't depend on an SVD, like loops, \
+           duplicate names, and bad defaults, without generating anything.",
+        )
+        .takes_value(true)
+        .multiple(true)
+        .number_of_values(1)
+        .conflicts_with_all(&["files", "out", "as-module", "list", "compare"]),
+    )
+    .arg(
+      Arg::with_name("cortex-m-version")
+        .long("cortex-m-version")
+        .value_name("version")
+        .help("Version of `cortex-m` the generated crate depends on.")
+        .takes_value(true),
+    )
+    .arg(
+      Arg::with_name("cortex-m-rt-version")
+        .long("cortex-m-rt-version")
+        .value_name("version")
+        .help(
+          "Version of `cortex-m-rt` for the generated crate's optional SysTick support and the \
+           example Cargo.toml in the includes directory.",
+        )
+        .takes_value(true),
+    )
+    .arg(
+      Arg::with_name("embedded-hal-version")
+        .long("embedded-hal-version")
+        .value_name("version")
+        .help("Add `embedded-hal` as an optional dependency of the generated crate.")
+        .takes_value(true),
+    )
+    .arg(
+      Arg::with_name("defmt-version")
+        .long("defmt-version")
+        .value_name("version")
+        .help("Add `defmt` as an optional dependency of the generated crate.")
+        .takes_value(true),
+    )
     .get_matches();
 
-  let out_dir = OutputDirectory::new(match matches.value_of("out") {
-    Some(od) => od,
-    None => bail!("No output directory was provided."),
-  })?;
+  logging::set_level(match (matches.is_present("quiet"), matches.is_present("verbose")) {
+    (true, _) => LogLevel::Quiet,
+    (false, true) => LogLevel::Verbose,
+    (false, false) => LogLevel::Normal,
+  });
+
+  if let Some(paths) = matches.values_of("check-clock-spec") {
+    let mut problem_count = 0;
+    for path in paths {
+      let problems = generators::clocks::check_spec(Path::new(path))?;
+      for problem in problems.iter() {
+        error!("{}: {}", path, problem);
+      }
+      problem_count += problems.len();
+    }
+
+    if problem_count > 0 {
+      bail!("Found {} problem(s) in the clock spec(s).", problem_count);
+    }
+    success!("No problems found.");
+
+    return Ok(());
+  }
+
+  if let Some(mut paths) = matches.values_of("compare") {
+    let (old_path, new_path) = match (paths.next(), paths.next()) {
+      (Some(o), Some(n)) => (o, n),
+      _ => bail!("--compare needs an old and a new SVD file."),
+    };
+
+    let old_spec = DeviceSpec::from_file(old_path)?;
+    let new_spec = DeviceSpec::from_file(new_path)?;
+    let diffs = compare::differences(&old_spec, &new_spec);
+
+    println!("{} -> {}", old_path, new_path);
+    for diff in diffs.iter() {
+      println!("  {}", diff);
+    }
+    success!("Found {} difference(s).", diffs.len());
+
+    return Ok(());
+  }
+
+  let list = matches.is_present("list");
+  let run_format = !matches.is_present("no-fmt");
+  let output = match (matches.value_of("out"), matches.value_of("as-module")) {
+    (_, Some(src_dir)) => Some((
+      OutputDirectory::new(src_dir)?.with_tidy_rust(!run_format),
+      true,
+    )),
+    (Some(od), None) => Some((OutputDirectory::new(od)?.with_tidy_rust(!run_format), false)),
+    (None, None) => None,
+  };
 
   let file_glob = matches.value_of("files").unwrap_or("./*");
 
+  let default_deps = DependencyVersions::default();
+  let deps = DependencyVersions {
+    cortex_m: matches
+      .value_of("cortex-m-version")
+      .map_or(default_deps.cortex_m, |v| v.to_owned()),
+    cortex_m_rt: matches
+      .value_of("cortex-m-rt-version")
+      .map_or(default_deps.cortex_m_rt, |v| v.to_owned()),
+    embedded_hal: matches
+      .value_of("embedded-hal-version")
+      .map(|v| v.to_owned()),
+    defmt: matches.value_of("defmt-version").map(|v| v.to_owned()),
+  };
+
   let run_fix = !matches.is_present("no-fix");
-  let run_format = !matches.is_present("no-fmt");
   let run_check = !matches.is_present("no-check");
   let build_release = matches.is_present("build-release");
   let build_debug = matches.is_present("build-debug");
   let build_docs = matches.is_present("build-docs");
   let dry_run = matches.is_present("dry-run");
-  let as_source = matches.is_present("as-source");
+  let post_commands = matches
+    .values_of("post-command")
+    .map_or(vec![], |v| v.map(|c| c.to_owned()).collect::<Vec<String>>());
+
+  let default_clock_specs = ClockSpecLocation::default();
+  let clock_specs = ClockSpecLocation {
+    path: matches.value_of("clock-spec").map(PathBuf::from),
+    search_dirs: matches
+      .values_of("clock-spec-dir")
+      .map_or(vec![], |v| v.map(PathBuf::from).collect::<Vec<PathBuf>>())
+      .into_iter()
+      .chain(default_clock_specs.search_dirs)
+      .collect(),
+  };
+
+  let default_board_specs = BoardSpecLocation::default();
+  let board_specs = BoardSpecLocation {
+    path: matches.value_of("board-spec").map(PathBuf::from),
+    search_dirs: matches
+      .values_of("board-spec-dir")
+      .map_or(vec![], |v| v.map(PathBuf::from).collect::<Vec<PathBuf>>())
+      .into_iter()
+      .chain(default_board_specs.search_dirs)
+      .collect(),
+  };
+
+  let mut options = GenerateOptions {
+    as_source: output.as_ref().map_or(false, |(_, as_source)| *as_source),
+    deps,
+    strict_reset: matches.is_present("strict-reset"),
+    validate_svd: matches.is_present("validate-svd"),
+    named_registers: matches.is_present("named-registers"),
+    clock_specs,
+    board_specs,
+    wait_strategy: WaitStrategy::from_name(matches.value_of("wait-strategy").unwrap_or("spin"))?,
+    async_api: matches.is_present("async"),
+    allow_warnings: !matches.is_present("show-warnings"),
+    gpio_low_power: matches.is_present("gpio-low-power"),
+    emit_skeleton: matches.is_present("emit-skeleton"),
+    ..GenerateOptions::default()
+  };
+
+  let patches = match matches.values_of("patch") {
+    Some(paths) => paths
+      .map(DevicePatch::from_ron_file)
+      .collect::<Result<Vec<DevicePatch>>>()?,
+    None => vec![],
+  };
+
+  let post_process = |base_dir: &OutputDirectory, module_root: Option<&str>| -> Result<()> {
+    Ok(file::post_process(
+      dry_run,
+      &base_dir.get_path()?,
+      module_root,
+      run_fix,
+      run_format,
+      run_check,
+      build_release,
+      build_debug,
+      build_docs,
+      &post_commands,
+    )?)
+  };
+
+  let emit_svd_summary = matches.is_present("emit-svd-summary");
+  let check_only = matches.is_present("check-only");
+  let mut failed_checks = 0;
+  let fixups = Fixups::builtin();
+  let workspace = matches.is_present("workspace");
+  let mut generated: Vec<(String, String, OutputDirectory)> = vec![];
+
+  let manifest = match matches.value_of("manifest") {
+    Some(path) => {
+      let manifest = GenerationManifest::from_ron_file(path)?;
+      manifest.check()?;
+      Some(manifest)
+    }
+    None => None,
+  };
+  let entries: Vec<(PathBuf, Option<&ManifestEntry>)> = match &manifest {
+    Some(m) => m.devices.iter().map(|d| (d.svd.clone(), Some(d))).collect(),
+    None => glob(file_glob)?
+      .map(|entry| Ok((entry?, None)))
+      .collect::<Result<Vec<(PathBuf, Option<&ManifestEntry>)>>>()?,
+  };
+  let mut crate_names: BTreeMap<String, PathBuf> = BTreeMap::new();
 
   let mut found_file = false;
-  for entry in glob(file_glob)? {
-    let entry = entry?;
+  for (entry, manifest_entry) in entries {
     if !entry.is_dir() {
       found_file = true;
 
@@ -121,25 +549,82 @@ fn run() -> Result<()> {
 
       info!("Loading {}", &path_str);
 
+      if let Some(manifest_entry) = manifest_entry {
+        options.crate_name = manifest_entry.crate_name.clone();
+        options.families = manifest_entry.peripherals.clone();
+        options.target = manifest_entry.target.clone();
+        options.clock_specs.path = manifest_entry.clock_spec.clone();
+      }
+
       // Load and parse the SVD file
       let xml = &mut String::new();
-      File::open(path_str).unwrap().read_to_string(xml)?;
-      let spec = DeviceSpec::from_xml(xml)?;
+      File::open(path_str)?.read_to_string(xml)?;
+      let mut spec = fixups.apply(generators::parse_device(xml)?)?;
+      for patch in patches.iter() {
+        patch.apply(&mut spec)?;
+      }
+
+      let crate_name = options.crate_name_for(&spec);
+      if manifest.is_some() {
+        if let Some(other) = crate_names.insert(crate_name.clone(), entry.clone()) {
+          bail!(
+            "{} and {} would both generate crate '{}'",
+            other.display(),
+            entry.display(),
+            crate_name
+          );
+        }
+      }
       //let crate_out_dir = out_dir.new_in_subdir(&format!("{}-api", spec.name.to_kebab_case()))?;
 
-      let base_dir = generators::generate(dry_run, &spec, &out_dir, as_source)?;
+      if list {
+        generators::list(&spec, &options.clock_specs)?;
+        continue;
+      }
 
-      file::post_process(
-        dry_run,
-        &base_dir.get_path()?,
-        run_fix,
-        run_format,
-        run_check,
-        build_release,
-        build_debug,
-        build_docs,
-      )?;
+      if check_only {
+        match generators::check(&spec, &options) {
+          Ok(()) => success!("No problems found in {}", path_str),
+          Err(err) => {
+            error!("{}: {:?}", path_str, err);
+            failed_checks += 1;
+          }
+        }
+        continue;
+      }
 
+      let out_dir = match &output {
+        Some((od, _)) => od,
+        None => bail!("No output directory was provided."),
+      };
+
+      // Written first, since it's most useful when generation fails.
+      if emit_svd_summary {
+        out_dir.publish(
+          dry_run,
+          &format!("{}.svd.ron", crate_name),
+          &summary::to_ron(&spec)?,
+        )?;
+      }
+
+      let base_dir = generators::generate(dry_run, &spec, out_dir, &options)?;
+
+      // Cargo won't build a crate under a workspace that doesn't list it, so
+      // hold off until the manifest is written.
+      if workspace {
+        generated.push((spec.name.clone(), crate_name, base_dir));
+        continue;
+      }
+
+      let module_root = match options.as_source {
+        true => Some(format!(
+          "{}/{}/mod.rs",
+          out_dir.get_path()?,
+          generators::module_name(&spec)
+        )),
+        false => None,
+      };
+      post_process(&base_dir, module_root.as_deref())?;
       success!("Generated crate for device {}", spec.name);
     }
   }
@@ -148,7 +633,30 @@ fn run() -> Result<()> {
     error!("No files found");
   }
 
-  success!("All crates generated successfully.");
+  if failed_checks > 0 {
+    bail!("Found problems in {} device(s).", failed_checks);
+  }
+
+  if workspace && !generated.is_empty() {
+    let out_dir = match &output {
+      Some((od, _)) => od,
+      None => bail!("No output directory was provided."),
+    };
+    let crate_names = generated
+      .iter()
+      .map(|(_, crate_name, _)| crate_name.clone())
+      .collect::<Vec<String>>();
+    generators::generate_workspace(dry_run, out_dir, &crate_names)?;
+
+    for (device_name, _, base_dir) in generated.iter() {
+      post_process(base_dir, None)?;
+      success!("Generated crate for device {}", device_name);
+    }
+  }
+
+  if !list && !check_only {
+    success!("All crates generated successfully.");
+  }
 
   Ok(())
 }
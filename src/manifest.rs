@@ -0,0 +1,152 @@
+use std::{
+  collections::BTreeMap,
+  fs,
+  path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Result};
+use serde::Deserialize;
+
+use crate::system::FAMILIES;
+
+// The devices to generate in one run, each with the settings that would
+// otherwise take a separate invocation. Paths are relative to the manifest.
+#[derive(Deserialize, Debug, Default)]
+pub struct GenerationManifest {
+  #[serde(default)]
+  pub devices: Vec<ManifestEntry>,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct ManifestEntry {
+  pub svd: PathBuf,
+  #[serde(default)]
+  pub crate_name: Option<String>,
+  // Peripheral families to generate, by module name. All of them when unset.
+  #[serde(default)]
+  pub peripherals: Option<Vec<String>>,
+  #[serde(default)]
+  pub clock_spec: Option<PathBuf>,
+  #[serde(default)]
+  pub target: Option<String>,
+}
+
+impl GenerationManifest {
+  pub fn from_ron_file<P: AsRef<Path>>(path: P) -> Result<GenerationManifest> {
+    info!(
+      "Parsing generation manifest from file '{}'",
+      match path.as_ref().to_str() {
+        Some(s) => s,
+        None => "(could not create string from path)",
+      }
+    );
+    let mut manifest: GenerationManifest = ron::from_str(&fs::read_to_string(&path)?)?;
+
+    if let Some(dir) = path.as_ref().parent() {
+      for device in manifest.devices.iter_mut() {
+        device.svd = dir.join(&device.svd);
+        device.clock_spec = device.clock_spec.as_ref().map(|p| dir.join(p));
+      }
+    }
+
+    Ok(manifest)
+  }
+
+  #[cfg(test)]
+  pub fn from_ron<S: Into<String>>(ron: S) -> Result<GenerationManifest> {
+    Ok(ron::from_str(&ron.into())?)
+  }
+
+  // Crate names that come from the device name aren't known until the SVDs
+  // are parsed, so those are checked as the devices are generated.
+  pub fn check(&self) -> Result<()> {
+    if self.devices.is_empty() {
+      bail!("The generation manifest doesn't list any devices");
+    }
+
+    let mut crate_names: BTreeMap<&str, &Path> = BTreeMap::new();
+    for device in self.devices.iter() {
+      if let Some(crate_name) = &device.crate_name {
+        let mut chars = crate_name.chars();
+        let is_crate_name = chars.next().map_or(false, |c| c.is_ascii_alphabetic())
+          && chars.all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !is_crate_name {
+          bail!(
+            "'{}' for {} isn't a valid crate name",
+            crate_name,
+            device.svd.display()
+          );
+        }
+
+        if let Some(other) = crate_names.insert(crate_name, &device.svd) {
+          bail!(
+            "{} and {} would both generate crate '{}'",
+            other.display(),
+            device.svd.display(),
+            crate_name
+          );
+        }
+      }
+
+      for family in device.peripherals.iter().flatten() {
+        if !FAMILIES.contains(&family.as_str()) {
+          bail!(
+            "Unknown peripheral family '{}' for {}, expected one of: {}",
+            family,
+            device.svd.display(),
+            FAMILIES.join(", ")
+          );
+        }
+      }
+    }
+
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn rejects_duplicate_crate_names() {
+    let manifest = GenerationManifest::from_ron(
+      r#"GenerationManifest(
+        devices: [
+          (svd: "svd/stm32f303.svd.patched", crate_name: Some("board-api"), target: Some("thumbv7em-none-eabi")),
+          (svd: "svd/stm32f103.svd.patched", peripherals: Some(["gpio", "timer"])),
+        ],
+      )"#,
+    )
+    .unwrap();
+    assert!(manifest.check().is_ok());
+    assert_eq!(
+      Some("thumbv7em-none-eabi".to_owned()),
+      manifest.devices[0].target
+    );
+
+    let manifest = GenerationManifest::from_ron(
+      r#"GenerationManifest(
+        devices: [
+          (svd: "svd/stm32f303.svd.patched", crate_name: Some("board-api")),
+          (svd: "svd/stm32f103.svd.patched", crate_name: Some("board-api")),
+        ],
+      )"#,
+    )
+    .unwrap();
+    let err = manifest.check().unwrap_err().to_string();
+    assert!(err.contains("would both generate crate 'board-api'"));
+  }
+
+  #[test]
+  fn rejects_unknown_families() {
+    let manifest = GenerationManifest::from_ron(
+      r#"GenerationManifest(
+        devices: [(svd: "svd/stm32f303.svd.patched", peripherals: Some(["gpio", "uart"]))],
+      )"#,
+    )
+    .unwrap();
+    let err = manifest.check().unwrap_err().to_string();
+    assert!(err.contains("Unknown peripheral family 'uart'"));
+  }
+}
@@ -0,0 +1,357 @@
+use std::{collections::BTreeMap, fs, path::Path};
+
+use anyhow::{anyhow, bail, Result};
+use serde::Deserialize;
+use svd_expander::{DeviceSpec, FieldSpec, PeripheralSpec, RegisterSpec};
+
+// Fixes for a vendor SVD, applied to the parsed device before anything is
+// generated from it. Only registers directly in a peripheral can be patched,
+// not ones in clusters.
+#[derive(Deserialize, Debug, Default)]
+pub struct DevicePatch {
+  #[serde(default)]
+  peripherals: BTreeMap<String, PeripheralPatch>,
+}
+impl DevicePatch {
+  pub fn from_ron_file<P: AsRef<Path>>(path: P) -> Result<DevicePatch> {
+    info!(
+      "Parsing device patch from file '{}'",
+      match path.as_ref().to_str() {
+        Some(s) => s,
+        None => "(could not create string from path)",
+      }
+    );
+    Ok(ron::from_str(&fs::read_to_string(path)?)?)
+  }
+
+  #[cfg(test)]
+  pub fn from_ron<S: Into<String>>(ron: S) -> Result<DevicePatch> {
+    Ok(ron::from_str(&ron.into())?)
+  }
+
+  pub fn apply(&self, device: &mut DeviceSpec) -> Result<()> {
+    for (peripheral_name, peripheral_patch) in self.peripherals.iter() {
+      let peripheral = find_peripheral(device, peripheral_name)?;
+      for (register_name, register_patch) in peripheral_patch.registers.iter() {
+        register_patch.apply(find_register(peripheral, register_name)?)?;
+      }
+    }
+
+    Ok(())
+  }
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct PeripheralPatch {
+  #[serde(default)]
+  registers: BTreeMap<String, RegisterPatch>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct RegisterPatch {
+  #[serde(default)]
+  rename: Option<String>,
+  #[serde(default)]
+  fields: BTreeMap<String, FieldPatch>,
+}
+impl RegisterPatch {
+  fn apply(&self, register: &mut RegisterSpec) -> Result<()> {
+    for (field_name, field_patch) in self.fields.iter() {
+      let existing = register
+        .fields
+        .iter()
+        .position(|f| f.name.eq_ignore_ascii_case(field_name));
+
+      match (field_patch, existing) {
+        (FieldPatch::Modify(values), Some(i)) => values.apply(&mut register.fields[i]),
+        (FieldPatch::Delete, Some(i)) => {
+          register.fields.remove(i);
+        }
+        (FieldPatch::Add(new_field), None) => {
+          // Start from a sibling so the new field has the register's path and
+          // address, then replace everything that describes the field itself.
+          let mut field = match register.fields.first() {
+            Some(f) => f.clone(),
+            None => bail!(
+              "Can't add field {} to register {}, it has no fields to base it on",
+              field_name,
+              register.name
+            ),
+          };
+          field.name = field_name.clone();
+          field.description = None;
+          field.enumerated_value_sets = vec![];
+          field.reset_value = None;
+          field.reset_mask = None;
+          field.access = register.access.clone();
+          field.modified_write_values = register.modified_write_values.clone();
+          field.read_action = register.read_action.clone();
+          new_field.values().apply(&mut field);
+          register.fields.push(field);
+        }
+        (FieldPatch::Add(_), Some(_)) => bail!(
+          "Can't add field {} to register {}, it already exists",
+          field_name,
+          register.name
+        ),
+        (_, None) => bail!(
+          "Can't patch field {} of register {}, it doesn't exist",
+          field_name,
+          register.name
+        ),
+      }
+    }
+
+    if let Some(ref name) = self.rename {
+      register.name = name.clone();
+      let register_path = register.path();
+      for field in register.fields.iter_mut() {
+        field.preceding_path = register_path.clone();
+      }
+    }
+
+    Ok(())
+  }
+}
+
+#[derive(Deserialize, Debug)]
+enum FieldPatch {
+  Add(NewField),
+  Modify(FieldValues),
+  Delete,
+}
+
+// There's no field to take the bits of a new one from, so they're required.
+#[derive(Deserialize, Debug)]
+struct NewField {
+  #[serde(default)]
+  description: Option<String>,
+  offset: u32,
+  width: u32,
+  #[serde(default)]
+  reset_value: Option<u32>,
+}
+impl NewField {
+  fn values(&self) -> FieldValues {
+    FieldValues {
+      description: self.description.clone(),
+      offset: Some(self.offset),
+      width: Some(self.width),
+      reset_value: self.reset_value,
+    }
+  }
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct FieldValues {
+  #[serde(default)]
+  description: Option<String>,
+  #[serde(default)]
+  offset: Option<u32>,
+  #[serde(default)]
+  width: Option<u32>,
+  #[serde(default)]
+  reset_value: Option<u32>,
+}
+impl FieldValues {
+  fn apply(&self, field: &mut FieldSpec) {
+    if let Some(ref description) = self.description {
+      field.description = Some(description.clone());
+    }
+    if let Some(offset) = self.offset {
+      field.offset = offset;
+    }
+    if let Some(width) = self.width {
+      field.width = width;
+    }
+    if let Some(reset_value) = self.reset_value {
+      field.reset_value = Some(reset_value);
+    }
+    // The mask covers the field's bits, so it moves with them.
+    let moved = self.offset.is_some() || self.width.is_some();
+    if self.reset_value.is_some() || (moved && field.reset_mask.is_some()) {
+      field.reset_mask = Some(field.mask());
+    }
+  }
+}
+
+fn find_peripheral<'a>(device: &'a mut DeviceSpec, name: &str) -> Result<&'a mut PeripheralSpec> {
+  let device_name = device.name.clone();
+  device
+    .peripherals
+    .iter_mut()
+    .find(|p| p.name.eq_ignore_ascii_case(name))
+    .ok_or_else(|| {
+      anyhow!(
+        "Can't patch peripheral {}, it isn't in device {}",
+        name,
+        device_name
+      )
+    })
+}
+
+fn find_register<'a>(
+  peripheral: &'a mut PeripheralSpec,
+  name: &str,
+) -> Result<&'a mut RegisterSpec> {
+  let peripheral_name = peripheral.name.clone();
+  peripheral
+    .registers
+    .iter_mut()
+    .find(|r| r.name.eq_ignore_ascii_case(name))
+    .ok_or_else(|| {
+      anyhow!(
+        "Can't patch register {}, it isn't in peripheral {}",
+        name,
+        peripheral_name
+      )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn patched(ron: &str) -> Result<DeviceSpec> {
+    let mut device = DeviceSpec::from_file("specs/svd/arm_device.svd").unwrap();
+    DevicePatch::from_ron(ron)?.apply(&mut device)?;
+    Ok(device)
+  }
+
+  #[test]
+  fn modifies_adds_and_deletes_fields() {
+    let device = patched(
+      r#"
+      DevicePatch(
+        peripherals: {
+          "TIMER0": (
+            registers: {
+              "CR": (
+                fields: {
+                  "RST": Delete,
+                  "EN": Modify((reset_value: Some(1))),
+                  "SPARE": Add((offset: 30, width: 2)),
+                },
+              ),
+            },
+          ),
+        },
+      )
+      "#,
+    )
+    .unwrap();
+
+    assert!(device.try_get_field("timer0.cr.rst").is_none());
+    assert_eq!(
+      Some(1),
+      device.get_field("timer0.cr.en").unwrap().reset_value
+    );
+
+    let spare = device.get_field("timer0.cr.spare").unwrap();
+    assert_eq!(0xC000_0000, spare.mask());
+    assert_eq!(0x4001_0000, spare.address());
+  }
+
+  #[test]
+  fn requires_bits_for_added_fields() {
+    let err = DevicePatch::from_ron(
+      r#"
+      DevicePatch(
+        peripherals: {
+          "TIMER0": (
+            registers: {
+              "CR": (fields: { "SPARE": Add((width: 2)) }),
+            },
+          ),
+        },
+      )
+      "#,
+    )
+    .unwrap_err();
+    assert!(err.to_string().contains("offset"));
+  }
+
+  #[test]
+  fn moves_reset_mask_with_field() {
+    let mut device = patched(
+      r#"
+      DevicePatch(
+        peripherals: {
+          "TIMER0": (
+            registers: {
+              "CR": (fields: { "EN": Modify((reset_value: Some(1))) }),
+            },
+          ),
+        },
+      )
+      "#,
+    )
+    .unwrap();
+    DevicePatch::from_ron(
+      r#"
+      DevicePatch(
+        peripherals: {
+          "TIMER0": (
+            registers: {
+              "CR": (fields: { "EN": Modify((offset: Some(31))) }),
+            },
+          ),
+        },
+      )
+      "#,
+    )
+    .unwrap()
+    .apply(&mut device)
+    .unwrap();
+
+    let en = device.get_field("timer0.cr.en").unwrap();
+    assert_eq!(Some(0x8000_0000), en.reset_mask);
+  }
+
+  #[test]
+  fn renames_registers() {
+    let device = patched(
+      r#"
+      DevicePatch(
+        peripherals: {
+          "TIMER0": (
+            registers: {
+              "SR": (rename: Some("STATUS")),
+            },
+          ),
+        },
+      )
+      "#,
+    )
+    .unwrap();
+
+    assert!(device.try_get_field("timer0.sr.run").is_none());
+    assert_eq!(
+      "timer0.status.run",
+      device
+        .get_field("timer0.status.run")
+        .unwrap()
+        .path()
+        .to_lowercase()
+    );
+  }
+
+  #[test]
+  fn rejects_patches_for_missing_fields() {
+    let result = patched(
+      r#"
+      DevicePatch(
+        peripherals: {
+          "TIMER0": (
+            registers: {
+              "CR": (fields: { "BOGUS": Delete }),
+            },
+          ),
+        },
+      )
+      "#,
+    );
+
+    assert!(result.is_err());
+  }
+}
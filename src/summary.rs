@@ -0,0 +1,109 @@
+use anyhow::Result;
+use ron::ser::PrettyConfig;
+use serde::Serialize;
+use svd_expander::{DeviceSpec, FieldSpec, PeripheralSpec, RegisterSpec};
+
+// The device as the generator sees it, after `derivedFrom` and `dim` arrays are
+// expanded and the fixups and patches are applied. Addresses and masks are hex
+// strings so they can be compared against the reference manual.
+#[derive(Serialize)]
+struct DeviceSummary {
+  name: String,
+  peripherals: Vec<PeripheralSummary>,
+}
+
+#[derive(Serialize)]
+struct PeripheralSummary {
+  name: String,
+  base_address: String,
+  registers: Vec<RegisterSummary>,
+}
+impl PeripheralSummary {
+  fn new(peripheral: &PeripheralSpec) -> Self {
+    Self {
+      name: peripheral.name.clone(),
+      base_address: format!("{:#010x}", peripheral.base_address),
+      registers: peripheral
+        .iter_registers()
+        .map(RegisterSummary::new)
+        .collect(),
+    }
+  }
+}
+
+#[derive(Serialize)]
+struct RegisterSummary {
+  path: String,
+  address: String,
+  size: u32,
+  fields: Vec<FieldSummary>,
+}
+impl RegisterSummary {
+  fn new(register: &RegisterSpec) -> Self {
+    Self {
+      path: register.path(),
+      address: format!("{:#010x}", register.address()),
+      size: register.size.unwrap_or(32),
+      fields: register.fields.iter().map(FieldSummary::new).collect(),
+    }
+  }
+}
+
+#[derive(Serialize)]
+struct FieldSummary {
+  name: String,
+  offset: u32,
+  width: u32,
+  mask: String,
+  reset_value: Option<u32>,
+  values: Vec<String>,
+}
+impl FieldSummary {
+  fn new(field: &FieldSpec) -> Self {
+    Self {
+      name: field.name.clone(),
+      offset: field.offset,
+      width: field.width,
+      mask: format!("{:#010x}", field.mask()),
+      reset_value: field.reset_value,
+      values: field
+        .enumerated_value_sets
+        .iter()
+        .flat_map(|vs| vs.values.iter())
+        .map(|v| v.name.clone())
+        .collect(),
+    }
+  }
+}
+
+pub fn to_ron(device: &DeviceSpec) -> Result<String> {
+  let summary = DeviceSummary {
+    name: device.name.clone(),
+    peripherals: device
+      .peripherals
+      .iter()
+      .map(PeripheralSummary::new)
+      .collect(),
+  };
+  Ok(ron::ser::to_string_pretty(
+    &summary,
+    PrettyConfig::default(),
+  )?)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::fixup::load_fixed;
+
+  #[test]
+  fn lists_expanded_fields_under_registers() {
+    let summary = to_ron(&load_fixed("specs/svd/stm32f303.svd.patched")).unwrap();
+
+    let moder = &summary[summary.find(r#"path: "GPIOA.MODER""#).unwrap()..];
+    let moder = &moder[..moder[1..].find("path: ").map_or(moder.len(), |i| i + 1)];
+    assert!(moder.contains(r#"address: "0x48000000""#));
+    assert!(moder.contains(r#"name: "MODER13""#));
+    assert!(moder.contains(r#"mask: "0x0c000000""#));
+  }
+}
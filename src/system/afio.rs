@@ -0,0 +1,154 @@
+use anyhow::{bail, Result};
+use svd_expander::{DeviceSpec, PeripheralSpec};
+
+use super::*;
+
+// The F1 family routes alternate functions with the remap fields in AFIO's
+// MAPR registers instead of per-pin AFR registers.
+#[derive(Clone)]
+pub struct Afio {
+  pub name: Name,
+  pub peripheral_enable_field: String,
+  pub remaps: Vec<Remap>,
+  pub swj_cfg_field: Option<String>,
+  pub exti_lines: Vec<ExtiLine>,
+}
+impl Afio {
+  pub fn new(device: &DeviceSpec, peripheral: &PeripheralSpec) -> Result<Option<Self>> {
+    let rcc = match device
+      .peripherals
+      .iter()
+      .find(|p| p.name.to_lowercase() == "rcc")
+    {
+      Some(p) => p,
+      None => bail!("Could not find RCC peripheral"),
+    };
+
+    let peripheral_enable_field = match find_field_in_peripheral(rcc, "afioen") {
+      Some(f) => f.path().to_lowercase(),
+      None => {
+        warn!("Skipping AFIO because RCC has no AFIOEN field.");
+        return Ok(None);
+      }
+    };
+
+    let swj_cfg_field = find_field_in_peripheral(peripheral, "swj_cfg");
+
+    let remaps = peripheral
+      .iter_registers()
+      .filter(|r| r.name.to_lowercase().starts_with("mapr"))
+      .flat_map(|r| {
+        let shares_swj_register = swj_cfg_field
+          .as_ref()
+          .map_or(false, |swj| swj.address() == r.address());
+        r.fields.iter().filter_map(move |f| {
+          Remap::new(
+            &f.name,
+            f.path().to_lowercase(),
+            f.width,
+            shares_swj_register,
+          )
+        })
+      })
+      .collect::<Vec<Remap>>();
+
+    Ok(Some(Self {
+      name: Name::from("afio"),
+      peripheral_enable_field,
+      remaps,
+      swj_cfg_field: swj_cfg_field.map(|f| f.path().to_lowercase()),
+      exti_lines: ExtiLine::find_all(peripheral),
+    }))
+  }
+
+  pub fn submodule(&self) -> Submodule {
+    Submodule {
+      parent_path: "afio".to_owned(),
+      name: self.name.clone(),
+      needs_clocks: false,
+    }
+  }
+}
+
+#[derive(Clone)]
+pub struct Remap {
+  pub name: Name,
+  pub path: String,
+  pub width: u32,
+  // SWJ_CFG is write-only and reads back undefined, so writing a remap in the
+  // same register has to set it too.
+  pub shares_swj_register: bool,
+}
+impl Remap {
+  // Remap fields are named after what they move, like `TIM2_REMAP` or
+  // `TIM5CH4_IREMAP`. Anything else in MAPR (like SWJ_CFG) isn't a remap.
+  fn new(field_name: &str, path: String, width: u32, shares_swj_register: bool) -> Option<Self> {
+    let lower = field_name.to_lowercase();
+    let target = ["_iremap", "_remap"]
+      .iter()
+      .find_map(|suffix| lower.strip_suffix(suffix))?;
+
+    Some(Self {
+      name: Name::from(target),
+      path,
+      width,
+      shares_swj_register,
+    })
+  }
+
+  // Single-bit remaps are either off or on, wider ones choose between partial
+  // and full remaps.
+  pub fn is_flag(&self) -> bool {
+    self.width == 1
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use svd_expander::DeviceSpec;
+
+  use crate::fixup::load_fixed;
+
+  use super::*;
+
+  fn afio(device: &DeviceSpec) -> Option<Afio> {
+    let peripheral = device
+      .peripherals
+      .iter()
+      .find(|p| p.name == "AFIO")
+      .unwrap();
+    Afio::new(device, peripheral).unwrap()
+  }
+
+  #[test]
+  fn finds_remap_fields() {
+    let device = load_fixed("specs/svd/stm32f103.svd.patched");
+    let afio = afio(&device).unwrap();
+
+    let spi1 = afio
+      .remaps
+      .iter()
+      .find(|r| r.name.snake() == "spi1")
+      .unwrap();
+    assert!(spi1.is_flag());
+    assert_eq!("afio.mapr.spi1_remap", spi1.path);
+    assert!(spi1.shares_swj_register);
+    assert_eq!(Some("afio.mapr.swj_cfg".to_owned()), afio.swj_cfg_field);
+
+    let tim2 = afio
+      .remaps
+      .iter()
+      .find(|r| r.name.snake() == "tim2")
+      .unwrap();
+    assert!(!tim2.is_flag());
+
+    assert!(afio.remaps.iter().any(|r| r.name.snake() == "tim5ch4"));
+    assert!(afio
+      .remaps
+      .iter()
+      .filter(|r| r.path.starts_with("afio.mapr2."))
+      .all(|r| !r.shares_swj_register));
+    assert!(!afio.remaps.iter().any(|r| r.path.ends_with("swj_cfg")));
+    assert_eq!(16, afio.exti_lines.len());
+  }
+}
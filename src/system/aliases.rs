@@ -0,0 +1,81 @@
+use std::{collections::BTreeMap, fs, path::Path};
+
+use anyhow::{bail, Result};
+use serde::Deserialize;
+use svd_expander::{DeviceSpec, PeripheralSpec};
+
+// Names to use for the generated modules and structs instead of the ones that
+// come from the SVD, by peripheral (`"TIM2": "motor_timer"`).
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct PeripheralAliases {
+  #[serde(default)]
+  peripherals: BTreeMap<String, String>,
+}
+impl PeripheralAliases {
+  pub fn from_ron_file<P: AsRef<Path>>(path: P) -> Result<PeripheralAliases> {
+    info!(
+      "Parsing peripheral aliases from file '{}'",
+      match path.as_ref().to_str() {
+        Some(s) => s,
+        None => "(could not create string from path)",
+      }
+    );
+    Ok(ron::from_str(&fs::read_to_string(path)?)?)
+  }
+
+  #[cfg(test)]
+  pub fn from_ron<S: Into<String>>(ron: S) -> Result<PeripheralAliases> {
+    Ok(ron::from_str(&ron.into())?)
+  }
+
+  // Loads the aliases for the device from `specs/aliases/<device>.ron` if there
+  // are any.
+  pub fn for_device(device: &DeviceSpec) -> Result<PeripheralAliases> {
+    let aliases_filepath = format!("specs/aliases/{}.ron", device.name.to_lowercase());
+    match Path::new(&aliases_filepath).exists() {
+      true => Self::from_ron_file(&aliases_filepath),
+      false => Ok(Self::default()),
+    }
+  }
+
+  pub fn check(&self, device: &DeviceSpec) -> Result<()> {
+    for (peripheral_name, alias) in self.peripherals.iter() {
+      if !device
+        .peripherals
+        .iter()
+        .any(|p| p.name.eq_ignore_ascii_case(peripheral_name))
+      {
+        bail!(
+          "Alias '{}' is for peripheral '{}', which doesn't exist in device {}",
+          alias,
+          peripheral_name,
+          device.name
+        );
+      }
+
+      let mut chars = alias.chars();
+      let is_identifier = chars.next().map_or(false, |c| c.is_ascii_lowercase())
+        && chars.all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+      if !is_identifier {
+        bail!(
+          "Alias '{}' for peripheral '{}' must be a snake case identifier",
+          alias,
+          peripheral_name
+        );
+      }
+    }
+    Ok(())
+  }
+
+  pub fn get(&self, peripheral: &PeripheralSpec) -> Option<&String> {
+    self
+      .peripherals
+      .iter()
+      .find(|(name, _)| name.eq_ignore_ascii_case(&peripheral.name))
+      .map(|(_, alias)| alias)
+  }
+
+  pub fn iter(&self) -> impl Iterator<Item = (&String, &String)> {
+    self.peripherals.iter()
+  }
+}
@@ -0,0 +1,331 @@
+use std::{
+  collections::BTreeMap,
+  fs,
+  path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, bail, Result};
+use heck::SnakeCase;
+use serde::Deserialize;
+use svd_expander::DeviceSpec;
+
+use super::{gpio::Gpio, Name};
+
+// Where to look for a device's board spec, like `ClockSpecLocation` for clock
+// schematics.
+pub struct BoardSpecLocation {
+  pub path: Option<PathBuf>,
+  pub search_dirs: Vec<PathBuf>,
+}
+impl BoardSpecLocation {
+  pub fn find(&self, d: &DeviceSpec) -> Result<Option<PathBuf>> {
+    if let Some(ref path) = self.path {
+      if !path.is_file() {
+        bail!("Board spec '{}' does not exist", path.display());
+      }
+      return Ok(Some(path.clone()));
+    }
+
+    let file_name = format!("{}.ron", d.name.to_lowercase());
+    Ok(
+      self
+        .search_dirs
+        .iter()
+        .map(|dir| dir.join(&file_name))
+        .find(|p| p.is_file()),
+    )
+  }
+}
+impl Default for BoardSpecLocation {
+  fn default() -> Self {
+    Self {
+      path: None,
+      search_dirs: vec![PathBuf::from("specs/board")],
+    }
+  }
+}
+
+// What the board connects to the device's pins, by the name of the helper to
+// generate for each (`"led": (pin: "PC13", mode: Output)`).
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct BoardPins {
+  #[serde(default)]
+  pins: BTreeMap<String, PinAssignment>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct PinAssignment {
+  pin: String,
+  mode: PinMode,
+  #[serde(default)]
+  pull: Pull,
+  #[serde(default)]
+  open_drain: bool,
+  // Low for outputs and high for alternate functions when left out.
+  #[serde(default)]
+  speed: Option<Speed>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+enum PinMode {
+  Input,
+  Output,
+  Analog,
+  // By the alternate function's name in the SVD, like `"USART1_TX"`.
+  AltFunc(String),
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+enum Pull {
+  Floating,
+  Up,
+  Down,
+}
+impl Default for Pull {
+  fn default() -> Self {
+    Pull::Floating
+  }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+enum Speed {
+  Low,
+  Medium,
+  High,
+  VeryHigh,
+}
+
+impl BoardPins {
+  pub fn from_ron_file<P: AsRef<Path>>(path: P) -> Result<BoardPins> {
+    info!(
+      "Parsing board pins from file '{}'",
+      match path.as_ref().to_str() {
+        Some(s) => s,
+        None => "(could not create string from path)",
+      }
+    );
+    Ok(ron::from_str(&fs::read_to_string(path)?)?)
+  }
+
+  #[cfg(test)]
+  pub fn from_ron<S: Into<String>>(ron: S) -> Result<BoardPins> {
+    Ok(ron::from_str(&ron.into())?)
+  }
+
+  // Loads the board's pins from the spec `location` finds for the device, if
+  // there is one.
+  pub fn for_device(device: &DeviceSpec, location: &BoardSpecLocation) -> Result<BoardPins> {
+    match location.find(device)? {
+      Some(path) => Self::from_ron_file(path),
+      None => Ok(Self::default()),
+    }
+  }
+
+  // Finds each assigned pin and alternate function among the device's GPIO
+  // ports, failing on any the device doesn't have.
+  pub fn resolve(&self, gpios: &[Gpio]) -> Result<Vec<BoardPin>> {
+    let mut assigned: BTreeMap<String, &str> = BTreeMap::new();
+    let mut board_pins = Vec::new();
+
+    for (name, assignment) in self.pins.iter() {
+      let mut chars = name.chars();
+      let is_identifier = chars.next().map_or(false, |c| c.is_ascii_lowercase())
+        && chars.all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+      if !is_identifier {
+        bail!("Board pin '{}' must be a snake case identifier", name);
+      }
+
+      let pin_name = assignment.pin.to_lowercase();
+      let (gpio, pin) = match gpios.iter().find_map(|g| {
+        g.pins
+          .iter()
+          .find(|p| p.name.original == pin_name)
+          .map(|p| (g, p))
+      }) {
+        Some(found) => found,
+        None => bail!(
+          "Board pin '{}' is on {}, which the device doesn't have",
+          name,
+          assignment.pin
+        ),
+      };
+
+      if let Some(other) = assigned.insert(pin_name, name) {
+        bail!(
+          "Board pins '{}' and '{}' are both on {}",
+          other,
+          name,
+          assignment.pin
+        );
+      }
+
+      let pin_type = pin.name.camel();
+      let pull = match assignment.pull {
+        Pull::Floating => "PullDirection::Floating",
+        Pull::Up => "PullDirection::Up",
+        Pull::Down => "PullDirection::Down",
+      };
+      let output_type = match assignment.open_drain {
+        true => "OutputType::OpenDrain",
+        false => "OutputType::PushPull",
+      };
+      let speed = |default: Speed| match assignment.speed.unwrap_or(default) {
+        Speed::Low => "OutputSpeed::Low",
+        Speed::Medium => "OutputSpeed::Medium",
+        Speed::High => "OutputSpeed::High",
+        Speed::VeryHigh => "OutputSpeed::VeryHigh",
+      };
+
+      let (types, setup) = match &assignment.mode {
+        PinMode::Input => (
+          vec![format!("{}Input", pin_type)],
+          format!("as_input({})", pull),
+        ),
+        PinMode::Output => (
+          vec![format!("{}Output", pin_type)],
+          format!(
+            "as_output({}, {}, {})",
+            pull,
+            output_type,
+            speed(Speed::Low)
+          ),
+        ),
+        PinMode::Analog => (
+          vec![format!("{}Analog", pin_type)],
+          "as_analog()".to_owned(),
+        ),
+        PinMode::AltFunc(alt_func_name) => {
+          let alt_func = pin
+            .alt_funcs
+            .iter()
+            .find(|a| a.name.snake() == alt_func_name.to_snake_case())
+            .ok_or_else(|| {
+              anyhow!(
+                "Board pin '{}' uses {} as {}, but its alternate functions are: {}",
+                name,
+                assignment.pin,
+                alt_func_name,
+                pin
+                  .alt_funcs
+                  .iter()
+                  .map(|a| a.name.original.to_uppercase())
+                  .collect::<Vec<String>>()
+                  .join(", ")
+              )
+            })?;
+          let alt_func_type = format!("{}{}", pin_type, alt_func.name.camel());
+          (
+            vec![format!("{}AltFunc", pin_type), alt_func_type.clone()],
+            format!(
+              "as_alt_func::<{}>({}, {}, {})",
+              alt_func_type,
+              pull,
+              output_type,
+              speed(Speed::High)
+            ),
+          )
+        }
+      };
+
+      board_pins.push(BoardPin {
+        name: name.clone(),
+        port: gpio.name.clone(),
+        pin: pin.name.clone(),
+        types,
+        setup,
+      });
+    }
+
+    Ok(board_pins)
+  }
+}
+
+// A pin as the `board` module hands it out: taken from its port and set up
+// with `setup`, a call on the unconfigured pin.
+pub struct BoardPin {
+  pub name: String,
+  pub port: Name,
+  pub pin: Name,
+  // The configured pin's type first, then any it's generic over.
+  pub types: Vec<String>,
+  pub setup: String,
+}
+impl BoardPin {
+  pub fn return_type(&self) -> String {
+    match self.types.split_first() {
+      Some((ty, [])) => ty.clone(),
+      Some((ty, params)) => format!("{}<{}>", ty, params.join(", ")),
+      None => String::new(),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::fixup::load_fixed;
+  use crate::system::SystemInfo;
+
+  use super::*;
+
+  fn resolved(ron: &str) -> Result<Vec<BoardPin>> {
+    let device = load_fixed("specs/svd/stm32f303.svd.patched");
+    let sys_info = SystemInfo::new(&device).unwrap();
+    BoardPins::from_ron(ron).unwrap().resolve(&sys_info.gpios)
+  }
+
+  #[test]
+  fn loads_no_pins_without_a_board_spec() {
+    let device = load_fixed("specs/svd/stm32f303.svd.patched");
+    let location = BoardSpecLocation {
+      path: None,
+      search_dirs: vec![PathBuf::from("specs/missing")],
+    };
+    let pins = BoardPins::for_device(&device, &location).unwrap();
+    assert!(pins.pins.is_empty());
+
+    let missing = BoardSpecLocation {
+      path: Some(PathBuf::from("specs/board/missing.ron")),
+      search_dirs: vec![],
+    };
+    assert!(BoardPins::for_device(&device, &missing).is_err());
+  }
+
+  #[test]
+  fn resolves_pins_and_alt_funcs() {
+    let pins = resolved(
+      r#"BoardPins(pins: {
+        "led": (pin: "PE9", mode: Output),
+        "uart_tx": (pin: "PA9", mode: AltFunc("USART1_TX")),
+        "button": (pin: "PA0", mode: Input, pull: Down),
+      })"#,
+    )
+    .unwrap();
+
+    let pin = |name: &str| pins.iter().find(|p| p.name == name).unwrap();
+    assert_eq!("gpio_e", pin("led").port.snake());
+    assert_eq!("Pe9Output", pin("led").return_type());
+    assert_eq!(
+      "as_output(PullDirection::Floating, OutputType::PushPull, OutputSpeed::Low)",
+      pin("led").setup
+    );
+    assert_eq!("Pa9AltFunc<Pa9Usart1Tx>", pin("uart_tx").return_type());
+    assert_eq!("as_input(PullDirection::Down)", pin("button").setup);
+  }
+
+  #[test]
+  fn rejects_impossible_assignments() {
+    let err =
+      resolved(r#"BoardPins(pins: { "uart_tx": (pin: "PA9", mode: AltFunc("SPI1_SCK")) })"#)
+        .unwrap_err();
+    assert!(err.to_string().contains("USART1_TX"));
+
+    assert!(resolved(r#"BoardPins(pins: { "led": (pin: "PZ3", mode: Output) })"#).is_err());
+    assert!(resolved(
+      r#"BoardPins(pins: {
+        "led": (pin: "PE9", mode: Output),
+        "buzzer": (pin: "PE9", mode: Output),
+      })"#
+    )
+    .is_err());
+  }
+}
@@ -0,0 +1,85 @@
+use anyhow::{bail, Result};
+use regex::Regex;
+use svd_expander::{DeviceSpec, PeripheralSpec};
+
+use super::*;
+
+#[derive(Clone)]
+pub struct Dbgmcu {
+  pub name: Name,
+  pub peripheral_enable_field: Option<String>,
+  pub freeze_fields: Vec<FreezeField>,
+}
+impl Dbgmcu {
+  pub fn new(device: &DeviceSpec, peripheral: &PeripheralSpec) -> Result<Option<Self>> {
+    let rcc = match device
+      .peripherals
+      .iter()
+      .find(|p| p.name.to_lowercase() == "rcc")
+    {
+      Some(p) => p,
+      None => bail!("Could not find RCC peripheral"),
+    };
+
+    // Freeze bits are named after the peripheral they stop, e.g. DBG_TIM2_STOP,
+    // DBG_TIMER6_STOP or DBG_IWDG_STOP. Some SVDs list the same bit twice.
+    let freeze_field_test =
+      Regex::new(r"^dbg_(tim|timer|lptim|lptimer|hrtim|iwdg|wwdg)([0-9]*)_stop$")?;
+    let mut freeze_fields: Vec<FreezeField> = Vec::new();
+    for field in peripheral.iter_fields() {
+      if let Some(c) = freeze_field_test.captures(&field.name.to_lowercase()) {
+        let kind = match &c[1] {
+          "timer" => "tim",
+          "lptimer" => "lptim",
+          k => k,
+        };
+        let name = Name::from(format!("{}{}", kind, &c[2]));
+        if !freeze_fields.iter().any(|f| f.name == name) {
+          freeze_fields.push(FreezeField {
+            name,
+            field: field.path(),
+          });
+        }
+      }
+    }
+
+    if freeze_fields.is_empty() {
+      warn!("Skipping DBGMCU because it has no timer or watchdog freeze bits.");
+      return Ok(None);
+    }
+
+    freeze_fields.sort_by(|a, b| a.name.cmp(&b.name));
+
+    Ok(Some(Self {
+      name: Name::from("dbgmcu"),
+      // Only the F0 parts gate the DBGMCU clock.
+      peripheral_enable_field: find_field_in_peripheral(rcc, "dbgmcuen").map(|f| f.path()),
+      freeze_fields,
+    }))
+  }
+
+  pub fn submodule(&self) -> Submodule {
+    Submodule {
+      parent_path: "dbgmcu".to_owned(),
+      name: self.name.clone(),
+      needs_clocks: false,
+    }
+  }
+
+  pub fn has_peripheral_enable_field(&self) -> bool {
+    self.peripheral_enable_field.is_some()
+  }
+
+  pub fn peripheral_enable_field(&self) -> String {
+    match self.peripheral_enable_field {
+      Some(ref f) => f.clone(),
+      None => panic!("DBGMCU has no peripheral enable field."),
+    }
+  }
+}
+
+#[derive(Clone)]
+pub struct FreezeField {
+  pub name: Name,
+  pub field: String,
+}
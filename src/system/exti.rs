@@ -0,0 +1,114 @@
+use anyhow::Result;
+use svd_expander::{PeripheralSpec, RegisterSpec};
+
+use super::*;
+
+// The EXTI lines the GPIO pins are routed to, set up as events: the line wakes
+// a core waiting in `WFE` without an interrupt handler.
+#[derive(Clone)]
+pub struct Exti {
+  pub name: Name,
+  pub event_lines: Vec<EventLine>,
+}
+impl Exti {
+  pub fn new(peripheral: &PeripheralSpec) -> Result<Option<Self>> {
+    // The registers are numbered on parts with more than 32 lines, and on the
+    // H7 the mask registers are per core.
+    let find_register = |names: &[&str]| {
+      names
+        .iter()
+        .find_map(|n| find_register_in_peripheral(peripheral, n))
+    };
+    let (emr, rtsr, ftsr) = match (
+      find_register(&["emr", "emr1", "cpuemr1"]),
+      find_register(&["rtsr", "rtsr1"]),
+      find_register(&["ftsr", "ftsr1"]),
+    ) {
+      (Some(emr), Some(rtsr), Some(ftsr)) => (emr, rtsr, ftsr),
+      _ => {
+        warn!("Skipping EXTI because it has no event mask or trigger selection registers.");
+        return Ok(None);
+      }
+    };
+
+    let event_lines = (0..16)
+      .filter_map(|number| {
+        Some(EventLine {
+          number,
+          event_field: line_field(emr, number)?,
+          rising_field: line_field(rtsr, number)?,
+          falling_field: line_field(ftsr, number)?,
+        })
+      })
+      .collect::<Vec<EventLine>>();
+
+    if event_lines.is_empty() {
+      warn!("Skipping EXTI because none of its GPIO lines have event mask and trigger bits.");
+      return Ok(None);
+    }
+
+    Ok(Some(Self {
+      name: Name::from("exti"),
+      event_lines,
+    }))
+  }
+
+  pub fn submodule(&self) -> Submodule {
+    Submodule {
+      parent_path: "exti".to_owned(),
+      name: self.name.clone(),
+      needs_clocks: false,
+    }
+  }
+}
+
+#[derive(Clone)]
+pub struct EventLine {
+  pub number: u32,
+  pub event_field: String,
+  pub rising_field: String,
+  pub falling_field: String,
+}
+
+// The bit for a line is named after its number, with a prefix that depends on
+// the register and the family (`MR5`, `EM5`, `TR5`).
+fn line_field(register: &RegisterSpec, number: u32) -> Option<String> {
+  register
+    .fields
+    .iter()
+    .find(|f| {
+      let name = f.name.to_lowercase();
+      let digits = name.trim_start_matches(|c: char| c.is_ascii_alphabetic());
+      digits.len() < name.len() && digits.parse::<u32>().ok() == Some(number)
+    })
+    .map(|f| f.path().to_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::fixup::load_fixed;
+
+  use super::*;
+
+  fn exti_for(svd: &str) -> Exti {
+    let device = load_fixed(svd);
+    let peripheral = device
+      .peripherals
+      .iter()
+      .find(|p| p.name == "EXTI")
+      .unwrap();
+    Exti::new(peripheral).unwrap().unwrap()
+  }
+
+  #[test]
+  fn finds_event_lines_across_register_layouts() {
+    let f303 = exti_for("specs/svd/stm32f303.svd.patched");
+    assert_eq!(16, f303.event_lines.len());
+    assert_eq!("exti.emr1.mr3", f303.event_lines[3].event_field);
+    assert_eq!("exti.rtsr1.tr3", f303.event_lines[3].rising_field);
+    assert_eq!("exti.ftsr1.tr3", f303.event_lines[3].falling_field);
+
+    let f103 = exti_for("specs/svd/stm32f103.svd.patched");
+    assert_eq!("exti.emr.mr15", f103.event_lines[15].event_field);
+  }
+}
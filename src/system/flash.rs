@@ -0,0 +1,190 @@
+use anyhow::Result;
+use svd_expander::PeripheralSpec;
+
+use super::*;
+
+#[derive(Clone)]
+pub struct Flash {
+  pub name: Name,
+  pub layout: FlashLayout,
+  pub key_field: String,
+  pub pg_field: String,
+  pub per_field: String,
+  pub strt_field: String,
+  pub lock_field: String,
+  pub eopie_field: String,
+  pub bsy_field: String,
+  pub eop_field: String,
+  pub error_flags: Vec<StatusFlag>,
+  pub ar_field: Option<String>,
+  pub pnb_field: Option<RangedField>,
+}
+impl Flash {
+  // Bounds the wait for BSY. Erasing a page takes tens of milliseconds, much
+  // longer than the usual 1000 loops, so it's polled with interrupts enabled.
+  pub const BUSY_MAX_LOOPS: u32 = 20_000_000;
+
+  pub fn new(peripheral: &PeripheralSpec) -> Result<Option<Self>> {
+    // L0/L1/L5 parts program through PEKEYR/PECR instead, which isn't
+    // supported yet.
+    let find_register = |name: &str| {
+      peripheral
+        .iter_registers()
+        .find(|r| r.name.to_lowercase() == name)
+    };
+    let (keyr, cr, sr) = match (
+      find_register("keyr"),
+      find_register("cr"),
+      find_register("sr"),
+    ) {
+      (Some(keyr), Some(cr), Some(sr)) => (keyr, cr, sr),
+      _ => {
+        warn!(
+          "Skipping flash peripheral {} because it has no KEYR, CR or SR register.",
+          peripheral.name
+        );
+        return Ok(None);
+      }
+    };
+
+    let key_field = match keyr.fields.iter().next() {
+      Some(f) => f.path(),
+      None => {
+        warn!(
+          "Skipping flash peripheral {} because its KEYR register has no fields.",
+          peripheral.name
+        );
+        return Ok(None);
+      }
+    };
+
+    // Older parts (F0/F1/F3) select the page to erase with an address register
+    // and program half-words. Newer parts (G4/L4) select a page number and
+    // program double-words. Sector-based parts (F2/F4/F7/H7) aren't supported
+    // yet. The address field is looked up by path, since the H7 ECC fault
+    // address registers also have a FAR field.
+    let ar_field = find_field_in_peripheral(peripheral, "ar.far").map(|f| f.path());
+    let pnb_field = find_ranged_field_in_register(cr, "pnb");
+
+    let layout = match (&ar_field, &pnb_field) {
+      (Some(_), _) => FlashLayout::HalfWord,
+      (None, Some(_)) => FlashLayout::DoubleWord,
+      (None, None) => {
+        warn!(
+          "Skipping flash peripheral {} because it has neither a page address nor page number field.",
+          peripheral.name
+        );
+        return Ok(None);
+      }
+    };
+
+    let strt_field = match find_field_in_register(cr, "strt") {
+      Some(f) => f.path(),
+      None => try_find_field_in_register(cr, "start")?.path(),
+    };
+
+    // PGERR and WRPRTERR on older parts, PROGERR, WRPERR, PGAERR and so on on
+    // newer ones. They're all cleared by writing 1, like EOP.
+    let error_flags = sr
+      .fields
+      .iter()
+      .filter(|f| f.width == 1 && f.name.to_lowercase().ends_with("err"))
+      .map(|f| StatusFlag {
+        name: Name::from(&f.name),
+        path: f.path().to_lowercase(),
+        clear: FlagClear::WriteOne,
+      })
+      .collect();
+
+    Ok(Some(Self {
+      name: Name::from("flash"),
+      layout,
+      key_field,
+      pg_field: try_find_field_in_register(cr, "pg")?.path(),
+      per_field: try_find_field_in_register(cr, "per")?.path(),
+      strt_field,
+      lock_field: try_find_field_in_register(cr, "lock")?.path(),
+      eopie_field: try_find_field_in_register(cr, "eopie")?.path(),
+      bsy_field: try_find_field_in_register(sr, "bsy")?.path(),
+      eop_field: try_find_field_in_register(sr, "eop")?.path(),
+      error_flags,
+      ar_field,
+      pnb_field,
+    }))
+  }
+
+  pub fn submodule(&self) -> Submodule {
+    Submodule {
+      parent_path: "flash".to_owned(),
+      name: self.name.clone(),
+      needs_clocks: false,
+    }
+  }
+
+  // Clearing EOP with a read-modify-write of SR would also clear the error
+  // flags that were read as set.
+  pub fn eop_flag(&self) -> StatusFlag {
+    StatusFlag {
+      name: Name::from("eop"),
+      path: self.eop_field.clone(),
+      clear: FlagClear::WriteOne,
+    }
+  }
+
+  pub fn is_double_word(&self) -> bool {
+    match self.layout {
+      FlashLayout::DoubleWord => true,
+      FlashLayout::HalfWord => false,
+    }
+  }
+
+  pub fn ar_field(&self) -> String {
+    match self.ar_field {
+      Some(ref f) => f.clone(),
+      None => panic!("Flash has no page address field."),
+    }
+  }
+
+  pub fn pnb_field(&self) -> RangedField {
+    match self.pnb_field {
+      Some(ref f) => f.clone(),
+      None => panic!("Flash has no page number field."),
+    }
+  }
+}
+
+#[derive(Clone)]
+pub enum FlashLayout {
+  HalfWord,
+  DoubleWord,
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::fixup::load_fixed;
+
+  use super::*;
+
+  fn flash_for(svd: &str) -> Option<Flash> {
+    let device = load_fixed(svd);
+    let peripheral = device
+      .peripherals
+      .iter()
+      .find(|p| p.name == "FLASH")
+      .unwrap();
+    Flash::new(peripheral).unwrap()
+  }
+
+  #[test]
+  fn skips_unsupported_flash_layouts() {
+    let f303 = flash_for("specs/svd/stm32f303.svd.patched").unwrap();
+    assert!(!f303.is_double_word());
+    assert_eq!("flash.ar.far", f303.ar_field());
+
+    let errors: Vec<String> = f303.error_flags.iter().map(|f| f.path.clone()).collect();
+    assert_eq!(vec!["flash.sr.wrprterr", "flash.sr.pgerr"], errors);
+
+    assert!(flash_for("specs/svd/stm32l0x1.svd.patched").is_none());
+    assert!(flash_for("specs/svd/stm32h743.svd.patched").is_none());
+  }
+}
@@ -1,17 +1,19 @@
 use anyhow::{bail, Result};
 use regex::Regex;
-use svd_expander::{PeripheralSpec, RegisterSpec};
+use svd_expander::{DeviceSpec, PeripheralSpec, RegisterSpec};
 
-use super::{Name, Submodule};
+use super::{find_field_in_peripheral, Name, Submodule};
+use crate::generators::reset;
 
 #[derive(Clone)]
 pub struct Gpio {
   pub name: Name,
   pub pins: Vec<Pin>,
   pub enable_field: String,
+  pub lock_key_field: Option<String>,
 }
 impl Gpio {
-  pub fn new(peripheral: &PeripheralSpec) -> Result<Self> {
+  pub fn new(device: &DeviceSpec, peripheral: &PeripheralSpec) -> Result<Self> {
     let letter = match peripheral.name.to_lowercase().chars().nth(4) {
       Some(l) => l,
       None => {
@@ -22,10 +24,35 @@ impl Gpio {
       }
     };
 
+    let rcc = match device
+      .peripherals
+      .iter()
+      .find(|p| p.name.to_lowercase() == "rcc")
+    {
+      Some(p) => p,
+      None => bail!("Could not find RCC peripheral"),
+    };
+
+    // F0/F1/F3 call the enable bit IOPxEN, the rest call it GPIOxEN, and which
+    // register it's in varies by family.
+    let enable_field_names = vec![f!("iop{letter}en"), f!("gpio{letter}en")];
+    let enable_field = match enable_field_names
+      .iter()
+      .find_map(|n| find_field_in_peripheral(rcc, n))
+    {
+      Some(f) => f.path().to_lowercase(),
+      None => bail!(
+        "Could not find an RCC enable field for {} (tried {})",
+        peripheral.name,
+        enable_field_names.join(", ").to_uppercase()
+      ),
+    };
+
     Ok(Self {
       name: Name::from(f!("gpio_{letter}")),
       pins: Pin::new_all(&letter, peripheral)?,
-      enable_field: f!("rcc.ahbenr.iop{letter}en").to_owned(),
+      enable_field,
+      lock_key_field: find_field_in_peripheral(peripheral, "lckk").map(|f| f.path().to_lowercase()),
     })
   }
 
@@ -36,6 +63,53 @@ impl Gpio {
       needs_clocks: false,
     }
   }
+
+  // The port's letter, and its number in the EXTICR fields (A is 0).
+  pub fn port_letter(&self) -> String {
+    self
+      .name
+      .original
+      .trim_start_matches("gpio_")
+      .to_uppercase()
+  }
+
+  pub fn port_number(&self) -> u32 {
+    self
+      .port_letter()
+      .chars()
+      .next()
+      .map_or(0, |l| l as u32 - 'A' as u32)
+  }
+
+  // The pins to put in analog mode for the lowest power draw. Pins that come
+  // out of reset in alternate function mode are the debug port on ST's parts,
+  // so they're left alone to keep the debugger connected.
+  pub fn low_power_pins(&self, device: &DeviceSpec) -> Vec<&Pin> {
+    self
+      .pins
+      .iter()
+      .filter(|p| {
+        let reset_mode = reset::override_for(&p.moder_field).or_else(|| {
+          device
+            .get_field(&p.moder_field)
+            .ok()
+            .and_then(|f| f.reset_value)
+        });
+        reset_mode != Some(0b10)
+      })
+      .collect()
+  }
+
+  pub fn has_lock(&self) -> bool {
+    self.lock_key_field.is_some()
+  }
+
+  pub fn lock_key_field(&self) -> String {
+    match self.lock_key_field {
+      Some(ref f) => f.clone(),
+      None => panic!("GPIO {} has no LCKK field.", self.name.camel()),
+    }
+  }
 }
 
 #[derive(Clone)]
@@ -49,14 +123,15 @@ pub struct Pin {
   pub ospeedr_field: String,
   pub odr_field: String,
   pub idr_field: String,
+  pub bs_field: String,
+  pub br_field: String,
+  pub ascr_field: Option<String>,
 }
 impl Pin {
   pub fn new_all(letter: &char, peripheral: &PeripheralSpec) -> Result<Vec<Self>> {
-    Ok(
-      (0..16)
-        .map(|n| Pin::new(letter, n, peripheral))
-        .collect::<Result<Vec<Self>>>()?,
-    )
+    (0..16)
+      .map(|n| Pin::new(letter, n, peripheral))
+      .collect::<Result<Vec<Self>>>()
   }
 
   pub fn new(letter: &char, number: i32, peripheral: &PeripheralSpec) -> Result<Self> {
@@ -87,8 +162,59 @@ impl Pin {
       ospeedr_field: f!("gpio{letter}.ospeedr.ospeedr{number}"),
       odr_field: f!("gpio{letter}.odr.odr{number}"),
       idr_field: f!("gpio{letter}.idr.idr{number}"),
+      bs_field: f!("gpio{letter}.bsrr.bs{number}"),
+      br_field: f!("gpio{letter}.bsrr.br{number}"),
+      // Only the L47x/L48x have an analog switch between the pin and the ADC.
+      ascr_field: find_field_in_peripheral(peripheral, &f!("asc{number}"))
+        .map(|f| f.path().to_lowercase()),
     })
   }
+
+  pub fn has_ascr(&self) -> bool {
+    self.ascr_field.is_some()
+  }
+
+  pub fn ascr_field(&self) -> String {
+    match self.ascr_field {
+      Some(ref f) => f.clone(),
+      None => panic!("Pin {} has no ASC field.", self.name.camel()),
+    }
+  }
+}
+
+// The OSPEEDR values for each `OutputSpeed`. F0 and F3 ports only have three
+// speeds, and ignore the low bit when the high one is clear, so high speed is
+// 0b11 there and 0b10 is low. Their SVDs list the four-speed values anyway, so
+// this goes by family instead.
+const THREE_SPEED_FAMILIES: &[&str] = &["stm32f0", "stm32f3"];
+
+pub struct OutputSpeeds {
+  pub low: u32,
+  pub medium: u32,
+  pub high: u32,
+  pub very_high: u32,
+}
+impl OutputSpeeds {
+  pub fn for_device(device_name: &str) -> Self {
+    let device_name = device_name.to_lowercase();
+    match THREE_SPEED_FAMILIES
+      .iter()
+      .any(|f| device_name.starts_with(f))
+    {
+      true => Self {
+        low: 0b00,
+        medium: 0b01,
+        high: 0b11,
+        very_high: 0b11,
+      },
+      false => Self {
+        low: 0b00,
+        medium: 0b01,
+        high: 0b10,
+        very_high: 0b11,
+      },
+    }
+  }
 }
 
 #[derive(Clone)]
@@ -148,3 +274,53 @@ impl AltFunc {
 pub enum AltFuncKind {
   Other,
 }
+
+#[cfg(test)]
+mod tests {
+  use svd_expander::DeviceSpec;
+
+  use crate::fixup::load_fixed;
+
+  use super::*;
+
+  fn gpio_a(device: &DeviceSpec) -> Gpio {
+    let peripheral = device
+      .peripherals
+      .iter()
+      .find(|p| p.name == "GPIOA")
+      .unwrap();
+    Gpio::new(device, peripheral).unwrap()
+  }
+
+  #[test]
+  fn encodes_output_speeds_by_family() {
+    assert_eq!(0b11, OutputSpeeds::for_device("STM32F303").high);
+    assert_eq!(0b11, OutputSpeeds::for_device("STM32F0x1").high);
+    assert_eq!(0b10, OutputSpeeds::for_device("STM32F405").high);
+    assert_eq!(0b11, OutputSpeeds::for_device("STM32F405").very_high);
+  }
+
+  #[test]
+  fn finds_iop_enable_field() {
+    let device = load_fixed("specs/svd/stm32f103.svd.patched");
+    assert_eq!("rcc.apb2enr.iopaen", gpio_a(&device).enable_field);
+  }
+
+  #[test]
+  fn finds_gpio_enable_field() {
+    let device = load_fixed("specs/svd/stm32f405.svd.patched");
+    assert_eq!("rcc.ahb1enr.gpioaen", gpio_a(&device).enable_field);
+  }
+
+  #[test]
+  fn probes_analog_switch_field() {
+    let device = load_fixed("specs/svd/stm32l4x6.svd.patched");
+    assert_eq!(
+      Some("gpioa.ascr.asc5".to_owned()),
+      gpio_a(&device).pins[5].ascr_field
+    );
+
+    let device = load_fixed("specs/svd/stm32f303.svd.patched");
+    assert!(gpio_a(&device).pins.iter().all(|p| !p.has_ascr()));
+  }
+}
@@ -1,41 +1,220 @@
-use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+
+use anyhow::{anyhow, bail, Result};
 use heck::{CamelCase, SnakeCase};
-use svd_expander::{DeviceSpec, EnumeratedValueSpec, FieldSpec, PeripheralSpec, RegisterSpec};
+use svd_expander::{
+  AccessSpec, DeviceSpec, EnumeratedValueSpec, FieldSpec, ModifiedWriteValuesSpec, PeripheralSpec,
+  ReadActionSpec, RegisterSpec,
+};
 
-use self::{gpio::Gpio, spi::Spi, timer::Timer};
+use self::{
+  afio::Afio,
+  aliases::PeripheralAliases,
+  board::{BoardPin, BoardPins},
+  dbgmcu::Dbgmcu,
+  exti::Exti,
+  flash::Flash,
+  gpio::Gpio,
+  pwr::Pwr,
+  qspi::Qspi,
+  rng::Rng,
+  sai::Sai,
+  signature::Signature,
+  spi::Spi,
+  syscfg::Syscfg,
+  timer::Timer,
+};
 
+pub mod afio;
+pub mod aliases;
+pub mod board;
+pub mod dbgmcu;
+pub mod exti;
+pub mod flash;
 pub mod gpio;
+pub mod probe;
+pub mod pwr;
+pub mod qspi;
+pub mod rng;
+pub mod sai;
+pub mod signature;
 pub mod spi;
+pub mod syscfg;
 pub mod timer;
 
+// The peripheral families, by the name of the module they're generated into.
+pub const FAMILIES: [&str; 12] = [
+  "gpio", "afio", "syscfg", "exti", "timer", "spi", "sai", "flash", "qspi", "pwr", "dbgmcu", "rng",
+];
+
 pub struct SystemInfo<'a> {
   pub device: &'a DeviceSpec,
   pub gpios: Vec<Gpio>,
+  pub afio: Option<Afio>,
+  pub syscfg: Option<Syscfg>,
+  pub exti: Option<Exti>,
   pub timers: Vec<Timer>,
   pub spis: Vec<Spi>,
+  pub sais: Vec<Sai>,
+  pub flash: Option<Flash>,
+  pub qspis: Vec<Qspi>,
+  pub pwr: Option<Pwr>,
+  pub dbgmcu: Option<Dbgmcu>,
+  pub rng: Option<Rng>,
+  pub signature: Option<Signature>,
+  pub board: Vec<BoardPin>,
+  aliases: PeripheralAliases,
 }
 impl<'a> SystemInfo<'a> {
   pub fn new(device: &'a DeviceSpec) -> Result<Self> {
+    Self::with_aliases(device, PeripheralAliases::for_device(device)?)
+  }
+
+  pub fn with_aliases(device: &'a DeviceSpec, aliases: PeripheralAliases) -> Result<Self> {
+    aliases.check(device)?;
+
     let mut system_info = Self {
       device,
       gpios: Vec::new(),
+      afio: None,
+      syscfg: None,
+      exti: None,
       timers: Vec::new(),
       spis: Vec::new(),
+      sais: Vec::new(),
+      flash: None,
+      qspis: Vec::new(),
+      pwr: None,
+      dbgmcu: None,
+      rng: None,
+      signature: None,
+      board: Vec::new(),
+      aliases,
     };
     system_info.load_gpios(device)?;
+    system_info.load_afio(device)?;
+    system_info.load_syscfg(device)?;
+    system_info.load_exti(device)?;
     system_info.load_timers(device)?;
     system_info.load_spis(device)?;
+    system_info.load_sais(device)?;
+    system_info.load_flash(device)?;
+    system_info.load_qspis(device)?;
+    system_info.load_pwr(device)?;
+    system_info.load_dbgmcu(device)?;
+    system_info.load_rng(device)?;
+    system_info.load_signature(device);
+    system_info.check_aliases_used();
+    system_info.check_identifiers()?;
 
     Ok(system_info)
   }
 
+  // Without a clock schematic there's no `Clocks` to create these from.
+  pub fn remove_clocked_submodules(&mut self) {
+    self.timers.clear();
+    self.spis.clear();
+  }
+
+  // Leaves out the peripheral families that aren't listed, by module name
+  // (`"timer"`, `"gpio"`). Their modules are still generated, empty.
+  pub fn retain_families(&mut self, families: &[String]) -> Result<()> {
+    for family in families.iter() {
+      if !FAMILIES.contains(&family.as_str()) {
+        bail!(
+          "Unknown peripheral family '{}', expected one of: {}",
+          family,
+          FAMILIES.join(", ")
+        );
+      }
+    }
+
+    let keep = |family: &str| families.iter().any(|f| f == family);
+    if !keep("gpio") && (keep("afio") || keep("syscfg")) {
+      bail!("The afio and syscfg families route EXTI lines to GPIO ports, so they need gpio too");
+    }
+
+    if !keep("gpio") {
+      self.gpios.clear();
+      self.board.clear();
+    }
+    if !keep("afio") {
+      self.afio = None;
+    }
+    if !keep("syscfg") {
+      self.syscfg = None;
+    }
+    if !keep("exti") {
+      self.exti = None;
+    }
+    if !keep("timer") {
+      self.timers.clear();
+    }
+    if !keep("spi") {
+      self.spis.clear();
+    }
+    if !keep("sai") {
+      self.sais.clear();
+    }
+    if !keep("flash") {
+      self.flash = None;
+    }
+    if !keep("qspi") {
+      self.qspis.clear();
+    }
+    if !keep("pwr") {
+      self.pwr = None;
+    }
+    if !keep("dbgmcu") {
+      self.dbgmcu = None;
+    }
+    if !keep("rng") {
+      self.rng = None;
+    }
+
+    Ok(())
+  }
+
+  // Finds the board's pins among the GPIO ports. Has to come before
+  // `retain_families` leaves the ports out.
+  pub fn assign_board(&mut self, board_pins: &BoardPins) -> Result<()> {
+    self.board = board_pins.resolve(&self.gpios)?;
+    Ok(())
+  }
+
+  // Points the peripherals created from `Clocks` at the clock of the bus they
+  // hang off, when the clock schematic has a tap for it.
+  pub fn assign_bus_clocks(&mut self, clock_taps: &[String]) {
+    let tap_for = |enable_field: &str| {
+      bus_clock_tap(enable_field)
+        .filter(|t| clock_taps.iter().any(|c| c == t))
+        .map(|t| t.to_owned())
+    };
+
+    for timer in self.timers.iter_mut() {
+      timer.bus_clock = tap_for(&timer.peripheral_enable_field);
+    }
+    for spi in self.spis.iter_mut() {
+      spi.bus_clock = tap_for(&spi.peripheral_enable_field);
+    }
+  }
+
   pub fn submodules(&self) -> Vec<Submodule> {
     let mut submodules = self
       .gpios
       .iter()
       .map(|g| g.submodule())
+      .chain(self.afio.iter().map(|a| a.submodule()))
+      .chain(self.syscfg.iter().map(|s| s.submodule()))
+      .chain(self.exti.iter().map(|e| e.submodule()))
       .chain(self.timers.iter().map(|t| t.submodule()))
       .chain(self.spis.iter().map(|t| t.submodule()))
+      .chain(self.sais.iter().map(|s| s.submodule()))
+      .chain(self.flash.iter().map(|f| f.submodule()))
+      .chain(self.qspis.iter().map(|q| q.submodule()))
+      .chain(self.pwr.iter().map(|p| p.submodule()))
+      .chain(self.dbgmcu.iter().map(|d| d.submodule()))
+      .chain(self.rng.iter().map(|r| r.submodule()))
       .collect::<Vec<Submodule>>();
 
     submodules.sort();
@@ -43,13 +222,88 @@ impl<'a> SystemInfo<'a> {
     submodules
   }
 
+  fn check_identifiers(&self) -> Result<()> {
+    let submodules = self.submodules();
+    check_identifier_collisions(
+      "the crate root",
+      submodules.iter().map(|s| &s.name),
+      Name::snake,
+    )?;
+    check_identifier_collisions(
+      "the crate root",
+      submodules.iter().map(|s| &s.name),
+      Name::camel,
+    )?;
+
+    for gpio in self.gpios.iter() {
+      let scope = f!("module gpio::{}", gpio.name.snake());
+      check_identifier_collisions(&scope, gpio.pins.iter().map(|p| &p.name), Name::camel)?;
+      for pin in gpio.pins.iter() {
+        check_identifier_collisions(&scope, pin.alt_funcs.iter().map(|a| &a.name), Name::camel)?;
+      }
+    }
+
+    for timer in self.timers.iter() {
+      let scope = f!("module timer::{}", timer.name.snake());
+      for channel in timer.channels.iter() {
+        if channel.is_output() {
+          let output = channel.as_output();
+          output.compare_mode.check_identifiers(&scope)?;
+          if output.has_io_select() {
+            output.io_select().check_identifiers(&scope)?;
+          }
+        }
+        if channel.is_input() {
+          let input = channel.as_input();
+          input.capture_filter.check_identifiers(&scope)?;
+          if input.has_io_select() {
+            input.io_select().check_identifiers(&scope)?;
+          }
+        }
+      }
+    }
+
+    for spi in self.spis.iter() {
+      spi
+        .br_field
+        .check_identifiers(&f!("module spi::{}", spi.name.snake()))?;
+    }
+
+    for sai in self.sais.iter() {
+      let scope = f!("module sai::{}", sai.name.snake());
+      sai.mode.check_identifiers(&scope)?;
+      sai.protocol.check_identifiers(&scope)?;
+      sai.data_size.check_identifiers(&scope)?;
+    }
+
+    for pwr in self.pwr.iter() {
+      check_identifier_collisions(
+        "module pwr",
+        pwr.wakeup_pins.iter().map(|p| &p.name),
+        Name::snake,
+      )?;
+    }
+
+    for dbgmcu in self.dbgmcu.iter() {
+      check_identifier_collisions(
+        "module dbgmcu",
+        dbgmcu.freeze_fields.iter().map(|f| &f.name),
+        Name::snake,
+      )?;
+    }
+
+    Ok(())
+  }
+
   fn load_gpios(&mut self, device: &DeviceSpec) -> Result<()> {
     for peripheral in device
       .peripherals
       .iter()
       .filter(|p| p.name.to_lowercase().starts_with("gpio"))
     {
-      self.gpios.push(Gpio::new(peripheral)?);
+      let mut gpio = Gpio::new(&self.device, peripheral)?;
+      self.alias(&mut gpio.name, peripheral);
+      self.gpios.push(gpio);
     }
     Ok(())
   }
@@ -60,7 +314,8 @@ impl<'a> SystemInfo<'a> {
       .iter()
       .filter(|p| p.name.to_lowercase().starts_with("tim"))
     {
-      if let Some(timer) = Timer::new(&self.device, peripheral)? {
+      if let Some(mut timer) = Timer::new(&self.device, peripheral)? {
+        self.alias(&mut timer.name, peripheral);
         self.timers.push(timer);
       };
     }
@@ -73,10 +328,201 @@ impl<'a> SystemInfo<'a> {
       .iter()
       .filter(|p| p.name.to_lowercase().starts_with("spi"))
     {
-      self.spis.push(Spi::new(&self.device, peripheral)?);
+      let mut spi = Spi::new(&self.device, peripheral)?;
+      self.alias(&mut spi.struct_name, peripheral);
+      self.spis.push(spi);
+    }
+    Ok(())
+  }
+
+  fn load_sais(&mut self, device: &DeviceSpec) -> Result<()> {
+    for peripheral in device
+      .peripherals
+      .iter()
+      .filter(|p| p.name.to_lowercase().starts_with("sai"))
+    {
+      if let Some(mut sai) = Sai::new(&self.device, peripheral)? {
+        self.alias(&mut sai.name, peripheral);
+        self.sais.push(sai);
+      };
+    }
+    Ok(())
+  }
+
+  fn load_flash(&mut self, device: &DeviceSpec) -> Result<()> {
+    if let Some(peripheral) = device
+      .peripherals
+      .iter()
+      .find(|p| p.name.to_lowercase() == "flash")
+    {
+      self.flash = Flash::new(peripheral)?.map(|mut flash| {
+        self.alias(&mut flash.name, peripheral);
+        flash
+      });
+    }
+    Ok(())
+  }
+
+  fn load_qspis(&mut self, device: &DeviceSpec) -> Result<()> {
+    for peripheral in device.peripherals.iter().filter(|p| {
+      let name = p.name.to_lowercase();
+      name == "quadspi"
+        || name
+          .strip_prefix("octospi")
+          .map_or(false, |n| n.chars().all(|c| c.is_ascii_digit()))
+    }) {
+      if let Some(mut qspi) = Qspi::new(&self.device, peripheral)? {
+        self.alias(&mut qspi.name, peripheral);
+        self.qspis.push(qspi);
+      };
+    }
+    Ok(())
+  }
+
+  fn load_pwr(&mut self, device: &DeviceSpec) -> Result<()> {
+    if let Some(peripheral) = device
+      .peripherals
+      .iter()
+      .find(|p| p.name.to_lowercase() == "pwr")
+    {
+      self.pwr = Pwr::new(&self.device, peripheral)?.map(|mut pwr| {
+        self.alias(&mut pwr.name, peripheral);
+        pwr
+      });
+    }
+    Ok(())
+  }
+
+  fn load_exti(&mut self, device: &DeviceSpec) -> Result<()> {
+    if let Some(peripheral) = device
+      .peripherals
+      .iter()
+      .find(|p| p.name.to_lowercase() == "exti")
+    {
+      self.exti = Exti::new(peripheral)?.map(|mut exti| {
+        self.alias(&mut exti.name, peripheral);
+        exti
+      });
+    }
+    Ok(())
+  }
+
+  fn load_dbgmcu(&mut self, device: &DeviceSpec) -> Result<()> {
+    if let Some(peripheral) = device
+      .peripherals
+      .iter()
+      .find(|p| p.name.to_lowercase() == "dbgmcu")
+    {
+      self.dbgmcu = Dbgmcu::new(&self.device, peripheral)?.map(|mut dbgmcu| {
+        self.alias(&mut dbgmcu.name, peripheral);
+        dbgmcu
+      });
+    }
+    Ok(())
+  }
+
+  fn load_rng(&mut self, device: &DeviceSpec) -> Result<()> {
+    if let Some(peripheral) = device
+      .peripherals
+      .iter()
+      .find(|p| p.name.to_lowercase() == "rng")
+    {
+      self.rng = Rng::new(&self.device, peripheral)?.map(|mut rng| {
+        self.alias(&mut rng.name, peripheral);
+        rng
+      });
     }
     Ok(())
   }
+
+  // Only the F1 family remaps alternate functions through AFIO, the others
+  // have AFR registers on each GPIO port.
+  fn load_afio(&mut self, device: &DeviceSpec) -> Result<()> {
+    let has_afr = device
+      .peripherals
+      .iter()
+      .filter(|p| p.name.to_lowercase().starts_with("gpio"))
+      .flat_map(|p| p.iter_registers())
+      .any(|r| r.name.to_lowercase().starts_with("afr"));
+    if has_afr {
+      return Ok(());
+    }
+
+    if let Some(peripheral) = device
+      .peripherals
+      .iter()
+      .find(|p| p.name.to_lowercase() == "afio")
+    {
+      self.afio = Afio::new(&self.device, peripheral)?.map(|mut afio| {
+        self.alias(&mut afio.name, peripheral);
+        afio
+      });
+    }
+    Ok(())
+  }
+
+  // Some devices name it after what else shares its registers, like
+  // `SYSCFG_COMP_OPAMP`.
+  fn load_syscfg(&mut self, device: &DeviceSpec) -> Result<()> {
+    if let Some(peripheral) = device
+      .peripherals
+      .iter()
+      .find(|p| p.name.to_lowercase().starts_with("syscfg"))
+    {
+      self.syscfg = Syscfg::new(&self.device, peripheral)?.map(|mut syscfg| {
+        self.alias(&mut syscfg.name, peripheral);
+        syscfg
+      });
+    }
+    Ok(())
+  }
+
+  // Opt-in with `--validate-svd`. Each peripheral spans from its base address
+  // to the end of its last register.
+  pub fn check_address_overlaps(&self) -> Result<()> {
+    check_address_overlaps(
+      self
+        .device
+        .peripherals
+        .iter()
+        .filter_map(|p| {
+          p.iter_registers()
+            .map(|r| r.address() as u64 + (r.size.unwrap_or(32) / 8) as u64)
+            .max()
+            .map(|end| (p.name.as_str(), p.base_address as u64, end))
+        })
+        .collect(),
+    )
+  }
+
+  fn alias(&self, name: &mut Name, peripheral: &PeripheralSpec) {
+    name.alias = self.aliases.get(peripheral).cloned();
+  }
+
+  fn check_aliases_used(&self) {
+    let submodules = self.submodules();
+    for (peripheral_name, alias) in self.aliases.iter() {
+      if !submodules
+        .iter()
+        .any(|s| s.name.alias.as_ref() == Some(alias))
+      {
+        warn!(
+          "Alias '{}' isn't used, no module is generated for peripheral {}.",
+          alias, peripheral_name
+        );
+      }
+    }
+  }
+
+  fn load_signature(&mut self, device: &DeviceSpec) {
+    self.signature = Signature::for_device(&device.name);
+    if self.signature.is_none() {
+      warn!(
+        "Skipping device ID and flash size, their addresses aren't known for {}.",
+        device.name
+      );
+    }
+  }
 }
 
 #[derive(Clone, Eq, PartialEq)]
@@ -99,17 +545,35 @@ impl Ord for Submodule {
 #[derive(Clone, Eq, PartialEq)]
 pub struct Name {
   pub original: String,
+  pub alias: Option<String>,
 }
 impl Name {
   pub fn from<S: Into<String>>(s: S) -> Self {
-    Self { original: s.into() }
+    Self {
+      original: s.into(),
+      alias: None,
+    }
   }
 
   pub fn camel(&self) -> String {
-    self.original.to_camel_case()
+    self
+      .alias
+      .as_ref()
+      .unwrap_or(&self.original)
+      .to_camel_case()
   }
 
   pub fn snake(&self) -> String {
+    self
+      .alias
+      .as_ref()
+      .unwrap_or(&self.original)
+      .to_snake_case()
+  }
+
+  // For names that have to match the SVD or the clock schematic whatever the
+  // generated module is called, like the `to_tim2` clock tap.
+  pub fn unaliased_snake(&self) -> String {
     self.original.to_snake_case()
   }
 }
@@ -124,11 +588,48 @@ impl Ord for Name {
   }
 }
 
+// The clock tap for the bus a peripheral is on, going by the RCC register its
+// enable bit is in, so `rcc.apb1enr.tim2en` is on `p_clk1`.
+pub fn bus_clock_tap(enable_field: &str) -> Option<&'static str> {
+  let register = enable_field.split('.').nth(1)?.to_lowercase();
+  match register {
+    r if r.starts_with("apb1") => Some("p_clk1"),
+    r if r.starts_with("apb2") => Some("p_clk2"),
+    r if r.starts_with("ahb") => Some("h_clk"),
+    _ => None,
+  }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FieldAccess {
+  pub readable: bool,
+  pub writable: bool,
+}
+impl FieldAccess {
+  pub fn of(field: &FieldSpec) -> Self {
+    match field.access {
+      Some(AccessSpec::ReadOnly) => Self {
+        readable: true,
+        writable: false,
+      },
+      Some(AccessSpec::WriteOnly) | Some(AccessSpec::WriteOnce) => Self {
+        readable: false,
+        writable: true,
+      },
+      _ => Self {
+        readable: true,
+        writable: true,
+      },
+    }
+  }
+}
+
 #[derive(Clone)]
 pub struct RangedField {
   pub path: String,
   pub min: u32,
   pub max: u32,
+  pub access: FieldAccess,
 }
 impl RangedField {
   pub fn from_field_spec(f: FieldSpec) -> Self {
@@ -136,16 +637,139 @@ impl RangedField {
       path: f.path().to_lowercase(),
       min: 0,
       max: (2u64.pow(f.width) - 1) as u32,
+      access: FieldAccess::of(&f),
     }
   }
 }
 
+// How an interrupt flag goes back to 0. Flags in a status register that have
+// none of these (`TXE`, `BUSY`) are cleared by the hardware.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FlagClear {
+  WriteZero,
+  WriteOne,
+  Read,
+}
+
+// The EXTICR fields that pick the GPIO port each EXTI line listens to, which
+// are in SYSCFG or, on the F1, AFIO.
+#[derive(Clone)]
+pub struct ExtiLine {
+  pub number: u32,
+  pub path: String,
+}
+impl ExtiLine {
+  pub fn find_all(p: &PeripheralSpec) -> Vec<Self> {
+    let mut lines = p
+      .iter_registers()
+      .filter(|r| r.name.to_lowercase().starts_with("exticr"))
+      .flat_map(|r| r.fields.iter())
+      .filter_map(|f| {
+        let number = f
+          .name
+          .to_lowercase()
+          .strip_prefix("exti")?
+          .parse::<u32>()
+          .ok()?;
+        Some(Self {
+          number,
+          path: f.path().to_lowercase(),
+        })
+      })
+      .collect::<Vec<Self>>();
+    lines.sort_by_key(|l| l.number);
+    lines
+  }
+}
+
+#[derive(Clone)]
+pub struct StatusFlag {
+  pub name: Name,
+  // The field that is written or read to clear the flag, which is in the ICR
+  // register if the peripheral has one.
+  pub path: String,
+  pub clear: FlagClear,
+}
+impl StatusFlag {
+  pub fn find_all(p: &PeripheralSpec) -> Vec<Self> {
+    let status_fields = p
+      .iter_registers()
+      .filter(|r| is_status_register(&r.name))
+      .flat_map(|r| r.fields.iter())
+      .filter(|f| f.width == 1)
+      .collect::<Vec<&FieldSpec>>();
+
+    let mut flags: Vec<Self> = status_fields
+      .iter()
+      .filter_map(|f| {
+        let clear = match (&f.read_action, &f.modified_write_values) {
+          (Some(ReadActionSpec::Clear), _) => FlagClear::Read,
+          (_, Some(ModifiedWriteValuesSpec::ZeroToClear)) => FlagClear::WriteZero,
+          (_, Some(ModifiedWriteValuesSpec::OneToClear)) => FlagClear::WriteOne,
+          _ => return None,
+        };
+        Some(Self {
+          name: Name::from(&f.name),
+          path: f.path().to_lowercase(),
+          clear,
+        })
+      })
+      .collect();
+
+    // ICR fields are named after the flag with a `CF` suffix, minus the flag's
+    // own `F` or `IF` (`TC` -> `TCCF`, `NF` -> `NCF`, `CTSIF` -> `CTSCF`). Real
+    // flags go first so that `CTSIF`, not the `CTS` line state, gets `CTSCF`.
+    if let Some(icr) = find_register_in_peripheral(p, "icr") {
+      let mut candidates = status_fields
+        .iter()
+        .filter(|f| !flags.iter().any(|flag| flag.name.original == f.name))
+        .collect::<Vec<&&FieldSpec>>();
+      candidates.sort_by_key(|f| !f.name.to_lowercase().ends_with('f'));
+
+      let mut claimed: Vec<String> = vec![];
+      for field in candidates {
+        let name = field.name.to_lowercase();
+        let clear_field = vec![
+          Some(name.as_str()),
+          name.strip_suffix("if"),
+          name.strip_suffix('f'),
+        ]
+        .into_iter()
+        .flatten()
+        .filter_map(|base| find_field_in_register(icr, &format!("{}cf", base)))
+        .find(|f| !claimed.contains(&f.name));
+
+        if let Some(clear_field) = clear_field {
+          claimed.push(clear_field.name.clone());
+          flags.push(Self {
+            name: Name::from(&field.name),
+            path: clear_field.path().to_lowercase(),
+            clear: FlagClear::WriteOne,
+          });
+        }
+      }
+    }
+
+    flags
+  }
+}
+
+fn is_status_register(name: &str) -> bool {
+  let name = name.to_lowercase();
+  name == "isr"
+    || name
+      .strip_prefix("sr")
+      .map_or(false, |n| n.chars().all(|c| c.is_ascii_digit()))
+}
+
 #[derive(Clone)]
 pub struct EnumField {
   pub description: String,
   pub path: String,
   pub name: Name,
   pub values: Vec<EnumValue>,
+  pub width: u32,
+  pub access: FieldAccess,
 }
 impl EnumField {
   pub fn from_field_spec(field: FieldSpec) -> Self {
@@ -162,13 +786,27 @@ impl EnumField {
         .flat_map(|vs| vs.values.iter())
         .filter_map(EnumValue::new)
         .collect::<Vec<EnumValue>>(),
+      width: field.width,
+      access: FieldAccess::of(&field),
     }
   }
 
-  pub fn clone_values_from(&mut self, other: &EnumField) {
-    for value in other.values.iter() {
-      self.values.push(value.clone());
-    }
+  // Whether every bit pattern the field can hold has a value, so that reading
+  // it back can't find a reserved one.
+  pub fn covers_all_values(&self) -> bool {
+    let mut bit_values = self
+      .values
+      .iter()
+      .map(|v| v.bit_value as u64)
+      .filter(|v| *v < 1u64 << self.width)
+      .collect::<Vec<u64>>();
+    bit_values.sort_unstable();
+    bit_values.dedup();
+    bit_values.len() as u64 == 1u64 << self.width
+  }
+
+  pub fn check_identifiers(&self, scope: &str) -> Result<()> {
+    check_identifier_collisions(scope, self.values.iter().map(|v| &v.name), Name::camel)
   }
 }
 
@@ -194,10 +832,17 @@ impl EnumValue {
   }
 }
 
+// Fields and registers nested in clusters are found like any other. Where a
+// cluster repeats (`CH%s`), a bare name finds the copy in the first element,
+// and a path relative to the peripheral (`ch2.cr.en`) picks a specific one.
 #[allow(dead_code)]
 fn find_field_in_peripheral(p: &PeripheralSpec, name: &str) -> Option<FieldSpec> {
+  let name = normalize_register_name(name);
   p.iter_fields()
-    .find(|f| f.name.to_lowercase() == name.to_lowercase())
+    .find(|f| match name.contains('.') {
+      true => path_in_peripheral(&f.path()) == name,
+      false => f.name.to_lowercase() == name,
+    })
     .map(|f| f.clone())
 }
 
@@ -238,6 +883,135 @@ fn try_find_enum_field_in_peripheral(p: &PeripheralSpec, name: &str) -> Result<E
   ))
 }
 
+// Distinct SVD names can turn into the same identifier after case conversion
+// (`IC1F` and `ic1_f`), which otherwise only shows up as a duplicate definition
+// when the generated crate is compiled.
+fn check_identifier_collisions<'a, I>(
+  scope: &str,
+  names: I,
+  to_ident: fn(&Name) -> String,
+) -> Result<()>
+where
+  I: IntoIterator<Item = &'a Name>,
+{
+  let mut seen: HashMap<String, &Name> = HashMap::new();
+  for name in names {
+    let ident = to_ident(name);
+    match seen.get(&ident) {
+      Some(other) if other.original != name.original => bail!(
+        "In {}, '{}' and '{}' both become the identifier '{}'",
+        scope,
+        other.original,
+        name.original,
+        ident
+      ),
+      Some(_) => {}
+      None => {
+        seen.insert(ident, name);
+      }
+    }
+  }
+  Ok(())
+}
+
+fn check_address_overlaps(mut ranges: Vec<(&str, u64, u64)>) -> Result<()> {
+  ranges.sort_by_key(|(_, start, _)| *start);
+  let mut furthest: Option<(&str, u64, u64)> = None;
+  for (name, start, end) in ranges {
+    if let Some((other, _, other_end)) = furthest {
+      if start < other_end {
+        bail!(
+          "Peripherals {} and {} overlap at {:#010x}..{:#010x}",
+          other,
+          name,
+          start,
+          end.min(other_end)
+        );
+      }
+    }
+    if furthest.map_or(true, |(_, _, other_end)| end > other_end) {
+      furthest = Some((name, start, end));
+    }
+  }
+  Ok(())
+}
+
+// Array registers (`CCR[%s]`, `S%sCR`) are expanded into one register per
+// index, and depending on how the SVD writes the name, the index may or may
+// not keep its brackets (`CCR[1]` vs `CCR1`). Brackets are ignored so both
+// spellings match `ccr1`.
+fn normalize_register_name(name: &str) -> String {
+  name
+    .chars()
+    .filter(|c| *c != '[' && *c != ']')
+    .collect::<String>()
+    .to_lowercase()
+}
+
+#[allow(dead_code)]
+fn find_register_in_peripheral<'a>(p: &'a PeripheralSpec, name: &str) -> Option<&'a RegisterSpec> {
+  let name = normalize_register_name(name);
+  p.iter_registers().find(|r| match name.contains('.') {
+    true => path_in_peripheral(&r.path()) == name,
+    false => normalize_register_name(&r.name) == name,
+  })
+}
+
+// `dma1.ch2.cr` becomes `ch2.cr`.
+fn path_in_peripheral(path: &str) -> String {
+  normalize_register_name(match path.find('.') {
+    Some(i) => &path[i + 1..],
+    None => path,
+  })
+}
+
+#[allow(dead_code)]
+fn try_find_register_in_peripheral<'a>(
+  p: &'a PeripheralSpec,
+  name: &str,
+) -> Result<&'a RegisterSpec> {
+  find_register_in_peripheral(p, name).ok_or(anyhow!(
+    "Could not find register {} in peripheral {}",
+    name,
+    p.name
+  ))
+}
+
+// Finds a field that is either named `name` itself (`CCR1.CCR1`) or is the
+// value field of a register named `name`, as happens when the register is one
+// element of an array (`CCR[%s].CCR`).
+#[allow(dead_code)]
+fn find_indexed_field_in_peripheral(p: &PeripheralSpec, name: &str) -> Option<FieldSpec> {
+  if let Some(f) = find_field_in_peripheral(p, name) {
+    return Some(f);
+  }
+
+  let register = find_register_in_peripheral(p, name)?;
+  let base_name = name.trim_end_matches(|c: char| c.is_ascii_digit());
+
+  match register.fields.len() {
+    1 => register.fields.first().cloned(),
+    _ => find_field_in_register(register, base_name),
+  }
+}
+
+#[allow(dead_code)]
+fn find_indexed_ranged_field_in_peripheral(p: &PeripheralSpec, name: &str) -> Option<RangedField> {
+  find_indexed_field_in_peripheral(p, name).map(RangedField::from_field_spec)
+}
+
+#[allow(dead_code)]
+fn try_find_indexed_ranged_field_in_peripheral(
+  p: &PeripheralSpec,
+  name: &str,
+) -> Result<RangedField> {
+  find_indexed_ranged_field_in_peripheral(p, name).ok_or(anyhow!(
+    "Could not find field or register {} in peripheral {}",
+    name,
+    p.name
+  ))
+}
+
 #[allow(dead_code)]
 fn find_field_in_register(r: &RegisterSpec, name: &str) -> Option<FieldSpec> {
   r.fields
@@ -282,3 +1056,200 @@ fn try_find_enum_field_in_register(r: &RegisterSpec, name: &str) -> Result<EnumF
     r.name
   ))
 }
+
+#[cfg(test)]
+mod tests {
+  use svd_expander::DeviceSpec;
+
+  use crate::fixup::load_fixed;
+
+  use super::aliases::PeripheralAliases;
+  use super::timer::OutputChannel;
+  use super::*;
+
+  fn peripheral<'a>(device: &'a DeviceSpec, name: &str) -> &'a PeripheralSpec {
+    device.peripherals.iter().find(|p| p.name == name).unwrap()
+  }
+
+  #[test]
+  fn resolves_channels_from_array_registers() {
+    let device = DeviceSpec::from_file("specs/test/dim_arrays.svd").unwrap();
+    let tim1 = peripheral(&device, "TIM1");
+
+    for channel_number in 1..=4 {
+      let channel = OutputChannel::new(tim1, channel_number).unwrap().unwrap();
+      assert_eq!(0xFFFF, channel.compare_field.max);
+    }
+
+    let ccr3 = find_indexed_field_in_peripheral(tim1, "ccr3").unwrap();
+    assert_eq!(0x4001_2C3C, ccr3.address());
+  }
+
+  #[test]
+  fn resolves_registers_with_index_in_name() {
+    let device = DeviceSpec::from_file("specs/test/dim_arrays.svd").unwrap();
+    let dma1 = peripheral(&device, "DMA1");
+
+    let s7cr = find_register_in_peripheral(dma1, "s7cr").unwrap();
+    let en = find_field_in_register(s7cr, "en").unwrap();
+    assert_eq!(0x4002_60B8, en.address());
+  }
+
+  #[test]
+  fn resolves_fields_in_clusters() {
+    let device = DeviceSpec::from_file("specs/test/clusters.svd").unwrap();
+    let dma1 = peripheral(&device, "DMA1");
+
+    let ndt = find_field_in_peripheral(dma1, "ndt").unwrap();
+    assert_eq!(0x4002_000C, ndt.address());
+
+    let en = find_field_in_peripheral(dma1, "ch2.cr.en").unwrap();
+    assert_eq!(0x4002_001C, en.address());
+
+    let ndtr = find_register_in_peripheral(dma1, "ch2.ndtr").unwrap();
+    let ndt = find_field_in_register(ndtr, "ndt").unwrap();
+    assert_eq!(0x4002_0020, ndt.address());
+
+    assert!(find_field_in_peripheral(dma1, "ch3.cr.en").is_none());
+  }
+
+  #[test]
+  fn detects_reserved_enum_bit_patterns() {
+    let device = load_fixed("specs/svd/stm32f303.svd.patched");
+    let tim2 = peripheral(&device, "TIM2");
+
+    let mut compare_mode = OutputChannel::new(tim2, 1).unwrap().unwrap().compare_mode;
+    assert!(compare_mode.covers_all_values());
+
+    compare_mode.values.retain(|v| v.bit_value != 5);
+    assert!(!compare_mode.covers_all_values());
+  }
+
+  #[test]
+  fn rejects_names_that_collide_after_case_conversion() {
+    let names = vec![Name::from("CaptureMode"), Name::from("capture_mode")];
+    let res = check_identifier_collisions("module timer::tim1", names.iter(), Name::snake);
+
+    assert!(res.is_err());
+    assert_eq!(
+      "In module timer::tim1, 'CaptureMode' and 'capture_mode' both become the identifier 'capture_mode'",
+      res.unwrap_err().to_string()
+    );
+  }
+
+  #[test]
+  fn infers_bus_clock_from_enable_register() {
+    assert_eq!(Some("p_clk1"), bus_clock_tap("RCC.APB1ENR.TIM2EN"));
+    assert_eq!(Some("p_clk2"), bus_clock_tap("rcc.apb2enr.spi1en"));
+    assert_eq!(Some("h_clk"), bus_clock_tap("rcc.ahbenr.iopaen"));
+    assert_eq!(None, bus_clock_tap("rcc.bdcr.rtcen"));
+  }
+
+  #[test]
+  fn clears_flags_through_icr() {
+    let device = load_fixed("specs/svd/stm32f303.svd.patched");
+    let flags = StatusFlag::find_all(peripheral(&device, "USART1"));
+    let flag = |name: &str| flags.iter().find(|f| f.name.snake() == name);
+
+    assert_eq!("usart1.icr.tccf", flag("tc").unwrap().path);
+    assert_eq!(FlagClear::WriteOne, flag("tc").unwrap().clear);
+    assert_eq!("usart1.icr.ncf", flag("nf").unwrap().path);
+    assert_eq!("usart1.icr.ctscf", flag("ctsif").unwrap().path);
+    assert!(flag("cts").is_none());
+    assert!(flag("txe").is_none());
+  }
+
+  #[test]
+  fn clears_rc_w0_flags_by_writing_zero() {
+    let mut device = load_fixed("specs/svd/stm32f303.svd.patched");
+    let tim2 = device
+      .peripherals
+      .iter_mut()
+      .find(|p| p.name == "TIM2")
+      .unwrap();
+    for register in tim2.registers.iter_mut().filter(|r| r.name == "SR") {
+      for field in register.fields.iter_mut().filter(|f| f.name == "UIF") {
+        field.modified_write_values = Some(ModifiedWriteValuesSpec::ZeroToClear);
+      }
+    }
+
+    let flags = StatusFlag::find_all(peripheral(&device, "TIM2"));
+    assert_eq!(1, flags.len());
+    assert_eq!("tim2.sr.uif", flags[0].path);
+    assert_eq!(FlagClear::WriteZero, flags[0].clear);
+  }
+
+  fn aliased(ron: &str) -> Result<()> {
+    let device = load_fixed("specs/svd/stm32f303.svd.patched");
+    SystemInfo::with_aliases(&device, PeripheralAliases::from_ron(ron).unwrap()).map(|_| ())
+  }
+
+  #[test]
+  fn names_modules_after_aliases() {
+    let device = load_fixed("specs/svd/stm32f303.svd.patched");
+    let aliases =
+      PeripheralAliases::from_ron(r#"PeripheralAliases(peripherals: { "TIM2": "motor_timer" })"#)
+        .unwrap();
+    let sys_info = SystemInfo::with_aliases(&device, aliases).unwrap();
+
+    let timer = sys_info
+      .timers
+      .iter()
+      .find(|t| t.name.original == "TIM2")
+      .unwrap();
+    assert_eq!("motor_timer", timer.name.snake());
+    assert_eq!("MotorTimer", timer.name.camel());
+    assert_eq!("tim2", timer.name.unaliased_snake());
+    assert!(sys_info
+      .submodules()
+      .iter()
+      .any(|s| s.parent_path == "timer" && s.name.snake() == "motor_timer"));
+  }
+
+  #[test]
+  fn finds_overlapping_peripherals() {
+    let device = load_fixed("specs/svd/stm32f303.svd.patched");
+    assert!(SystemInfo::new(&device)
+      .unwrap()
+      .check_address_overlaps()
+      .is_ok());
+
+    assert!(check_address_overlaps(vec![("TIM2", 0x100, 0x150), ("TIM3", 0x150, 0x200)]).is_ok());
+    let err = check_address_overlaps(vec![
+      ("TIM3", 0x400, 0x450),
+      ("TIM2", 0x100, 0x500),
+      ("TIM4", 0x600, 0x650),
+    ])
+    .unwrap_err();
+    assert_eq!(
+      "Peripherals TIM2 and TIM3 overlap at 0x00000400..0x00000450",
+      err.to_string()
+    );
+  }
+
+  #[test]
+  fn rejects_bad_aliases() {
+    assert!(aliased(r#"PeripheralAliases(peripherals: { "TIM2": "tim3" })"#).is_err());
+    assert!(aliased(
+      r#"PeripheralAliases(peripherals: { "TIM2": "motor_timer", "TIM3": "motor_timer" })"#
+    )
+    .is_err());
+    assert!(aliased(r#"PeripheralAliases(peripherals: { "TIM99": "spare_timer" })"#).is_err());
+    assert!(aliased(r#"PeripheralAliases(peripherals: { "TIM2": "MotorTimer" })"#).is_err());
+  }
+
+  #[test]
+  fn retains_listed_families() {
+    let device = load_fixed("specs/svd/stm32f303.svd.patched");
+    let mut sys_info = SystemInfo::new(&device).unwrap();
+    assert!(!sys_info.timers.is_empty() && sys_info.syscfg.is_some());
+
+    let families = vec!["gpio".to_owned(), "timer".to_owned()];
+    sys_info.retain_families(&families).unwrap();
+    assert!(!sys_info.gpios.is_empty() && !sys_info.timers.is_empty());
+    assert!(sys_info.spis.is_empty() && sys_info.syscfg.is_none() && sys_info.flash.is_none());
+
+    assert!(sys_info.retain_families(&["syscfg".to_owned()]).is_err());
+    assert!(sys_info.retain_families(&["uart".to_owned()]).is_err());
+  }
+}
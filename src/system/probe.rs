@@ -0,0 +1,73 @@
+// The SVDs describe a whole line, but probe-rs wants the exact part to know
+// its flash size. Each line maps to the part on its most common development
+// board, which is what the includes are written for.
+const PROBE_RS_CHIPS: &[(&str, &str)] = &[
+  ("stm32f0x2", "STM32F072RBTx"),
+  ("stm32f103", "STM32F103C8"),
+  ("stm32f303", "STM32F303VCTx"),
+  ("stm32f401", "STM32F401RETx"),
+  ("stm32f407", "STM32F407VGTx"),
+  ("stm32f411", "STM32F411RETx"),
+  ("stm32f429", "STM32F429ZITx"),
+  ("stm32f446", "STM32F446RETx"),
+  ("stm32f7x6", "STM32F746NGHx"),
+  ("stm32g07x", "STM32G071RBTx"),
+  ("stm32g431xx", "STM32G431RBTx"),
+  ("stm32g474xx", "STM32G474RETx"),
+  ("stm32h743x", "STM32H743ZITx"),
+  ("stm32l0x3", "STM32L053R8Tx"),
+  ("stm32l4x6", "STM32L476RGTx"),
+];
+
+// Compilation targets by family prefix, for the runner configuration. The
+// first matching prefix wins.
+const RUST_TARGETS: &[(&str, &str)] = &[
+  ("stm32f0", "thumbv6m-none-eabi"),
+  ("stm32g0", "thumbv6m-none-eabi"),
+  ("stm32l0", "thumbv6m-none-eabi"),
+  ("stm32f1", "thumbv7m-none-eabi"),
+  ("stm32f2", "thumbv7m-none-eabi"),
+  ("stm32l1", "thumbv7m-none-eabi"),
+  ("stm32f3", "thumbv7em-none-eabihf"),
+  ("stm32f4", "thumbv7em-none-eabihf"),
+  ("stm32f7", "thumbv7em-none-eabihf"),
+  ("stm32g4", "thumbv7em-none-eabihf"),
+  ("stm32h7", "thumbv7em-none-eabihf"),
+  ("stm32l4", "thumbv7em-none-eabihf"),
+  ("stm32l5", "thumbv8m.main-none-eabihf"),
+];
+
+pub fn probe_rs_chip(device_name: &str) -> Option<&'static str> {
+  let device_name = device_name.to_lowercase();
+  PROBE_RS_CHIPS
+    .iter()
+    .find(|(name, _)| *name == device_name)
+    .map(|(_, chip)| *chip)
+}
+
+pub fn rust_target(device_name: &str) -> Option<&'static str> {
+  let device_name = device_name.to_lowercase();
+  RUST_TARGETS
+    .iter()
+    .find(|(prefix, _)| device_name.starts_with(prefix))
+    .map(|(_, target)| *target)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn maps_lines_to_board_parts() {
+    assert_eq!(Some("STM32F303VCTx"), probe_rs_chip("STM32F303"));
+    assert_eq!(Some("STM32F103C8"), probe_rs_chip("STM32F103"));
+    assert_eq!(None, probe_rs_chip("STM32F302"));
+  }
+
+  #[test]
+  fn picks_target_by_family() {
+    assert_eq!(Some("thumbv6m-none-eabi"), rust_target("STM32F0x2"));
+    assert_eq!(Some("thumbv7em-none-eabihf"), rust_target("STM32F302"));
+    assert_eq!(None, rust_target("ARM_Example"));
+  }
+}
@@ -0,0 +1,103 @@
+use anyhow::{bail, Result};
+use regex::Regex;
+use svd_expander::{DeviceSpec, PeripheralSpec};
+
+use super::*;
+
+#[derive(Clone)]
+pub struct Pwr {
+  pub name: Name,
+  pub peripheral_enable_field: String,
+  pub pdds_field: String,
+  pub lpds_field: String,
+  pub cwuf_field: String,
+  pub vos_field: Option<RangedField>,
+  pub wakeup_pins: Vec<WakeupPin>,
+}
+impl Pwr {
+  pub fn new(device: &DeviceSpec, peripheral: &PeripheralSpec) -> Result<Option<Self>> {
+    let rcc = match device
+      .peripherals
+      .iter()
+      .find(|p| p.name.to_lowercase() == "rcc")
+    {
+      Some(p) => p,
+      None => bail!("Could not find RCC peripheral"),
+    };
+
+    // Only the CR/CSR layout of the F0/F1/F2/F3/F4/F7/L0/L1 parts is supported.
+    // Newer parts select the low-power mode with an LPMS field instead.
+    let cr = match peripheral
+      .iter_registers()
+      .find(|r| r.name.to_lowercase() == "cr")
+    {
+      Some(r) => r,
+      None => {
+        warn!("Skipping PWR because it has no CR register.");
+        return Ok(None);
+      }
+    };
+
+    let peripheral_enable_field = match find_field_in_peripheral(rcc, "pwren") {
+      Some(f) => f.path(),
+      None => {
+        warn!("Skipping PWR because RCC has no PWREN field.");
+        return Ok(None);
+      }
+    };
+
+    // The low-power regulator bit in stop mode is LPSDSR on L0/L1 parts.
+    let lpds_field = match find_field_in_register(cr, "lpds") {
+      Some(f) => f.path(),
+      None => try_find_field_in_register(cr, "lpsdsr")?.path(),
+    };
+
+    let wakeup_pin_test = Regex::new(r"^ewup([0-9]*)$")?;
+    let wakeup_pins = peripheral
+      .iter_fields()
+      .filter_map(|f| {
+        wakeup_pin_test
+          .captures(&f.name.to_lowercase())
+          .map(|c| WakeupPin {
+            name: Name::from(format!("wakeup_pin{}", &c[1])),
+            enable_field: f.path(),
+          })
+      })
+      .collect::<Vec<WakeupPin>>();
+
+    Ok(Some(Self {
+      name: Name::from("pwr"),
+      peripheral_enable_field,
+      pdds_field: try_find_field_in_register(cr, "pdds")?.path(),
+      lpds_field,
+      cwuf_field: try_find_field_in_register(cr, "cwuf")?.path(),
+      vos_field: find_ranged_field_in_register(cr, "vos"),
+      wakeup_pins,
+    }))
+  }
+
+  pub fn submodule(&self) -> Submodule {
+    Submodule {
+      parent_path: "pwr".to_owned(),
+      name: self.name.clone(),
+      needs_clocks: false,
+    }
+  }
+
+  pub fn has_vos_field(&self) -> bool {
+    self.vos_field.is_some()
+  }
+
+  pub fn vos_field(&self) -> RangedField {
+    match self.vos_field {
+      Some(ref f) => f.clone(),
+      None => panic!("PWR has no voltage scaling field."),
+    }
+  }
+}
+
+#[derive(Clone)]
+pub struct WakeupPin {
+  pub name: Name,
+  pub enable_field: String,
+}
@@ -0,0 +1,141 @@
+use anyhow::{bail, Result};
+use svd_expander::{DeviceSpec, PeripheralSpec};
+
+use super::*;
+
+#[derive(Clone)]
+pub struct Qspi {
+  pub name: Name,
+  pub peripheral_enable_field: String,
+  pub en_field: String,
+  pub prescaler_field: RangedField,
+  pub flash_size_field: RangedField,
+  pub fmode_field: String,
+  pub imode_field: String,
+  pub instruction_field: String,
+  pub admode_field: String,
+  pub adsize_field: String,
+  pub dmode_field: String,
+  pub dcyc_field: RangedField,
+  pub dl_field: String,
+  pub address_field: String,
+  pub data_address: u32,
+  pub busy_field: String,
+  pub ftf_field: String,
+  pub tcf_field: String,
+  pub ctcf_field: String,
+}
+impl Qspi {
+  pub fn new(device: &DeviceSpec, peripheral: &PeripheralSpec) -> Result<Option<Self>> {
+    let name = Name::from(&peripheral.name);
+
+    let rcc = match device
+      .peripherals
+      .iter()
+      .find(|p| p.name.to_lowercase() == "rcc")
+    {
+      Some(p) => p,
+      None => bail!("Could not find RCC peripheral"),
+    };
+
+    // QUADSPI is enabled with QSPIEN, OCTOSPIn with OSPInEN.
+    let enable_field_name = match peripheral.name.to_lowercase().strip_prefix("octospi") {
+      Some(number) => f!("ospi{number}en"),
+      None => "qspien".to_owned(),
+    };
+
+    let peripheral_enable_field = match find_field_in_peripheral(rcc, &enable_field_name) {
+      Some(f) => f.path(),
+      None => {
+        warn!(
+          "Skipping {} because RCC has no {} field.",
+          name.camel(),
+          enable_field_name
+        );
+        return Ok(None);
+      }
+    };
+
+    let cr = try_find_register_in_peripheral(peripheral, "cr")?;
+    let ccr = try_find_register_in_peripheral(peripheral, "ccr")?;
+    let sr = try_find_register_in_peripheral(peripheral, "sr")?;
+    let fcr = try_find_register_in_peripheral(peripheral, "fcr")?;
+
+    // OCTOSPI moves a few of the QUADSPI fields into their own registers.
+    let (flash_size_field, prescaler_field, instruction_field, dcyc_field) =
+      match find_field_in_peripheral(peripheral, "fsize") {
+        Some(fsize) => (
+          RangedField::from_field_spec(fsize),
+          try_find_ranged_field_in_register(cr, "prescaler")?,
+          try_find_field_in_register(ccr, "instruction")?.path(),
+          try_find_ranged_field_in_register(ccr, "dcyc")?,
+        ),
+        None => (
+          try_find_ranged_field_in_register(
+            try_find_register_in_peripheral(peripheral, "dcr1")?,
+            "devsize",
+          )?,
+          try_find_ranged_field_in_register(
+            try_find_register_in_peripheral(peripheral, "dcr2")?,
+            "prescaler",
+          )?,
+          try_find_field_in_register(
+            try_find_register_in_peripheral(peripheral, "ir")?,
+            "instruction",
+          )?
+          .path(),
+          try_find_ranged_field_in_register(
+            try_find_register_in_peripheral(peripheral, "tcr")?,
+            "dcyc",
+          )?,
+        ),
+      };
+
+    let fmode_field = match find_field_in_register(ccr, "fmode") {
+      Some(f) => f.path(),
+      None => try_find_field_in_register(cr, "fmode")?.path(),
+    };
+
+    Ok(Some(Self {
+      name,
+      peripheral_enable_field,
+      en_field: try_find_field_in_register(cr, "en")?.path(),
+      prescaler_field,
+      flash_size_field,
+      fmode_field,
+      imode_field: try_find_field_in_register(ccr, "imode")?.path(),
+      instruction_field,
+      admode_field: try_find_field_in_register(ccr, "admode")?.path(),
+      adsize_field: try_find_field_in_register(ccr, "adsize")?.path(),
+      dmode_field: try_find_field_in_register(ccr, "dmode")?.path(),
+      dcyc_field,
+      dl_field: try_find_field_in_register(
+        try_find_register_in_peripheral(peripheral, "dlr")?,
+        "dl",
+      )?
+      .path(),
+      address_field: try_find_field_in_register(
+        try_find_register_in_peripheral(peripheral, "ar")?,
+        "address",
+      )?
+      .path(),
+      data_address: try_find_field_in_register(
+        try_find_register_in_peripheral(peripheral, "dr")?,
+        "data",
+      )?
+      .address(),
+      busy_field: try_find_field_in_register(sr, "busy")?.path(),
+      ftf_field: try_find_field_in_register(sr, "ftf")?.path(),
+      tcf_field: try_find_field_in_register(sr, "tcf")?.path(),
+      ctcf_field: try_find_field_in_register(fcr, "ctcf")?.path(),
+    }))
+  }
+
+  pub fn submodule(&self) -> Submodule {
+    Submodule {
+      parent_path: "qspi".to_owned(),
+      name: self.name.clone(),
+      needs_clocks: false,
+    }
+  }
+}
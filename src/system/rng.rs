@@ -0,0 +1,61 @@
+use anyhow::{bail, Result};
+use svd_expander::{DeviceSpec, PeripheralSpec};
+
+use super::*;
+
+#[derive(Clone)]
+pub struct Rng {
+  pub name: Name,
+  pub peripheral_enable_field: String,
+  pub rngen_field: String,
+  pub ie_field: String,
+  pub drdy_field: String,
+  pub cecs_field: String,
+  pub secs_field: String,
+  pub seis_field: String,
+  pub data_field: String,
+}
+impl Rng {
+  pub fn new(device: &DeviceSpec, peripheral: &PeripheralSpec) -> Result<Option<Self>> {
+    let rcc = match device
+      .peripherals
+      .iter()
+      .find(|p| p.name.to_lowercase() == "rcc")
+    {
+      Some(p) => p,
+      None => bail!("Could not find RCC peripheral"),
+    };
+
+    let peripheral_enable_field = match find_field_in_peripheral(rcc, "rngen") {
+      Some(f) => f.path(),
+      None => {
+        warn!("Skipping RNG because RCC has no RNGEN field.");
+        return Ok(None);
+      }
+    };
+
+    let cr = try_find_register_in_peripheral(peripheral, "cr")?;
+    let sr = try_find_register_in_peripheral(peripheral, "sr")?;
+    let dr = try_find_register_in_peripheral(peripheral, "dr")?;
+
+    Ok(Some(Self {
+      name: Name::from("rng"),
+      peripheral_enable_field,
+      rngen_field: try_find_field_in_register(cr, "rngen")?.path(),
+      ie_field: try_find_field_in_register(cr, "ie")?.path(),
+      drdy_field: try_find_field_in_register(sr, "drdy")?.path(),
+      cecs_field: try_find_field_in_register(sr, "cecs")?.path(),
+      secs_field: try_find_field_in_register(sr, "secs")?.path(),
+      seis_field: try_find_field_in_register(sr, "seis")?.path(),
+      data_field: try_find_field_in_register(dr, "rndata")?.path(),
+    }))
+  }
+
+  pub fn submodule(&self) -> Submodule {
+    Submodule {
+      parent_path: "rng".to_owned(),
+      name: self.name.clone(),
+      needs_clocks: false,
+    }
+  }
+}
@@ -0,0 +1,141 @@
+use anyhow::{anyhow, bail, Result};
+use svd_expander::{DeviceSpec, FieldSpec, PeripheralSpec};
+
+use super::*;
+
+#[derive(Clone)]
+pub struct Sai {
+  pub name: Name,
+  pub peripheral_enable_field: String,
+  pub mode: EnumField,
+  pub protocol: EnumField,
+  pub data_size: EnumField,
+  pub blocks: Vec<SaiBlock>,
+}
+impl Sai {
+  pub fn new(device: &DeviceSpec, peripheral: &PeripheralSpec) -> Result<Option<Self>> {
+    let name = Name::from(&peripheral.name);
+    let enable_field_name = format!("{}en", name.snake());
+
+    let rcc = match device
+      .peripherals
+      .iter()
+      .find(|p| p.name.to_lowercase() == "rcc")
+    {
+      Some(p) => p,
+      None => bail!("Could not find RCC peripheral"),
+    };
+
+    let blocks = vec![
+      SaiBlock::new(peripheral, 'a')?,
+      SaiBlock::new(peripheral, 'b')?,
+    ];
+
+    // Both blocks share the same register layout, so the enumerated values
+    // from block A are used for the mode, protocol, and data size types.
+    let mode = try_find_enum_field_in_block(peripheral, 'a', "mode")?;
+    let protocol = try_find_enum_field_in_block(peripheral, 'a', "prtcfg")?;
+    let data_size = try_find_enum_field_in_block(peripheral, 'a', "ds")?;
+
+    if mode.values.len() == 0 || protocol.values.len() == 0 || data_size.values.len() == 0 {
+      warn!(
+        "Skipping SAI {} because its mode, protocol, or data size fields have no enumerated values.",
+        name.camel()
+      );
+      return Ok(None);
+    }
+
+    Ok(Some(Self {
+      name,
+      peripheral_enable_field: try_find_field_in_peripheral(rcc, &enable_field_name)?.path(),
+      mode,
+      protocol,
+      data_size,
+      blocks,
+    }))
+  }
+
+  pub fn submodule(&self) -> Submodule {
+    Submodule {
+      parent_path: "sai".to_owned(),
+      name: self.name.clone(),
+      needs_clocks: false,
+    }
+  }
+}
+
+#[derive(Clone)]
+pub struct SaiBlock {
+  pub name: Name,
+  pub saien_field: String,
+  pub mode_field: String,
+  pub protocol_field: String,
+  pub data_size_field: String,
+  pub master_clock_divider_field: RangedField,
+  pub mute_field: String,
+  pub fifo_flush_field: String,
+  pub frame_length_field: RangedField,
+  pub active_frame_length_field: RangedField,
+  pub slot_count_field: RangedField,
+  pub slot_enable_field: RangedField,
+  pub fifo_level_field: String,
+  pub data_field: String,
+}
+impl SaiBlock {
+  pub fn new(peripheral: &PeripheralSpec, block: char) -> Result<Self> {
+    let master_clock_divider_field = match find_field_in_block(peripheral, block, "mckdiv") {
+      Some(f) => RangedField::from_field_spec(f),
+      // Some SVDs misspell MCKDIV as MCJDIV.
+      None => RangedField::from_field_spec(try_find_field_in_block(peripheral, block, "mcjdiv")?),
+    };
+
+    Ok(Self {
+      name: Name::from(f!("block_{block}")),
+      saien_field: try_find_field_in_block(peripheral, block, "saien")?.path(),
+      mode_field: try_find_field_in_block(peripheral, block, "mode")?.path(),
+      protocol_field: try_find_field_in_block(peripheral, block, "prtcfg")?.path(),
+      data_size_field: try_find_field_in_block(peripheral, block, "ds")?.path(),
+      master_clock_divider_field,
+      mute_field: try_find_field_in_block(peripheral, block, "mute")?.path(),
+      fifo_flush_field: try_find_field_in_block(peripheral, block, "fflush")?.path(),
+      frame_length_field: RangedField::from_field_spec(try_find_field_in_block(
+        peripheral, block, "frl",
+      )?),
+      active_frame_length_field: RangedField::from_field_spec(try_find_field_in_block(
+        peripheral, block, "fsall",
+      )?),
+      slot_count_field: RangedField::from_field_spec(try_find_field_in_block(
+        peripheral, block, "nbslot",
+      )?),
+      slot_enable_field: RangedField::from_field_spec(try_find_field_in_block(
+        peripheral, block, "sloten",
+      )?),
+      fifo_level_field: try_find_field_in_block(peripheral, block, "flvl")?.path(),
+      data_field: try_find_field_in_block(peripheral, block, "data")?.path(),
+    })
+  }
+}
+
+// The SAI sub-blocks are described as a two-element cluster (CHA and CHB) in the SVD,
+// so fields with the same name exist once per block and are told apart by path.
+fn find_field_in_block(p: &PeripheralSpec, block: char, name: &str) -> Option<FieldSpec> {
+  let cluster = f!(".ch{block}.");
+  p.iter_fields()
+    .find(|f| {
+      f.name.to_lowercase() == name.to_lowercase() && f.path().to_lowercase().contains(&cluster)
+    })
+    .map(|f| f.clone())
+}
+
+fn try_find_field_in_block(p: &PeripheralSpec, block: char, name: &str) -> Result<FieldSpec> {
+  find_field_in_block(p, block, name).ok_or(anyhow!(
+    "Could not find field {} in block {} of peripheral {}",
+    name,
+    block,
+    p.name
+  ))
+}
+
+fn try_find_enum_field_in_block(p: &PeripheralSpec, block: char, name: &str) -> Result<EnumField> {
+  try_find_field_in_block(p, block, name).map(EnumField::from_field_spec)
+}
@@ -0,0 +1,128 @@
+// The unique device ID and flash size are factory-programmed into system
+// memory at addresses the SVDs don't describe, so they're looked up by family.
+// The first matching prefix wins.
+const SIGNATURE_ADDRESSES: &[(&str, [u32; 3], u32)] = &[
+  (
+    "stm32f0",
+    [0x1FFF_F7AC, 0x1FFF_F7B0, 0x1FFF_F7B4],
+    0x1FFF_F7CC,
+  ),
+  (
+    "stm32f1",
+    [0x1FFF_F7E8, 0x1FFF_F7EC, 0x1FFF_F7F0],
+    0x1FFF_F7E0,
+  ),
+  (
+    "stm32f2",
+    [0x1FFF_7A10, 0x1FFF_7A14, 0x1FFF_7A18],
+    0x1FFF_7A22,
+  ),
+  (
+    "stm32f3",
+    [0x1FFF_F7AC, 0x1FFF_F7B0, 0x1FFF_F7B4],
+    0x1FFF_F7CC,
+  ),
+  (
+    "stm32f4",
+    [0x1FFF_7A10, 0x1FFF_7A14, 0x1FFF_7A18],
+    0x1FFF_7A22,
+  ),
+  (
+    "stm32f730",
+    [0x1FF0_7A10, 0x1FF0_7A14, 0x1FF0_7A18],
+    0x1FF0_7A22,
+  ),
+  (
+    "stm32f7x2",
+    [0x1FF0_7A10, 0x1FF0_7A14, 0x1FF0_7A18],
+    0x1FF0_7A22,
+  ),
+  (
+    "stm32f7x3",
+    [0x1FF0_7A10, 0x1FF0_7A14, 0x1FF0_7A18],
+    0x1FF0_7A22,
+  ),
+  (
+    "stm32f7",
+    [0x1FF0_F420, 0x1FF0_F424, 0x1FF0_F428],
+    0x1FF0_F442,
+  ),
+  (
+    "stm32g0",
+    [0x1FFF_7590, 0x1FFF_7594, 0x1FFF_7598],
+    0x1FFF_75E0,
+  ),
+  (
+    "stm32g4",
+    [0x1FFF_7590, 0x1FFF_7594, 0x1FFF_7598],
+    0x1FFF_75E0,
+  ),
+  (
+    "stm32h7b",
+    [0x08FF_F800, 0x08FF_F804, 0x08FF_F808],
+    0x08FF_F80C,
+  ),
+  (
+    "stm32h7",
+    [0x1FF1_E800, 0x1FF1_E804, 0x1FF1_E808],
+    0x1FF1_E880,
+  ),
+  // The L0 ID words aren't contiguous.
+  (
+    "stm32l0",
+    [0x1FF8_0050, 0x1FF8_0054, 0x1FF8_0064],
+    0x1FF8_007C,
+  ),
+  (
+    "stm32l4",
+    [0x1FFF_7590, 0x1FFF_7594, 0x1FFF_7598],
+    0x1FFF_75E0,
+  ),
+  (
+    "stm32l5",
+    [0x0BFA_0590, 0x0BFA_0594, 0x0BFA_0598],
+    0x0BFA_05E0,
+  ),
+];
+
+#[derive(Clone)]
+pub struct Signature {
+  pub uid_addresses: Vec<u32>,
+  pub flash_size_address: u32,
+}
+impl Signature {
+  pub fn for_device(device_name: &str) -> Option<Self> {
+    let device_name = device_name.to_lowercase();
+    SIGNATURE_ADDRESSES
+      .iter()
+      .find(|(prefix, _, _)| device_name.starts_with(prefix))
+      .map(|(_, uid_addresses, flash_size_address)| Self {
+        uid_addresses: uid_addresses.to_vec(),
+        flash_size_address: *flash_size_address,
+      })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn finds_signature_by_family() {
+    let signature = Signature::for_device("STM32F303").unwrap();
+    assert_eq!(
+      vec![0x1FFF_F7AC, 0x1FFF_F7B0, 0x1FFF_F7B4],
+      signature.uid_addresses
+    );
+    assert_eq!(0x1FFF_F7CC, signature.flash_size_address);
+
+    let signature = Signature::for_device("STM32F7x3").unwrap();
+    assert_eq!(0x1FF0_7A22, signature.flash_size_address);
+  }
+
+  #[test]
+  fn has_no_signature_for_unknown_family() {
+    // The L1 addresses depend on the density category, not just the family.
+    assert!(Signature::for_device("STM32L151").is_none());
+  }
+}
@@ -34,6 +34,10 @@ pub struct Spi {
   pub dr_field: String,
 
   pub bsy_field: String,
+  pub txe_field: String,
+  pub rxne_field: String,
+  pub status_flags: Vec<StatusFlag>,
+  pub bus_clock: Option<String>,
 }
 impl Spi {
   pub fn new(device: &DeviceSpec, peripheral: &PeripheralSpec) -> Result<Self> {
@@ -114,6 +118,10 @@ impl Spi {
       dr_field: try_find_field_in_peripheral(peripheral, "dr")?.path(),
 
       bsy_field: try_find_field_in_register(sr, "bsy")?.path(),
+      txe_field: try_find_field_in_register(sr, "txe")?.path(),
+      rxne_field: try_find_field_in_register(sr, "rxne")?.path(),
+      status_flags: StatusFlag::find_all(peripheral),
+      bus_clock: None,
     })
   }
 
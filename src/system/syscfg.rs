@@ -0,0 +1,89 @@
+use anyhow::{bail, Result};
+use svd_expander::{DeviceSpec, PeripheralSpec};
+
+use super::*;
+
+// System configuration glue outside the F1 family, which has AFIO instead.
+#[derive(Clone)]
+pub struct Syscfg {
+  pub name: Name,
+  pub peripheral_enable_field: String,
+  pub memory_remap_field: Option<String>,
+  pub exti_lines: Vec<ExtiLine>,
+}
+impl Syscfg {
+  pub fn new(device: &DeviceSpec, peripheral: &PeripheralSpec) -> Result<Option<Self>> {
+    let rcc = match device
+      .peripherals
+      .iter()
+      .find(|p| p.name.to_lowercase() == "rcc")
+    {
+      Some(p) => p,
+      None => bail!("Could not find RCC peripheral"),
+    };
+
+    let peripheral_enable_field = match find_field_in_peripheral(rcc, "syscfgen") {
+      Some(f) => f.path().to_lowercase(),
+      None => {
+        warn!("Skipping SYSCFG because RCC has no SYSCFGEN field.");
+        return Ok(None);
+      }
+    };
+
+    Ok(Some(Self {
+      name: Name::from("syscfg"),
+      peripheral_enable_field,
+      memory_remap_field: find_field_in_peripheral(peripheral, "mem_mode")
+        .map(|f| f.path().to_lowercase()),
+      exti_lines: ExtiLine::find_all(peripheral),
+    }))
+  }
+
+  pub fn submodule(&self) -> Submodule {
+    Submodule {
+      parent_path: "syscfg".to_owned(),
+      name: self.name.clone(),
+      needs_clocks: false,
+    }
+  }
+
+  pub fn has_memory_remap(&self) -> bool {
+    self.memory_remap_field.is_some()
+  }
+
+  pub fn memory_remap_field(&self) -> String {
+    match self.memory_remap_field {
+      Some(ref f) => f.clone(),
+      None => panic!("SYSCFG has no MEM_MODE field."),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::fixup::load_fixed;
+
+  use super::*;
+
+  #[test]
+  fn finds_memory_remap_and_exti_lines() {
+    let device = load_fixed("specs/svd/stm32f303.svd.patched");
+    let peripheral = device
+      .peripherals
+      .iter()
+      .find(|p| p.name == "SYSCFG")
+      .unwrap();
+    let syscfg = Syscfg::new(&device, peripheral).unwrap().unwrap();
+
+    assert_eq!("syscfg.cfgr1.mem_mode", syscfg.memory_remap_field());
+    assert_eq!(
+      (0..16).collect::<Vec<u32>>(),
+      syscfg
+        .exti_lines
+        .iter()
+        .map(|l| l.number)
+        .collect::<Vec<u32>>()
+    );
+    assert_eq!("syscfg.exticr4.exti13", syscfg.exti_lines[13].path);
+  }
+}
@@ -12,9 +12,20 @@ pub struct Timer {
   pub counter_field: RangedField,
   pub arpe_field: String,
   pub ug_field: String,
+  pub uif_field: String,
+  pub uie_field: String,
   pub cen_field: String,
   pub moe_field: Option<String>,
+  pub break_enable_field: Option<String>,
+  pub break_polarity_field: Option<String>,
+  pub automatic_output_enable_field: Option<String>,
+  pub dead_time_field: Option<String>,
+  pub master_mode_field: Option<String>,
+  pub slave_mode_field: Option<String>,
+  pub trigger_select_field: Option<String>,
   pub channels: Vec<TimerChannel>,
+  pub status_flags: Vec<StatusFlag>,
+  pub bus_clock: Option<String>,
 }
 impl Timer {
   pub fn new(device: &DeviceSpec, peripheral: &PeripheralSpec) -> Result<Option<Self>> {
@@ -31,56 +42,39 @@ impl Timer {
     };
 
     let mut channels: Vec<TimerChannel> = Vec::new();
-    for channel_number in 1..=10 {
+    for channel_number in channel_numbers(peripheral) {
       if let Some(tc) = TimerChannel::new(peripheral, channel_number)? {
         channels.push(tc);
       }
     }
 
-    // Fill in empty compare mode enums in case the SVD doesn't have proper inheritance
-    if let Some(good_enum) = channels
+    // The fixups fill in values missing from derived fields, so a timer with
+    // none at all can't be generated.
+    if channels
       .iter()
-      .find(|c| c.is_output() && c.as_output().compare_mode.values.len() > 0)
-      .map(|c| c.as_output().compare_mode.clone())
+      .any(|c| c.is_output() && c.as_output().compare_mode.values.is_empty())
     {
-      for channel in channels
-        .iter_mut()
-        .filter(|c| c.is_output() && c.as_output().compare_mode.values.len() == 0)
-      {
-        channel
-          .as_output_mut()
-          .compare_mode
-          .clone_values_from(&good_enum);
-      }
-    } else {
-      if channels.iter().filter(|c| c.is_output()).count() > 0 {
-        warn!("Skipping timer {} because it has output channels but none of them have enumerated compare mode values.", name.camel());
-        return Ok(None);
-      }
+      warn!(
+        "Skipping timer {} because it has output channels without enumerated compare mode values.",
+        name.camel()
+      );
+      return Ok(None);
     }
-
-    // Fill in empty capture filter enums in case the SVD doesn't have proper inheritance
-    if let Some(good_enum) = channels
+    if channels
       .iter()
-      .find(|c| c.is_input() && c.as_input().capture_filter.values.len() > 0)
-      .map(|c| c.as_input().capture_filter.clone())
+      .any(|c| c.is_input() && c.as_input().capture_filter.values.is_empty())
     {
-      for channel in channels
-        .iter_mut()
-        .filter(|c| c.is_input() && c.as_input().capture_filter.values.len() == 0)
-      {
-        channel
-          .as_input_mut()
-          .capture_filter
-          .clone_values_from(&good_enum);
-      }
-    } else {
-      if channels.iter().filter(|c| c.is_input()).count() > 0 {
-        warn!("Skipping timer {} because it has input channels but none of them have enumerated capture filter values.", name.camel());
-        return Ok(None);
-      }
+      warn!(
+        "Skipping timer {} because it has input channels without enumerated capture filter values.",
+        name.camel()
+      );
+      return Ok(None);
     }
 
+    // UIF gets `clear_update_flag` from the `Timer` trait rather than a
+    // `clear_uif` of its own.
+    let uif_field = try_find_field_in_peripheral(peripheral, "uif")?.path();
+
     Ok(Some(Self {
       name: name.clone(),
       peripheral_enable_field: try_find_field_in_peripheral(rcc, &enable_field_name)?.path(),
@@ -89,9 +83,23 @@ impl Timer {
       counter_field: try_find_ranged_field_in_peripheral(peripheral, "cnt")?,
       arpe_field: try_find_field_in_peripheral(peripheral, "arpe")?.path(),
       ug_field: try_find_field_in_peripheral(peripheral, "ug")?.path(),
+      uif_field: uif_field.clone(),
+      uie_field: try_find_field_in_peripheral(peripheral, "uie")?.path(),
       cen_field: try_find_field_in_peripheral(peripheral, "cen")?.path(),
       moe_field: find_field_in_peripheral(peripheral, "moe").map(|f| f.path()),
+      break_enable_field: find_field_in_peripheral(peripheral, "bke").map(|f| f.path()),
+      break_polarity_field: find_field_in_peripheral(peripheral, "bkp").map(|f| f.path()),
+      automatic_output_enable_field: find_field_in_peripheral(peripheral, "aoe").map(|f| f.path()),
+      dead_time_field: find_field_in_peripheral(peripheral, "dtg").map(|f| f.path()),
+      master_mode_field: find_field_in_peripheral(peripheral, "mms").map(|f| f.path()),
+      slave_mode_field: find_field_in_peripheral(peripheral, "sms").map(|f| f.path()),
+      trigger_select_field: find_field_in_peripheral(peripheral, "ts").map(|f| f.path()),
       channels,
+      status_flags: StatusFlag::find_all(peripheral)
+        .into_iter()
+        .filter(|f| f.path != uif_field)
+        .collect(),
+      bus_clock: None,
     }))
   }
 
@@ -103,6 +111,25 @@ impl Timer {
     }
   }
 
+  // UIF is rc_w0. Clearing it with a read-modify-write of SR would also write
+  // 0 to every other flag that was read as set.
+  pub fn update_flag(&self) -> StatusFlag {
+    StatusFlag {
+      name: Name::from("uif"),
+      path: self.uif_field.clone(),
+      clear: FlagClear::WriteZero,
+    }
+  }
+
+  // 32-bit timers (TIM2/TIM5 on most parts) get a 32-bit API so long periods
+  // aren't truncated.
+  pub fn counter_type(&self) -> &'static str {
+    match self.auto_reload_field.max.max(self.counter_field.max) {
+      m if m > u16::MAX as u32 => "u32",
+      _ => "u16",
+    }
+  }
+
   pub fn has_moe_field(&self) -> bool {
     self.moe_field.is_some()
   }
@@ -116,11 +143,118 @@ impl Timer {
       ),
     }
   }
+
+  // Advanced-control timers (TIM1/TIM8), and TIM15-17 on some parts, have a
+  // break input in their BDTR.
+  pub fn has_break_input(&self) -> bool {
+    self.has_moe_field() && self.break_enable_field.is_some() && self.break_polarity_field.is_some()
+  }
+
+  pub fn break_enable_field(&self) -> String {
+    match self.break_enable_field {
+      Some(ref f) => f.clone(),
+      None => panic!("Timer {} has no break enable field.", self.name.camel()),
+    }
+  }
+
+  pub fn break_polarity_field(&self) -> String {
+    match self.break_polarity_field {
+      Some(ref f) => f.clone(),
+      None => panic!("Timer {} has no break polarity field.", self.name.camel()),
+    }
+  }
+
+  pub fn has_automatic_output_enable(&self) -> bool {
+    self.has_moe_field() && self.automatic_output_enable_field.is_some()
+  }
+
+  pub fn automatic_output_enable_field(&self) -> String {
+    match self.automatic_output_enable_field {
+      Some(ref f) => f.clone(),
+      None => panic!(
+        "Timer {} has no automatic output enable field.",
+        self.name.camel()
+      ),
+    }
+  }
+
+  pub fn has_dead_time(&self) -> bool {
+    self.has_moe_field() && self.dead_time_field.is_some()
+  }
+
+  pub fn dead_time_field(&self) -> String {
+    match self.dead_time_field {
+      Some(ref f) => f.clone(),
+      None => panic!("Timer {} has no dead time field.", self.name.camel()),
+    }
+  }
+
+  // Reset mode triggered by TI1FP1 or TI2FP2 lets the counter itself hold the
+  // period, which only works for the first two channels.
+  pub fn can_measure_frequency(&self, channel: &TimerChannel) -> bool {
+    self.slave_mode_field.is_some()
+      && self.trigger_select_field.is_some()
+      && channel.is_input()
+      && channel.as_input().has_io_select()
+      && (channel.number == 1 || channel.number == 2)
+  }
+
+  pub fn has_master_mode(&self) -> bool {
+    self.master_mode_field.is_some()
+  }
+
+  pub fn master_mode_field(&self) -> String {
+    match self.master_mode_field {
+      Some(ref f) => f.clone(),
+      None => panic!("Timer {} has no master mode field.", self.name.camel()),
+    }
+  }
+
+  // Basic timers have a master mode but no SMCR, so they can only drive other
+  // timers, not be triggered by them.
+  pub fn has_slave_mode(&self) -> bool {
+    self.slave_mode_field.is_some() && self.trigger_select_field.is_some()
+  }
+
+  pub fn slave_mode_field(&self) -> String {
+    match self.slave_mode_field {
+      Some(ref f) => f.clone(),
+      None => panic!("Timer {} has no slave mode field.", self.name.camel()),
+    }
+  }
+
+  pub fn trigger_select_field(&self) -> String {
+    match self.trigger_select_field {
+      Some(ref f) => f.clone(),
+      None => panic!("Timer {} has no trigger select field.", self.name.camel()),
+    }
+  }
+}
+
+// Every channel, input or output, has an enable bit in CCER (`CC3E`), so the
+// timer's channels are the ones with one. Probing a range of numbers instead
+// could pick up fields from other features that happen to be named alike.
+fn channel_numbers(peripheral: &PeripheralSpec) -> Vec<u32> {
+  let mut numbers = peripheral
+    .iter_fields()
+    .filter_map(|f| {
+      f.name
+        .to_lowercase()
+        .strip_prefix("cc")?
+        .strip_suffix('e')?
+        .parse::<u32>()
+        .ok()
+    })
+    .collect::<Vec<u32>>();
+  numbers.sort();
+  numbers.dedup();
+  numbers
 }
 
 #[derive(Clone)]
 pub struct TimerChannel {
   pub name: Name,
+  pub number: u32,
   pub output: Option<OutputChannel>,
   pub input: Option<InputChannel>,
 }
@@ -135,6 +269,7 @@ impl TimerChannel {
       (None, None) => Ok(None),
       (output, input) => Ok(Some(Self {
         name,
+        number: channel_number,
         output,
         input,
       })),
@@ -152,13 +287,6 @@ impl TimerChannel {
     }
   }
 
-  pub fn as_output_mut(&mut self) -> &mut OutputChannel {
-    match self.output {
-      Some(ref mut output) => output,
-      None => panic!("{} is not an output channel", self.name.camel()),
-    }
-  }
-
   pub fn is_input(&self) -> bool {
     self.input.is_some()
   }
@@ -169,13 +297,6 @@ impl TimerChannel {
       None => panic!("{} is not an input channel", self.name.camel()),
     }
   }
-
-  pub fn as_input_mut(&mut self) -> &mut InputChannel {
-    match self.input {
-      Some(ref mut input) => input,
-      None => panic!("{} is not an input channel", self.name.camel()),
-    }
-  }
 }
 
 #[derive(Clone)]
@@ -197,19 +318,10 @@ impl OutputChannel {
       },
       io_select: find_enum_field_in_peripheral(peripheral, &f!("cc{channel_number}s")),
       compare_mode: try_find_enum_field_in_peripheral(peripheral, &f!("oc{channel_number}m"))?,
-      compare_field: match find_ranged_field_in_peripheral(peripheral, &f!("ccr{channel_number}")) {
-        Some(f) => f,
-        None => match peripheral
-          .iter_registers()
-          .find(|r| r.name.to_lowercase() == f!("ccr{channel_number}"))
-        {
-          Some(r) => try_find_ranged_field_in_register(r, &f!("ccr"))?,
-          None => bail!(
-            "Could not find Capture/Compare Mode field for {}",
-            peripheral.name
-          ),
-        },
-      },
+      compare_field: try_find_indexed_ranged_field_in_peripheral(
+        peripheral,
+        &f!("ccr{channel_number}"),
+      )?,
       preload_path: try_find_field_in_peripheral(peripheral, &f!("oc{channel_number}pe"))?.path(),
       polarity_path: try_find_field_in_peripheral(peripheral, &f!("cc{channel_number}p"))?.path(),
       complement: OutputComplement::new(peripheral, channel_number)?,
@@ -264,6 +376,12 @@ pub struct InputChannel {
   pub io_select: Option<EnumField>,
   pub capture_field: RangedField,
   pub enable_path: String,
+  pub polarity_path: String,
+  pub complement_polarity_path: Option<String>,
+  pub prescaler_path: String,
+  pub flag_path: String,
+  // CCxOF is rc_w0 like UIF, see `Timer::update_flag`.
+  pub overcapture_flag: StatusFlag,
 }
 impl InputChannel {
   pub fn new(peripheral: &PeripheralSpec, channel_number: u32) -> Result<Option<Self>> {
@@ -273,20 +391,22 @@ impl InputChannel {
         None => return Ok(None),
       },
       io_select: find_enum_field_in_peripheral(peripheral, &f!("cc{channel_number}s")),
-      capture_field: match find_ranged_field_in_peripheral(peripheral, &f!("ccr{channel_number}")) {
-        Some(f) => f,
-        None => match peripheral
-          .iter_registers()
-          .find(|r| r.name.to_lowercase() == f!("ccr{channel_number}"))
-        {
-          Some(r) => try_find_ranged_field_in_register(r, &f!("ccr"))?,
-          None => bail!(
-            "Could not find Capture/Compare Mode field for {}",
-            peripheral.name
-          ),
-        },
-      },
+      capture_field: try_find_indexed_ranged_field_in_peripheral(
+        peripheral,
+        &f!("ccr{channel_number}"),
+      )?,
       enable_path: try_find_field_in_peripheral(peripheral, &f!("cc{channel_number}e"))?.path(),
+      polarity_path: try_find_field_in_peripheral(peripheral, &f!("cc{channel_number}p"))?.path(),
+      complement_polarity_path: find_field_in_peripheral(peripheral, &f!("cc{channel_number}np"))
+        .map(|f| f.path()),
+      prescaler_path: try_find_field_in_peripheral(peripheral, &f!("ic{channel_number}psc"))?
+        .path(),
+      flag_path: try_find_field_in_peripheral(peripheral, &f!("cc{channel_number}if"))?.path(),
+      overcapture_flag: StatusFlag {
+        name: Name::from(f!("cc{channel_number}of")),
+        path: try_find_field_in_peripheral(peripheral, &f!("cc{channel_number}of"))?.path(),
+        clear: FlagClear::WriteZero,
+      },
     }))
   }
 
@@ -300,4 +420,15 @@ impl InputChannel {
       None => panic!("Channel input mode does not have an I/O mode select field"),
     }
   }
+
+  pub fn has_complement_polarity(&self) -> bool {
+    self.complement_polarity_path.is_some()
+  }
+
+  pub fn complement_polarity_path(&self) -> String {
+    match self.complement_polarity_path {
+      Some(ref p) => p.clone(),
+      None => panic!("Channel input does not have a complementary polarity field"),
+    }
+  }
 }
@@ -0,0 +1,142 @@
+// A rough stand-in for rustfmt, used when the output crate isn't formatted
+// with `cargo fmt`. It only re-indents lines by their brace depth, trims
+// trailing whitespace and collapses the blank lines the templates leave behind,
+// which is enough to make the rendered code readable.
+
+const INDENT: &str = "  ";
+
+pub fn tidy_rust(source: &str) -> String {
+  let mut scanner = Scanner::default();
+  let mut tidied = String::new();
+  let mut depth: usize = 0;
+  let mut pending_blank = false;
+  let mut last_opened = true;
+
+  for line in source.lines() {
+    let trimmed = line.trim();
+    if trimmed.is_empty() {
+      pending_blank = true;
+      continue;
+    }
+
+    let counts = scanner.scan(trimmed);
+
+    if pending_blank && !last_opened && counts.leading_closers == 0 {
+      tidied.push('\n');
+    }
+    pending_blank = false;
+
+    for _ in 0..depth.saturating_sub(counts.leading_closers) {
+      tidied.push_str(INDENT);
+    }
+    tidied.push_str(trimmed);
+    tidied.push('\n');
+
+    depth = (depth + counts.openers).saturating_sub(counts.closers);
+    last_opened = trimmed.ends_with(|c: char| c == '{' || c == '(' || c == '[');
+  }
+
+  tidied
+}
+
+struct DelimiterCounts {
+  openers: usize,
+  closers: usize,
+  // Closers before anything else on the line, which pull the line itself out
+  // one level, like the `}` in `} else {`.
+  leading_closers: usize,
+}
+
+// Block comments can span lines, so the scanner keeps that state between them.
+// Strings are assumed not to.
+#[derive(Default)]
+struct Scanner {
+  in_block_comment: bool,
+}
+impl Scanner {
+  fn scan(&mut self, line: &str) -> DelimiterCounts {
+    let mut counts = DelimiterCounts {
+      openers: 0,
+      closers: 0,
+      leading_closers: 0,
+    };
+    let mut only_closers_so_far = true;
+    let mut in_string = false;
+
+    let chars = line.chars().collect::<Vec<char>>();
+    let mut i = 0;
+    while i < chars.len() {
+      let c = chars[i];
+      let next = chars.get(i + 1).copied();
+
+      if self.in_block_comment {
+        if c == '*' && next == Some('/') {
+          self.in_block_comment = false;
+          i += 1;
+        }
+      } else if in_string {
+        match c {
+          '\\' => i += 1,
+          '"' => in_string = false,
+          _ => {}
+        }
+      } else {
+        match (c, next) {
+          ('/', Some('/')) => break,
+          ('/', Some('*')) => {
+            self.in_block_comment = true;
+            i += 1;
+          }
+          ('"', _) => in_string = true,
+          // Char literals, told apart from lifetimes by their closing quote.
+          ('\'', Some('\\')) => {
+            if let Some(p) = chars[i + 2..].iter().position(|c| *c == '\'') {
+              i += p + 2;
+            }
+          }
+          ('\'', Some(_)) if chars.get(i + 2) == Some(&'\'') => i += 2,
+          ('{', _) | ('(', _) | ('[', _) => counts.openers += 1,
+          ('}', _) | (')', _) | (']', _) => {
+            counts.closers += 1;
+            if only_closers_so_far {
+              counts.leading_closers += 1;
+            }
+          }
+          _ => {}
+        }
+      }
+
+      if !matches!(c, '}' | ')' | ']') && !c.is_whitespace() {
+        only_closers_so_far = false;
+      }
+      i += 1;
+    }
+
+    counts
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn indents_by_brace_depth() {
+    let source = "impl Foo {\n      pub fn bar(&self) {\nif x {\n    a();\n   } else {\nb(\"}\");\n}\n\n\n\n}\n  }\n";
+
+    assert_eq!(
+      "impl Foo {\n  pub fn bar(&self) {\n    if x {\n      a();\n    } else {\n      b(\"}\");\n    }\n  }\n}\n",
+      tidy_rust(source)
+    );
+  }
+
+  #[test]
+  fn ignores_delimiters_in_comments_and_char_literals() {
+    let source = "fn f() {\nlet c = '{'; // }\n/* ( */ g();\n}\n";
+
+    assert_eq!(
+      "fn f() {\n  let c = '{'; // }\n  /* ( */ g();\n}\n",
+      tidy_rust(source)
+    );
+  }
+}